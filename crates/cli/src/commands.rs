@@ -1,3 +1,4 @@
+use firmware::version::Channel;
 use std::path::PathBuf;
 
 #[derive(clap::Parser, Debug)]
@@ -10,6 +11,11 @@ pub(crate) struct Args {
 #[derive(clap::Subcommand, Debug)]
 pub(crate) enum Commands {
     Write(Write),
+    /// Sign a firmware image with the release key, writing `<image>.manifest` and
+    /// `<image>.sha256` next to it
+    SignFirmware(SignFirmware),
+    /// Update boards directly rather than through the update server
+    Ota(Ota),
 }
 
 #[derive(clap::Args, Debug)]
@@ -17,3 +23,39 @@ pub(crate) struct Write {
     #[clap(short, long)]
     pub output: PathBuf,
 }
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct SignFirmware {
+    /// File holding the secret half of the release key as hex
+    #[clap(short, long)]
+    pub key: PathBuf,
+    /// Version the image is released as, by default its file name
+    #[clap(long)]
+    pub version: Option<String>,
+    /// Channel the image is released to, by default `beta` for pre-releases and
+    /// `stable` otherwise
+    #[clap(long)]
+    pub channel: Option<Channel>,
+    pub image: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct Ota {
+    #[clap(subcommand)]
+    pub command: OtaCommands,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub(crate) enum OtaCommands {
+    /// Push a signed image to a board over HTTP, authenticating with `OTA_PUSH_SECRET`.
+    /// The board restarts into it once it has been checked.
+    Push(Push),
+}
+
+#[derive(clap::Args, Debug)]
+pub(crate) struct Push {
+    /// Address of the board, with the port if it isn't 80
+    pub host: String,
+    /// Image signed with `sign-firmware`, with its `.manifest` next to it
+    pub image: PathBuf,
+}
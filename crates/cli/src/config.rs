@@ -3,6 +3,11 @@ pub struct Config {
     pub hostname: Option<&'static str>,
     pub wifi_ssid: &'static str,
     pub wifi_pass: &'static str,
+    pub ota_secret: Option<&'static str>,
+    pub ota_push_secret: Option<&'static str>,
+    pub ota_fingerprint: Option<&'static str>,
+    pub ota_public_key: Option<&'static str>,
+    pub ota_channel: Option<&'static str>,
 }
 
 impl Config {
@@ -11,6 +16,11 @@ impl Config {
             hostname: option_env!("HOSTNAME"),
             wifi_ssid: env!("WIFI_SSID"),
             wifi_pass: env!("WIFI_PASS"),
+            ota_secret: option_env!("OTA_SECRET"),
+            ota_push_secret: option_env!("OTA_PUSH_SECRET"),
+            ota_fingerprint: option_env!("OTA_CERT_SHA256"),
+            ota_public_key: option_env!("OTA_PUBLIC_KEY"),
+            ota_channel: option_env!("OTA_CHANNEL"),
         }
     }
 }
@@ -2,6 +2,18 @@
 pub enum Error {
     #[error(transparent)]
     StdIo(#[from] std::io::Error),
+    #[error(transparent)]
+    Firmware(#[from] firmware::error::Error),
+    #[error("{0} must be set")]
+    MissingConfig(&'static str),
+    #[error("invalid response from {host}: {reason}")]
+    InvalidResponse { host: String, reason: String },
+    #[error("{host} refused the image ({status}): {body}")]
+    PushRefused {
+        host: String,
+        status: u16,
+        body: String,
+    },
 }
 
-// pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = std::result::Result<T, Error>;
@@ -1,12 +1,21 @@
-use std::{error::Error, fs::File, str::FromStr};
+use std::{error::Error, fs::File, path::Path, str::FromStr};
 
-use crate::{commands::Commands, config::Config};
+use crate::{
+    commands::{Commands, OtaCommands, SignFirmware},
+    config::Config,
+};
 use clap::Parser;
+use firmware::{
+    signature::{parse_signing_key, Manifest},
+    version::{Channel, Version},
+};
 use nvs_writer::{Key, Partition};
+use tracing::info;
 
 mod commands;
 mod config;
 mod error;
+mod push;
 
 fn main() -> Result<(), Box<dyn Error>> {
     tracing_subscriber::fmt::init();
@@ -38,10 +47,90 @@ fn main() -> Result<(), Box<dyn Error>> {
                 &Key::from_str("hostname").unwrap(),
                 config.hostname.unwrap_or("noname"),
             )?;
+            if let Some(ota_secret) = config.ota_secret {
+                partition.add_string_entry(
+                    &host_namespace,
+                    &Key::from_str("ota_secret").unwrap(),
+                    ota_secret,
+                )?;
+            }
+            if let Some(ota_push_secret) = config.ota_push_secret {
+                partition.add_string_entry(
+                    &host_namespace,
+                    &Key::from_str("ota_push_secret").unwrap(),
+                    ota_push_secret,
+                )?;
+            }
+            if let Some(ota_fingerprint) = config.ota_fingerprint {
+                partition.add_string_entry(
+                    &host_namespace,
+                    &Key::from_str("ota_cert_sha256").unwrap(),
+                    ota_fingerprint,
+                )?;
+            }
+            if let Some(ota_public_key) = config.ota_public_key {
+                partition.add_string_entry(
+                    &host_namespace,
+                    &Key::from_str("ota_public_key").unwrap(),
+                    ota_public_key,
+                )?;
+            }
+            if let Some(ota_channel) = config.ota_channel {
+                // catch typos before they reach a board
+                let channel = Channel::from_str(ota_channel)?;
+                partition.add_string_entry(
+                    &host_namespace,
+                    &Key::from_str("ota_channel").unwrap(),
+                    &channel.to_string(),
+                )?;
+            }
 
             partition.write(&mut file)?;
         }
+        Commands::SignFirmware(sign) => sign_firmware(&sign)?,
+        Commands::Ota(ota) => match ota.command {
+            OtaCommands::Push(args) => push::push(&args)?,
+        },
     }
 
     Ok(())
 }
+
+/// `path` with `extension` appended, keeping any extension it already has
+fn sibling(path: &Path, extension: &str) -> std::path::PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    path.into()
+}
+
+fn sign_firmware(args: &SignFirmware) -> error::Result<()> {
+    let key = parse_signing_key(&std::fs::read(&args.key)?)?;
+    let image = std::fs::read(&args.image)?;
+
+    let name = args
+        .image
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let version = args.version.clone().unwrap_or_else(|| name.to_string());
+    // a board only installs tagged versions
+    let parsed: Version = version.parse()?;
+    let channel = args.channel.unwrap_or_else(|| Channel::of(&parsed));
+    let signed = Manifest::for_image(version, channel, &image).sign(&key);
+
+    std::fs::write(
+        sibling(&args.image, "sha256"),
+        format!("{}  {name}\n", hex::encode(signed.manifest.digest)),
+    )?;
+    std::fs::write(sibling(&args.image, "manifest"), signed.to_string())?;
+
+    info!(
+        "signed {} as {} on {} with public key {}",
+        args.image.display(),
+        signed.manifest.version,
+        signed.manifest.channel,
+        hex::encode(key.verifying_key().as_bytes())
+    );
+    Ok(())
+}
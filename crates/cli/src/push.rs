@@ -0,0 +1,110 @@
+//! Installs an image on a board through its HTTP API, for boards that can't reach the
+//! update server.
+//!
+//! The board hands out a challenge at `/ota/challenge`, which is answered with the
+//! push secret as `firmware::push` describes. The image is then posted to
+//! `/ota/image` with the lines of its release manifest as headers, and the board checks
+//! the signature, its policy on versions and channels, and the digest before it
+//! restarts into it.
+use crate::{
+    commands::Push,
+    error::{Error, Result},
+    sibling,
+};
+use firmware::{
+    push::{authorization, Challenge, CHALLENGE_LEN},
+    signature::SignedManifest,
+};
+use std::{
+    fmt::Write as _,
+    io::{Read, Write},
+    net::TcpStream,
+};
+use tracing::info;
+
+pub(crate) fn push(args: &Push) -> Result<()> {
+    // read when pushing, so that the secret isn't built into the cli
+    let secret =
+        std::env::var("OTA_PUSH_SECRET").map_err(|_| Error::MissingConfig("OTA_PUSH_SECRET"))?;
+    let image = std::fs::read(&args.image)?;
+    let signed = SignedManifest::parse(&std::fs::read(sibling(&args.image, "manifest"))?)?;
+
+    let challenge = request(&args.host, "GET", "/ota/challenge", &[], &[])?;
+    let mut decoded: Challenge = [0; CHALLENGE_LEN];
+    hex::decode_to_slice(challenge.trim(), &mut decoded).map_err(|_| Error::InvalidResponse {
+        host: args.host.clone(),
+        reason: format!("the challenge isn't {CHALLENGE_LEN} bytes of hex"),
+    })?;
+
+    info!(
+        "pushing {} to {}, {} bytes",
+        args.image.display(),
+        args.host,
+        image.len()
+    );
+    let headers = [
+        ("authorization", authorization(secret.as_bytes(), &decoded)),
+        ("content-type", "application/octet-stream".to_string()),
+        ("x-firmware-version", signed.manifest.version.clone()),
+        ("x-firmware-channel", signed.manifest.channel.to_string()),
+        ("x-firmware-sha256", hex::encode(signed.manifest.digest)),
+        (
+            "x-firmware-signature",
+            hex::encode(signed.signature.to_bytes()),
+        ),
+    ];
+    let body = request(&args.host, "POST", "/ota/image", &headers, &image)?;
+    info!("{}: {}", args.host, body.trim());
+    Ok(())
+}
+
+/// Make an HTTP/1.1 request to `host`, returning the body of a successful response
+fn request(
+    host: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> Result<String> {
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{host}:80")
+    };
+    let mut stream = TcpStream::connect(addr)?;
+
+    let mut head = format!(
+        "{method} {path} HTTP/1.1\r\nhost: {host}\r\nconnection: close\r\ncontent-length: {}\r\n",
+        body.len()
+    );
+    for (name, value) in headers {
+        let _ = write!(head, "{name}: {value}\r\n");
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let response = String::from_utf8_lossy(&response);
+    let invalid = |reason: &str| Error::InvalidResponse {
+        host: host.to_string(),
+        reason: reason.to_string(),
+    };
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| invalid("the headers don't end"))?;
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| invalid("no status code"))?;
+    if !(200..300).contains(&status) {
+        return Err(Error::PushRefused {
+            host: host.to_string(),
+            status,
+            body: body.trim().to_string(),
+        });
+    }
+    Ok(body.to_string())
+}
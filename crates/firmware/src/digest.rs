@@ -0,0 +1,209 @@
+//! SHA-256 verification of an image while it is being written.
+//!
+//! Every build in `files/` has a `<rev>.sha256` next to it holding its digest, as
+//! written by `sha256sum`. [`VerifyingWriter`] hashes the image on its way into the
+//! update partition, and the update is only completed if [`VerifyingWriter::finish`]
+//! finds that the whole image arrived intact.
+use crate::error::{Error, Result};
+use embedded_io::{ErrorType, Write};
+use sha2::{Digest as _, Sha256};
+
+pub type Digest = [u8; 32];
+
+/// Parse the contents of a `.sha256` file: the digest in hex, optionally followed by
+/// the name of the file as `sha256sum` writes it
+/// # Errors
+/// [`Error::InvalidDigest`] if the file doesn't start with 64 hex digits
+pub fn parse_digest(contents: &[u8]) -> Result<Digest> {
+    let contents = std::str::from_utf8(contents).map_err(|_| Error::InvalidDigest)?;
+    let hex = contents
+        .split_whitespace()
+        .next()
+        .ok_or(Error::InvalidDigest)?;
+    let mut digest = [0; 32];
+    hex::decode_to_slice(hex, &mut digest).map_err(|_| Error::InvalidDigest)?;
+    Ok(digest)
+}
+
+/// Passes everything written to it on to `inner`, hashing it on the way
+pub struct VerifyingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    written: u64,
+    expected: Digest,
+    expected_len: Option<u64>,
+}
+
+impl<W> VerifyingWriter<W> {
+    pub fn new(inner: W, expected: Digest) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            written: 0,
+            expected,
+            expected_len: None,
+        }
+    }
+
+    /// Also require the image to be exactly `len` bytes, which tells a truncated
+    /// download apart from a corrupt one
+    #[must_use]
+    pub fn with_len(mut self, len: u64) -> Self {
+        self.expected_len = Some(len);
+        self
+    }
+
+    /// Bytes written so far
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Check that the whole image was written and matches the digest. The inner
+    /// writer is handed back only if it does.
+    /// # Errors
+    /// - [`Error::LengthMismatch`] if more or fewer bytes than expected were written
+    /// - [`Error::DigestMismatch`] if the image doesn't hash to the expected digest
+    pub fn finish(self) -> Result<W> {
+        if let Some(expected) = self.expected_len {
+            if expected != self.written {
+                return Err(Error::LengthMismatch {
+                    expected,
+                    actual: self.written,
+                });
+            }
+        }
+
+        let actual: Digest = self.hasher.finalize().into();
+        if actual != self.expected {
+            return Err(Error::DigestMismatch {
+                expected: hex::encode(self.expected),
+                actual: hex::encode(actual),
+            });
+        }
+        Ok(self.inner)
+    }
+}
+
+impl<W: ErrorType> ErrorType for VerifyingWriter<W> {
+    type Error = W::Error;
+}
+
+impl<W: Write> Write for VerifyingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::result::Result<usize, Self::Error> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::result::Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_image;
+    use std::convert::Infallible;
+
+    /// Stands in for the update partition, taking at most `chunk` bytes per write
+    struct FakeFlash {
+        data: Vec<u8>,
+        chunk: usize,
+    }
+
+    impl ErrorType for FakeFlash {
+        type Error = Infallible;
+    }
+
+    impl Write for FakeFlash {
+        fn write(&mut self, buf: &[u8]) -> std::result::Result<usize, Infallible> {
+            let len = buf.len().min(self.chunk);
+            self.data.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::result::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    fn digest(data: &[u8]) -> Digest {
+        Sha256::digest(data).into()
+    }
+
+    fn write(image: &[u8], expected: Digest, len: u64) -> Result<FakeFlash> {
+        let flash = FakeFlash {
+            data: Vec::new(),
+            chunk: 1000,
+        };
+        let mut writer = VerifyingWriter::new(flash, expected).with_len(len);
+        for block in image.chunks(4096) {
+            writer.write_all(block).unwrap();
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_parse_digest() {
+        let image = test_image();
+        let line = format!("{}  a1b2c3d\n", hex::encode(digest(&image)));
+        assert_eq!(parse_digest(line.as_bytes()), Ok(digest(&image)));
+        assert_eq!(
+            parse_digest(hex::encode(digest(&image)).as_bytes()),
+            Ok(digest(&image))
+        );
+
+        assert_eq!(parse_digest(b""), Err(Error::InvalidDigest));
+        assert_eq!(parse_digest(b"a1b2c3d  a1b2c3d"), Err(Error::InvalidDigest));
+        assert_eq!(parse_digest(&[0xff; 64]), Err(Error::InvalidDigest));
+    }
+
+    #[test]
+    fn test_intact_image() {
+        let image = test_image();
+        let flash = write(&image, digest(&image), image.len() as u64).unwrap();
+        assert_eq!(flash.data, image);
+    }
+
+    #[test]
+    fn test_corrupt_image() {
+        let image = test_image();
+        let mut corrupt = image.clone();
+        corrupt[5000] ^= 1;
+
+        let err = write(&corrupt, digest(&image), image.len() as u64)
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::DigestMismatch { .. }));
+    }
+
+    #[test]
+    fn test_truncated_image() {
+        let image = test_image();
+        let err = write(&image[..30_000], digest(&image), image.len() as u64)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            Error::LengthMismatch {
+                expected: 40_000,
+                actual: 30_000
+            }
+        );
+
+        // without the length, truncation still shows in the digest
+        let flash = FakeFlash {
+            data: Vec::new(),
+            chunk: 1000,
+        };
+        let mut writer = VerifyingWriter::new(flash, digest(&image));
+        writer.write_all(&image[..30_000]).unwrap();
+        assert_eq!(writer.written(), 30_000);
+        assert!(matches!(
+            writer.finish().err(),
+            Some(Error::DigestMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,31 @@
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("invalid sha-256 digest file")]
+    InvalidDigest,
+    #[error("invalid ed25519 key")]
+    InvalidKey,
+    #[error("invalid ed25519 signature file")]
+    InvalidSignature,
+    #[error("invalid release manifest: {0}")]
+    InvalidManifest(String),
+    #[error("image isn't signed by the release key")]
+    BadSignature,
+    #[error("authorization must be OtaPush <challenge> <response> in hex")]
+    InvalidAuthorization,
+    #[error("the challenge wasn't handed out or has expired")]
+    UnknownChallenge,
+    #[error("the response doesn't answer the challenge")]
+    BadPushResponse,
+    #[error("{0:?} is not a tagged firmware version")]
+    InvalidVersion(String),
+    #[error("unknown release channel {0:?}")]
+    InvalidChannel(String),
+    #[error("invalid rollout manifest: {0}")]
+    InvalidRollout(String),
+    #[error("image has {actual} bytes but {expected} were expected")]
+    LengthMismatch { expected: u64, actual: u64 },
+    #[error("image has sha-256 {actual} but {expected} was expected")]
+    DigestMismatch { expected: String, actual: String },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
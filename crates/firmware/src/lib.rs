@@ -0,0 +1,20 @@
+//! Decides which firmware images a board installs, tracks their download and checks
+//! them before the board boots into them. This lives outside `relay-controller` so
+//! that it can be tested on the host.
+pub mod digest;
+pub mod error;
+pub mod policy;
+pub mod progress;
+pub mod push;
+pub mod rollout;
+pub mod signature;
+pub mod version;
+
+pub use digest::{parse_digest, Digest, VerifyingWriter};
+pub use progress::Progress;
+
+/// Image the tests write, sign and verify
+#[cfg(test)]
+fn test_image() -> Vec<u8> {
+    (0..10_000u32).flat_map(u32::to_le_bytes).collect()
+}
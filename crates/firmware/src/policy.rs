@@ -0,0 +1,110 @@
+//! Which releases a board installs.
+//!
+//! Images from the update server and images pushed over HTTP go through the same
+//! [`Policy::check`], on the version and channel of their signed manifest, so that
+//! neither way onto a board gets around the other's rules.
+use crate::{
+    signature::Manifest,
+    version::{classify, Channel, Update, Version},
+};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Policy {
+    pub channel: Channel,
+    /// Whether versions older than the running one are installed too
+    pub allow_downgrade: bool,
+}
+
+/// Why a board doesn't install a release
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    Running,
+    Downgrade,
+    /// The release is a pre-release or signed for the beta channel, and the board
+    /// follows stable
+    NotOnChannel,
+    InvalidVersion,
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Refusal::Running => "already running it",
+            Refusal::Downgrade => "older than the running version and downgrades are off",
+            Refusal::NotOnChannel => "not released to the board's channel",
+            Refusal::InvalidVersion => "not a tagged version",
+        })
+    }
+}
+
+impl Policy {
+    /// Whether a board running `running` installs the release `manifest` describes,
+    /// which should have been verified already. Returns how the release relates to
+    /// the running version if it is installed.
+    /// # Errors
+    /// The [`Refusal`] if it isn't
+    pub fn check(&self, running: &str, manifest: &Manifest) -> Result<Update, Refusal> {
+        let version: Version = manifest
+            .version
+            .parse()
+            .map_err(|_| Refusal::InvalidVersion)?;
+        if !self.channel.accepts(&version) || !self.channel.includes(manifest.channel) {
+            return Err(Refusal::NotOnChannel);
+        }
+        match classify(running, &manifest.version).map_err(|_| Refusal::InvalidVersion)? {
+            Update::None => Err(Refusal::Running),
+            Update::Downgrade if !self.allow_downgrade => Err(Refusal::Downgrade),
+            update => Ok(update),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(version: &str, channel: Channel) -> Manifest {
+        Manifest::for_image(version.to_string(), channel, &[])
+    }
+
+    #[test]
+    fn test_check() {
+        let stable = Policy::default();
+        let release = manifest("v1.5.0", Channel::Stable);
+        assert_eq!(stable.check("v1.4.0", &release), Ok(Update::Upgrade));
+        assert_eq!(stable.check("v1.5.0", &release), Err(Refusal::Running));
+        assert_eq!(stable.check("v1.6.0", &release), Err(Refusal::Downgrade));
+        let downgrades = Policy {
+            allow_downgrade: true,
+            ..stable
+        };
+        assert_eq!(downgrades.check("v1.6.0", &release), Ok(Update::Downgrade));
+        assert_eq!(stable.check("a1b2c3d", &release), Ok(Update::Upgrade));
+    }
+
+    #[test]
+    fn test_channels() {
+        let stable = Policy::default();
+        let beta = Policy {
+            channel: Channel::Beta,
+            ..stable
+        };
+        for release in [
+            manifest("v1.5.0-beta.1", Channel::Beta),
+            manifest("v1.5.0-beta.1", Channel::Stable),
+            manifest("v1.5.0", Channel::Beta),
+        ] {
+            assert_eq!(
+                stable.check("v1.4.0", &release),
+                Err(Refusal::NotOnChannel),
+                "{release:?}"
+            );
+            assert_eq!(beta.check("v1.4.0", &release), Ok(Update::Upgrade));
+        }
+        assert_eq!(
+            beta.check("v1.4.0", &manifest("v1.5.0", Channel::Stable)),
+            Ok(Update::Upgrade)
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! How far the download of an image has come.
+//!
+//! A download that loses its connection picks up again at the offset it had written
+//! up to, so [`Progress`] also counts how often that happened.
+use std::{fmt, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Progress {
+    /// Bytes written to the update partition
+    pub written: u64,
+    /// Size of the image
+    pub total: u64,
+    /// Time since the download started, including time spent reconnecting
+    pub elapsed: Duration,
+    /// Times the download was resumed on a new connection
+    pub resumes: u32,
+}
+
+impl Progress {
+    #[must_use]
+    pub fn new(total: u64) -> Self {
+        Self {
+            total,
+            ..Self::default()
+        }
+    }
+
+    /// Share of the image written, from 0 to 100
+    #[must_use]
+    pub fn percent(&self) -> u8 {
+        if self.total == 0 {
+            return 100;
+        }
+        let percent = self.written.min(self.total) * 100 / self.total;
+        u8::try_from(percent).unwrap_or(100)
+    }
+
+    /// Average throughput since the download started, in bytes per second
+    #[must_use]
+    pub fn bytes_per_sec(&self) -> u64 {
+        let millis = self.elapsed.as_millis();
+        if millis == 0 {
+            return 0;
+        }
+        u64::try_from(u128::from(self.written) * 1000 / millis).unwrap_or(u64::MAX)
+    }
+
+    /// Whether the download went past a multiple of `step` percent since `previous`
+    /// bytes were written, to log at intervals
+    #[must_use]
+    pub fn crossed(&self, previous: u64, step: u8) -> bool {
+        let before = Self {
+            written: previous,
+            ..*self
+        };
+        let step = step.max(1);
+        self.percent() / step != before.percent() / step
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} bytes ({}%) at {} B/s",
+            self.written,
+            self.total,
+            self.percent(),
+            self.bytes_per_sec()
+        )?;
+        if self.resumes > 0 {
+            write!(f, ", resumed {} times", self.resumes)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent() {
+        let mut progress = Progress::new(1000);
+        assert_eq!(progress.percent(), 0);
+        progress.written = 999;
+        assert_eq!(progress.percent(), 99);
+        progress.written = 1000;
+        assert_eq!(progress.percent(), 100);
+        progress.written = 1500;
+        assert_eq!(progress.percent(), 100);
+        assert_eq!(Progress::new(0).percent(), 100);
+    }
+
+    #[test]
+    fn test_throughput() {
+        let mut progress = Progress::new(1 << 20);
+        assert_eq!(progress.bytes_per_sec(), 0);
+        progress.written = 64 * 1024;
+        progress.elapsed = Duration::from_secs(2);
+        assert_eq!(progress.bytes_per_sec(), 32 * 1024);
+        progress.resumes = 2;
+        assert_eq!(
+            progress.to_string(),
+            "65536/1048576 bytes (6%) at 32768 B/s, resumed 2 times"
+        );
+    }
+
+    #[test]
+    fn test_crossed() {
+        let mut progress = Progress::new(1000);
+        progress.written = 95;
+        assert!(!progress.crossed(0, 10));
+        progress.written = 100;
+        assert!(progress.crossed(95, 10));
+        assert!(!progress.crossed(100, 10));
+        progress.written = 350;
+        assert!(progress.crossed(100, 10));
+    }
+}
@@ -0,0 +1,186 @@
+//! Authentication of images pushed to a board over HTTP.
+//!
+//! A board hands out random challenges at `/ota/challenge`. A push answers one in its
+//! `Authorization` header as `OtaPush <challenge> <response>`, both in hex, where the
+//! response is `HMAC-SHA256(push secret, DOMAIN || challenge)`. The push secret isn't
+//! the one the board authenticates to the update server with, and the domain differs
+//! from the one of the 9P scheme, so an answer from either can't be used for the
+//! other.
+//!
+//! Every push answers its own challenge, which stays pending until it is answered or
+//! expires, so asking for a challenge doesn't take one away from anybody else.
+use crate::error::{Error, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Scheme of the `Authorization` header of a push
+pub const AUTH_SCHEME: &str = "OtaPush";
+
+pub const CHALLENGE_LEN: usize = 32;
+
+pub type Challenge = [u8; CHALLENGE_LEN];
+
+/// Separates these MACs from any other use of the same secret
+const DOMAIN: &[u8] = b"stowage ota push v1\0";
+
+/// How long a challenge can be answered
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(60);
+
+/// Challenges kept pending at most; handing out another drops the oldest
+pub const MAX_PENDING: usize = 8;
+
+fn mac(secret: &[u8], challenge: &Challenge) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(DOMAIN);
+    mac.update(challenge);
+    mac
+}
+
+/// The `Authorization` header that answers `challenge`
+#[must_use]
+pub fn authorization(secret: &[u8], challenge: &Challenge) -> String {
+    let response = mac(secret, challenge).finalize().into_bytes();
+    format!(
+        "{AUTH_SCHEME} {} {}",
+        hex::encode(challenge),
+        hex::encode(response)
+    )
+}
+
+/// Parse an `Authorization` header into the challenge it answers and the response
+/// # Errors
+/// [`Error::InvalidAuthorization`] if it isn't `OtaPush <challenge> <response>`
+pub fn parse_authorization(value: &str) -> Result<(Challenge, Vec<u8>)> {
+    let mut parts = value.split_whitespace();
+    let (Some(AUTH_SCHEME), Some(challenge), Some(response), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Error::InvalidAuthorization);
+    };
+    let mut decoded = [0; CHALLENGE_LEN];
+    hex::decode_to_slice(challenge, &mut decoded).map_err(|_| Error::InvalidAuthorization)?;
+    let response = hex::decode(response).map_err(|_| Error::InvalidAuthorization)?;
+    Ok((decoded, response))
+}
+
+/// Challenges handed out and not answered yet
+#[derive(Debug, Default)]
+pub struct PendingChallenges {
+    challenges: VecDeque<(Challenge, Instant)>,
+}
+
+impl PendingChallenges {
+    /// Keep `challenge`, handed out at `now`, until it is answered or expires
+    pub fn insert(&mut self, challenge: Challenge, now: Instant) {
+        self.expire(now);
+        if self.challenges.len() >= MAX_PENDING {
+            self.challenges.pop_front();
+        }
+        self.challenges.push_back((challenge, now));
+    }
+
+    /// Check `response` to `challenge` at `now`. The challenge can only be answered
+    /// once, but a wrong response leaves it pending.
+    /// # Errors
+    /// - [`Error::UnknownChallenge`] if it wasn't handed out or has expired
+    /// - [`Error::BadPushResponse`] if `response` doesn't answer it
+    pub fn answer(
+        &mut self,
+        secret: &[u8],
+        challenge: &Challenge,
+        response: &[u8],
+        now: Instant,
+    ) -> Result<()> {
+        self.expire(now);
+        let index = self
+            .challenges
+            .iter()
+            .position(|(pending, _)| pending == challenge)
+            .ok_or(Error::UnknownChallenge)?;
+        mac(secret, challenge)
+            .verify_slice(response)
+            .map_err(|_| Error::BadPushResponse)?;
+        self.challenges.remove(index);
+        Ok(())
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.challenges
+            .retain(|(_, issued)| now.saturating_duration_since(*issued) < CHALLENGE_TTL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"push secret";
+
+    fn answer(header: &str) -> (Challenge, Vec<u8>) {
+        parse_authorization(header).unwrap()
+    }
+
+    #[test]
+    fn test_answer() {
+        let now = Instant::now();
+        let mut pending = PendingChallenges::default();
+        pending.insert([1; CHALLENGE_LEN], now);
+        pending.insert([2; CHALLENGE_LEN], now);
+
+        // a wrong response leaves the challenge to its owner
+        let (challenge, response) = answer(&authorization(b"other", &[1; CHALLENGE_LEN]));
+        assert_eq!(
+            pending.answer(SECRET, &challenge, &response, now),
+            Err(Error::BadPushResponse)
+        );
+        let (challenge, response) = answer(&authorization(SECRET, &[1; CHALLENGE_LEN]));
+        assert_eq!(pending.answer(SECRET, &challenge, &response, now), Ok(()));
+        assert_eq!(
+            pending.answer(SECRET, &challenge, &response, now),
+            Err(Error::UnknownChallenge)
+        );
+
+        let (challenge, response) = answer(&authorization(SECRET, &[2; CHALLENGE_LEN]));
+        assert_eq!(
+            pending.answer(SECRET, &challenge, &response, now + CHALLENGE_TTL),
+            Err(Error::UnknownChallenge)
+        );
+    }
+
+    #[test]
+    fn test_oldest_dropped() {
+        let now = Instant::now();
+        let mut pending = PendingChallenges::default();
+        for i in 0..=MAX_PENDING {
+            pending.insert([u8::try_from(i).unwrap(); CHALLENGE_LEN], now);
+        }
+        let (challenge, response) = answer(&authorization(SECRET, &[0; CHALLENGE_LEN]));
+        assert_eq!(
+            pending.answer(SECRET, &challenge, &response, now),
+            Err(Error::UnknownChallenge)
+        );
+        let (challenge, response) = answer(&authorization(SECRET, &[1; CHALLENGE_LEN]));
+        assert_eq!(pending.answer(SECRET, &challenge, &response, now), Ok(()));
+    }
+
+    #[test]
+    fn test_parse_authorization() {
+        for invalid in [
+            "",
+            "SharedSecret cli 00",
+            "OtaPush 0101 00",
+            "OtaPush zz 00",
+            &format!("OtaPush {} 00 00", "01".repeat(CHALLENGE_LEN)),
+        ] {
+            assert_eq!(
+                parse_authorization(invalid),
+                Err(Error::InvalidAuthorization),
+                "{invalid}"
+            );
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Staged rollouts of a version across the fleet.
+//!
+//! Next to `version`, the update server can publish a rollout manifest that boards
+//! read instead. It names the version, the boards allowed to install it and when
+//! they may start:
+//!
+//! ```json
+//! {
+//!   "version": "v1.5.0",
+//!   "percent": 25,
+//!   "hosts": ["pump-house"],
+//!   "not_before": 1767225600
+//! }
+//! ```
+//!
+//! Listed hosts are always in the wave. Every other board has a fixed position from 0
+//! to 99 for each version, derived from its hostname, and is in the wave once
+//! `percent` is above it, so raising `percent` only ever adds boards. A board without
+//! a hostname only takes part at 100 percent.
+use crate::{
+    error::{Error, Result},
+    version::Version,
+};
+use serde::Deserialize;
+use sha2::{Digest as _, Sha256};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rollout {
+    pub version: String,
+    /// Share of the boards, besides the listed hosts, that install the version
+    #[serde(default)]
+    pub percent: u8,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Unix time before which no board installs the version
+    #[serde(default)]
+    pub not_before: Option<u64>,
+}
+
+/// Whether a board installs the version of a rollout now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Admitted,
+    /// The rollout hasn't started yet, or the board doesn't know the time
+    TooEarly,
+    /// The board isn't in the current wave
+    NotInWave,
+}
+
+impl fmt::Display for Admission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Admission::Admitted => "admitted",
+            Admission::TooEarly => "not started",
+            Admission::NotInWave => "not in the current wave",
+        })
+    }
+}
+
+impl Rollout {
+    /// Parse and check a manifest
+    /// # Errors
+    /// [`Error::InvalidRollout`] if it isn't a manifest, names a version that isn't
+    /// tagged, or has a percentage over 100
+    pub fn parse(contents: &[u8]) -> Result<Self> {
+        let rollout: Rollout =
+            serde_json::from_slice(contents).map_err(|e| Error::InvalidRollout(e.to_string()))?;
+        rollout
+            .version
+            .parse::<Version>()
+            .map_err(|e| Error::InvalidRollout(e.to_string()))?;
+        if rollout.percent > 100 {
+            return Err(Error::InvalidRollout(format!(
+                "percent is {}, at most 100",
+                rollout.percent
+            )));
+        }
+        Ok(rollout)
+    }
+
+    /// Position of `hostname` in this rollout, from 0 to 99
+    #[must_use]
+    pub fn position(&self, hostname: &str) -> u8 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.version.as_bytes());
+        hasher.update([0]);
+        hasher.update(hostname.as_bytes());
+        let hash = hasher.finalize();
+        let position = u16::from_be_bytes([hash[0], hash[1]]) % 100;
+        u8::try_from(position).unwrap_or_default()
+    }
+
+    /// Whether the board called `hostname` installs the version at unix time `now`,
+    /// which is `None` while the clock hasn't been set
+    #[must_use]
+    pub fn admit(&self, hostname: Option<&str>, now: Option<u64>) -> Admission {
+        if let Some(not_before) = self.not_before {
+            if !matches!(now, Some(now) if now >= not_before) {
+                return Admission::TooEarly;
+            }
+        }
+        let in_wave = match hostname {
+            Some(hostname) => {
+                self.hosts.iter().any(|host| host == hostname)
+                    || self.position(hostname) < self.percent
+            }
+            None => self.percent >= 100,
+        };
+        if in_wave {
+            Admission::Admitted
+        } else {
+            Admission::NotInWave
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rollout(percent: u8, hosts: &[&str]) -> Rollout {
+        Rollout {
+            version: "v1.5.0".to_string(),
+            percent,
+            hosts: hosts.iter().map(ToString::to_string).collect(),
+            not_before: None,
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = Rollout::parse(
+            br#"{ "version": "v1.5.0", "percent": 25, "hosts": ["pump-house"], "not_before": 1767225600 }"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.percent, 25);
+        assert_eq!(parsed.hosts, ["pump-house"]);
+        assert_eq!(parsed.not_before, Some(1_767_225_600));
+
+        for invalid in [
+            r#"{ "version": "v1.5.0", "percent": 101 }"#,
+            r#"{ "version": "a1b2c3d", "percent": 10 }"#,
+            r#"{ "version": "v1.5.0", "wave": 2 }"#,
+            r#"{ "percent": 10 }"#,
+        ] {
+            assert!(Rollout::parse(invalid.as_bytes()).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_waves_grow() {
+        let hosts: Vec<String> = (0..200).map(|i| format!("board-{i}")).collect();
+        let mut previous: Vec<&String> = Vec::new();
+        for percent in [0, 10, 50, 100] {
+            let wave: Vec<&String> = hosts
+                .iter()
+                .filter(|host| rollout(percent, &[]).admit(Some(host), None) == Admission::Admitted)
+                .collect();
+            assert!(previous.iter().all(|host| wave.contains(host)));
+            previous = wave;
+        }
+        assert_eq!(previous.len(), hosts.len());
+
+        // about half of the boards at 50 percent
+        let half = hosts
+            .iter()
+            .filter(|host| rollout(50, &[]).position(host) < 50)
+            .count();
+        assert!((60..=140).contains(&half), "{half}");
+    }
+
+    #[test]
+    fn test_admit() {
+        let listed = rollout(0, &["pump-house"]);
+        assert_eq!(listed.admit(Some("pump-house"), None), Admission::Admitted);
+        assert_eq!(listed.admit(Some("garage"), None), Admission::NotInWave);
+        assert_eq!(listed.admit(None, None), Admission::NotInWave);
+        assert_eq!(rollout(100, &[]).admit(None, None), Admission::Admitted);
+
+        let scheduled = Rollout {
+            not_before: Some(1_767_225_600),
+            ..rollout(100, &[])
+        };
+        assert_eq!(scheduled.admit(Some("garage"), None), Admission::TooEarly);
+        assert_eq!(
+            scheduled.admit(Some("garage"), Some(1_767_225_599)),
+            Admission::TooEarly
+        );
+        assert_eq!(
+            scheduled.admit(Some("garage"), Some(1_767_225_600)),
+            Admission::Admitted
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Ed25519 signatures over firmware releases.
+//!
+//! A release is signed by signing its manifest: the version, the channel it is
+//! released to and the SHA-256 digest of its image. The manifest and the signature go
+//! next to the image in `<version>.manifest`:
+//!
+//! ```text
+//! version v1.5.0
+//! channel stable
+//! sha256 4f3c...
+//! signature 9a1b...
+//! ```
+//!
+//! A board checks the signature against its release key before downloading the image
+//! and then checks the image against the digest while writing it, so an image that
+//! passes both was built by whoever holds the release key, and its version and channel
+//! are the ones they released it as.
+use crate::{
+    digest::Digest,
+    error::{Error, Result},
+    version::{Channel, Version},
+};
+use ed25519_dalek::Signer;
+use sha2::{Digest as _, Sha256};
+use std::fmt;
+
+pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+
+/// Decode exactly `N` bytes from the first word of `contents`
+fn decode_hex<const N: usize>(contents: &[u8]) -> Option<[u8; N]> {
+    let hex = std::str::from_utf8(contents)
+        .ok()?
+        .split_whitespace()
+        .next()?;
+    let mut bytes = [0; N];
+    hex::decode_to_slice(hex, &mut bytes).ok()?;
+    Some(bytes)
+}
+
+/// Parse a release key's secret half, 32 bytes in hex
+/// # Errors
+/// [`Error::InvalidKey`] if it isn't 64 hex digits
+pub fn parse_signing_key(contents: &[u8]) -> Result<SigningKey> {
+    decode_hex(contents)
+        .map(|bytes| SigningKey::from_bytes(&bytes))
+        .ok_or(Error::InvalidKey)
+}
+
+/// Parse a release key's public half, 32 bytes in hex
+/// # Errors
+/// [`Error::InvalidKey`] if it isn't 64 hex digits or not a point on the curve
+pub fn parse_verifying_key(contents: &[u8]) -> Result<VerifyingKey> {
+    decode_hex(contents)
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+        .ok_or(Error::InvalidKey)
+}
+
+/// Parse a signature, 64 bytes in hex
+/// # Errors
+/// [`Error::InvalidSignature`] if it isn't 128 hex digits
+pub fn parse_signature(contents: &[u8]) -> Result<Signature> {
+    decode_hex(contents)
+        .map(|bytes| Signature::from_bytes(&bytes))
+        .ok_or(Error::InvalidSignature)
+}
+
+/// Prefix of the signed bytes, so that a manifest signature can't be taken for a
+/// signature over anything else made with the same key
+const DOMAIN: &[u8] = b"stowage firmware manifest v1\n";
+
+/// What the signature of a release vouches for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub version: String,
+    pub channel: Channel,
+    pub digest: Digest,
+}
+
+impl Manifest {
+    /// The manifest of `image`, released as `version` to `channel`
+    #[must_use]
+    pub fn for_image(version: String, channel: Channel, image: &[u8]) -> Self {
+        Self {
+            version,
+            channel,
+            digest: Sha256::digest(image).into(),
+        }
+    }
+
+    /// The lines of the manifest file that the signature covers
+    fn body(&self) -> String {
+        format!(
+            "version {}\nchannel {}\nsha256 {}\n",
+            self.version,
+            self.channel,
+            hex::encode(self.digest)
+        )
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        [DOMAIN, self.body().as_bytes()].concat()
+    }
+
+    #[must_use]
+    pub fn sign(self, key: &SigningKey) -> SignedManifest {
+        let signature = key.sign(&self.signed_bytes());
+        SignedManifest {
+            manifest: self,
+            signature,
+        }
+    }
+}
+
+/// A manifest with its signature, as published in `<version>.manifest`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedManifest {
+    pub manifest: Manifest,
+    pub signature: Signature,
+}
+
+impl SignedManifest {
+    /// Parse the contents of a `.manifest` file
+    /// # Errors
+    /// [`Error::InvalidManifest`] if a line is missing, repeated or unknown, or its
+    /// value doesn't parse
+    pub fn parse(contents: &[u8]) -> Result<Self> {
+        let invalid = |reason: &str| Error::InvalidManifest(reason.to_string());
+        let contents = std::str::from_utf8(contents).map_err(|_| invalid("not utf-8"))?;
+
+        let (mut version, mut channel, mut digest, mut signature) = (None, None, None, None);
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid(&format!("{line:?} has no value")))?;
+            let value = value.trim();
+            let slot = match key {
+                "version" => &mut version,
+                "channel" => &mut channel,
+                "sha256" => &mut digest,
+                "signature" => &mut signature,
+                _ => return Err(invalid(&format!("unknown line {key:?}"))),
+            };
+            if slot.replace(value).is_some() {
+                return Err(invalid(&format!("{key} appears twice")));
+            }
+        }
+
+        let version = version.ok_or_else(|| invalid("no version"))?;
+        version
+            .parse::<Version>()
+            .map_err(|e| invalid(&e.to_string()))?;
+        let channel = channel
+            .ok_or_else(|| invalid("no channel"))?
+            .parse()
+            .map_err(|e: Error| invalid(&e.to_string()))?;
+        let digest = decode_hex(digest.ok_or_else(|| invalid("no sha256"))?.as_bytes())
+            .ok_or_else(|| invalid("sha256 isn't 64 hex digits"))?;
+        let signature =
+            parse_signature(signature.ok_or_else(|| invalid("no signature"))?.as_bytes())
+                .map_err(|_| invalid("signature isn't 128 hex digits"))?;
+        Ok(Self {
+            manifest: Manifest {
+                version: version.to_string(),
+                channel,
+                digest,
+            },
+            signature,
+        })
+    }
+
+    /// The manifest, if it was signed with the secret half of `key`
+    /// # Errors
+    /// [`Error::BadSignature`] if it wasn't
+    pub fn verify(&self, key: &VerifyingKey) -> Result<&Manifest> {
+        key.verify_strict(&self.manifest.signed_bytes(), &self.signature)
+            .map_err(|_| Error::BadSignature)?;
+        Ok(&self.manifest)
+    }
+}
+
+impl fmt::Display for SignedManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}signature {}",
+            self.manifest.body(),
+            hex::encode(self.signature.to_bytes())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_image;
+
+    const KEY: &[u8] = b"9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const OTHER_KEY: &[u8] = b"4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb";
+
+    fn manifest() -> Manifest {
+        Manifest::for_image("v1.5.0".to_string(), Channel::Stable, &test_image())
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        let key = parse_signing_key(KEY).unwrap();
+        let signed = manifest().sign(&key).to_string();
+
+        let public = hex::encode(key.verifying_key().as_bytes());
+        let public = parse_verifying_key(public.as_bytes()).unwrap();
+        let signed = SignedManifest::parse(signed.as_bytes()).unwrap();
+        assert_eq!(signed.verify(&public), Ok(&manifest()));
+    }
+
+    #[test]
+    fn test_tampered_manifest() {
+        let key = parse_signing_key(KEY).unwrap();
+        let signed = manifest().sign(&key);
+
+        let mut tampered = test_image();
+        tampered[5000] ^= 1;
+        for manifest in [
+            Manifest {
+                digest: Sha256::digest(&tampered).into(),
+                ..manifest()
+            },
+            Manifest {
+                version: "v1.4.0".to_string(),
+                ..manifest()
+            },
+            Manifest {
+                channel: Channel::Beta,
+                ..manifest()
+            },
+        ] {
+            let tampered = SignedManifest {
+                manifest,
+                ..signed.clone()
+            };
+            assert_eq!(
+                tampered.verify(&key.verifying_key()),
+                Err(Error::BadSignature)
+            );
+        }
+
+        let mut tampered = signed;
+        tampered.signature = Signature::from_bytes(&{
+            let mut bytes = tampered.signature.to_bytes();
+            bytes[0] ^= 1;
+            bytes
+        });
+        assert_eq!(
+            tampered.verify(&key.verifying_key()),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let key = parse_signing_key(KEY).unwrap();
+        let other = parse_signing_key(OTHER_KEY).unwrap();
+        let signed = manifest().sign(&other);
+        assert_eq!(
+            signed.verify(&key.verifying_key()),
+            Err(Error::BadSignature)
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse_signing_key(b"9d61").err(), Some(Error::InvalidKey));
+        assert_eq!(parse_verifying_key(b"").err(), Some(Error::InvalidKey));
+        assert_eq!(parse_signature(KEY).err(), Some(Error::InvalidSignature));
+
+        let signed = manifest()
+            .sign(&parse_signing_key(KEY).unwrap())
+            .to_string();
+        let without = |prefix: &str| -> String {
+            signed
+                .lines()
+                .filter(|line| !line.starts_with(prefix))
+                .flat_map(|line| [line, "\n"])
+                .collect()
+        };
+        for invalid in [
+            without("version"),
+            without("channel"),
+            without("sha256"),
+            without("signature"),
+            signed.replace("v1.5.0", "a1b2c3d"),
+            signed.replace("stable", "nightly"),
+            format!("{signed}channel beta\n"),
+            format!("{signed}rollout 10\n"),
+        ] {
+            assert!(
+                matches!(
+                    SignedManifest::parse(invalid.as_bytes()),
+                    Err(Error::InvalidManifest(_))
+                ),
+                "{invalid}"
+            );
+        }
+    }
+}
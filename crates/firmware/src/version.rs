@@ -0,0 +1,282 @@
+//! Ordering of firmware versions, so that a board only moves forward.
+//!
+//! Versions are what `git describe --tags` prints for a repository with release tags
+//! like `v1.4.0` or `v1.5.0-beta.1`: the tag, and for builds after it the number of
+//! commits since and the revision, as in `v1.4.0-3-ga1b2c3d`. Versions order by tag
+//! and then by that number; builds from different revisions at the same point are
+//! neither older nor newer than each other. Builds from before tags were used are
+//! named by their bare revision; they are older than any tagged version.
+use crate::error::{Error, Result};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub release: semver::Version,
+    /// Commits on top of the release tag
+    pub commits: u64,
+    pub rev: Option<String>,
+}
+
+/// Whether `s` is a revision as `git describe` prints it, `g` and hex digits
+fn is_rev(s: &str) -> bool {
+    s.strip_prefix('g')
+        .is_some_and(|rev| !rev.is_empty() && rev.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidVersion(s.to_string());
+        let s = s.trim();
+        let s = s.strip_suffix("-dirty").unwrap_or(s);
+
+        // the build suffix is the last two dash-separated parts, if they fit
+        let mut parts = s.rsplitn(3, '-');
+        let (tag, commits, rev) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(rev), Some(commits), Some(tag))
+                if is_rev(rev) && commits.bytes().all(|b| b.is_ascii_digit()) =>
+            {
+                let commits = commits.parse().map_err(|_| invalid())?;
+                (tag, commits, Some(rev[1..].to_string()))
+            }
+            _ => (s, 0, None),
+        };
+
+        let tag = tag.strip_prefix('v').ok_or_else(invalid)?;
+        let release = semver::Version::parse(tag).map_err(|_| invalid())?;
+        Ok(Self {
+            release,
+            commits,
+            rev,
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.release)?;
+        if let Some(rev) = &self.rev {
+            write!(f, "-{}-g{rev}", self.commits)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.release
+            .cmp(&other.release)
+            .then(self.commits.cmp(&other.commits))
+            // only so that the order agrees with equality; see `Update::Different`
+            .then_with(|| self.rev.cmp(&other.rev))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// How a published version relates to the running one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Update {
+    /// Already running it
+    None,
+    Upgrade,
+    Downgrade,
+    /// Another build as many commits after the same tag, from another branch or a
+    /// rewritten one, which is neither newer nor older
+    Different,
+}
+
+/// Compare the `upstream` version to the `running` one
+/// # Errors
+/// [`Error::InvalidVersion`] if `upstream` isn't a tagged version
+pub fn classify(running: &str, upstream: &str) -> Result<Update> {
+    if running.trim() == upstream.trim() {
+        return Ok(Update::None);
+    }
+    let upstream: Version = upstream.parse()?;
+    let Ok(running) = running.parse::<Version>() else {
+        // an untagged build
+        return Ok(Update::Upgrade);
+    };
+    if upstream == running {
+        return Ok(Update::None);
+    }
+    let position = |version: &Version| (version.release.clone(), version.commits);
+    Ok(match position(&upstream).cmp(&position(&running)) {
+        Ordering::Greater => Update::Upgrade,
+        Ordering::Less => Update::Downgrade,
+        Ordering::Equal => Update::Different,
+    })
+}
+
+/// Which releases a board follows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Channel {
+    /// Releases without a pre-release part only
+    #[default]
+    Stable,
+    /// Every release, including pre-releases like `v1.5.0-beta.1`
+    Beta,
+}
+
+impl Channel {
+    /// Name of the file on the update server holding the channel's current version
+    #[must_use]
+    pub fn version_file(self) -> &'static str {
+        match self {
+            Channel::Stable => "version",
+            Channel::Beta => "version.beta",
+        }
+    }
+
+    /// Name of the file on the update server holding the channel's rollout manifest,
+    /// which takes precedence over the version file when present
+    #[must_use]
+    pub fn rollout_file(self) -> &'static str {
+        match self {
+            Channel::Stable => "rollout",
+            Channel::Beta => "rollout.beta",
+        }
+    }
+
+    /// Whether a board on this channel may install `version`
+    #[must_use]
+    pub fn accepts(self, version: &Version) -> bool {
+        match self {
+            Channel::Stable => version.release.pre.is_empty(),
+            Channel::Beta => true,
+        }
+    }
+
+    /// Whether a board on this channel may install a release signed for `channel`
+    #[must_use]
+    pub fn includes(self, channel: Channel) -> bool {
+        self == Channel::Beta || channel == Channel::Stable
+    }
+
+    /// The channel `version` is released to unless said otherwise: beta for
+    /// pre-releases, stable for the rest
+    #[must_use]
+    pub fn of(version: &Version) -> Self {
+        if version.release.pre.is_empty() {
+            Channel::Stable
+        } else {
+            Channel::Beta
+        }
+    }
+}
+
+impl FromStr for Channel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stable" => Ok(Channel::Stable),
+            "beta" => Ok(Channel::Beta),
+            _ => Err(Error::InvalidChannel(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Channel::Stable => "stable",
+            Channel::Beta => "beta",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let v = version("v1.4.0-3-ga1b2c3d");
+        assert_eq!(v.release, semver::Version::new(1, 4, 0));
+        assert_eq!(v.commits, 3);
+        assert_eq!(v.rev.as_deref(), Some("a1b2c3d"));
+        assert_eq!(v.to_string(), "v1.4.0-3-ga1b2c3d");
+
+        let v = version("v1.5.0-beta.1-12-ge4f5a6b-dirty\n");
+        assert_eq!(v.release.pre.as_str(), "beta.1");
+        assert_eq!(v.commits, 12);
+
+        let v = version("v1.5.0-rc-1");
+        assert_eq!(v.release.pre.as_str(), "rc-1");
+        assert_eq!(v.rev, None);
+
+        for invalid in ["a1b2c3d", "1.4.0", "v1.4", "", "v1.4.0 beta"] {
+            assert!(invalid.parse::<Version>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let ordered = [
+            "v1.4.0-beta.1",
+            "v1.4.0-beta.1-5-g0000000",
+            "v1.4.0-beta.2",
+            "v1.4.0",
+            "v1.4.0-1-g1111111",
+            "v1.4.0-10-g2222222",
+            "v1.4.1",
+            "v2.0.0",
+        ];
+        for pair in ordered.windows(2) {
+            assert!(version(pair[0]) < version(pair[1]), "{pair:?}");
+        }
+        assert_ne!(version("v1.4.0-1-g1111111"), version("v1.4.0-1-g3333333"));
+        assert_eq!(
+            version("v1.4.0-1-g1111111"),
+            version("v1.4.0-1-g1111111-dirty")
+        );
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("v1.4.0", "v1.4.0\n"), Ok(Update::None));
+        assert_eq!(classify("v1.4.0", "v1.4.1"), Ok(Update::Upgrade));
+        assert_eq!(classify("v1.4.1", "v1.4.0"), Ok(Update::Downgrade));
+        assert_eq!(
+            classify("v1.4.0-2-ga1b2c3d", "v1.4.0-2-gb2c3d4e"),
+            Ok(Update::Different)
+        );
+        assert_eq!(
+            classify("v1.4.0-2-ga1b2c3d-dirty", "v1.4.0-2-ga1b2c3d"),
+            Ok(Update::None)
+        );
+        assert_eq!(classify("a1b2c3d", "v1.4.0"), Ok(Update::Upgrade));
+        assert_eq!(
+            classify("v1.4.0", "a1b2c3d"),
+            Err(Error::InvalidVersion("a1b2c3d".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_channels() {
+        assert_eq!("beta".parse(), Ok(Channel::Beta));
+        assert!("nightly".parse::<Channel>().is_err());
+        assert!(!Channel::Stable.accepts(&version("v1.5.0-beta.1")));
+        assert!(Channel::Stable.accepts(&version("v1.4.0-3-ga1b2c3d")));
+        assert!(Channel::Beta.accepts(&version("v1.5.0-beta.1")));
+        assert!(!Channel::Stable.includes(Channel::Beta));
+        assert!(Channel::Beta.includes(Channel::Stable));
+        assert_eq!(
+            Channel::of(&version("v1.5.0-beta.1-2-ga1b2c3d")),
+            Channel::Beta
+        );
+        assert_eq!(Channel::of(&version("v1.5.0")), Channel::Stable);
+        assert_eq!(Channel::Beta.rollout_file(), "rollout.beta");
+    }
+}
@@ -0,0 +1,133 @@
+//! Contents of small files, shared by every client.
+//!
+//! A fleet of boards polls the same few files, such as `version`, over and over. The
+//! first session to read such a file reads all of it from the upstream and later
+//! reads by any session are answered from here until the entry expires. Writes that
+//! pass through the mux drop the entry of the file they touch right away.
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A file as seen by one user of one attached tree
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct FileKey {
+    pub uname: String,
+    pub aname: String,
+    pub path: Vec<String>,
+}
+
+impl FileKey {
+    /// The key of the file reached by walking `names` from this one
+    pub(crate) fn walk(&self, names: &[String]) -> Self {
+        let mut path = self.path.clone();
+        for name in names {
+            match name.as_str() {
+                ".." => drop(path.pop()),
+                "." => {}
+                name => path.push(name.to_string()),
+            }
+        }
+        Self {
+            uname: self.uname.clone(),
+            aname: self.aname.clone(),
+            path,
+        }
+    }
+}
+
+struct Entry {
+    data: Bytes,
+    fetched: Instant,
+}
+
+pub(crate) struct Cache {
+    max_size: usize,
+    ttl: Duration,
+    entries: Mutex<HashMap<FileKey, Entry>>,
+}
+
+impl Cache {
+    pub(crate) fn new(max_size: usize, ttl: Duration) -> Self {
+        Self {
+            max_size,
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Files larger than this are always read from the upstream
+    pub(crate) fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Whole contents of the file at `key`, if they are cached and still fresh
+    pub(crate) fn get(&self, key: &FileKey) -> Option<Bytes> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|entry| entry.fetched.elapsed() < self.ttl)
+            .map(|entry| entry.data.clone())
+    }
+
+    pub(crate) fn insert(&self, key: FileKey, data: Bytes) {
+        if data.len() > self.max_size || self.ttl.is_zero() {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.fetched.elapsed() < self.ttl);
+        entries.insert(
+            key,
+            Entry {
+                data,
+                fetched: Instant::now(),
+            },
+        );
+    }
+
+    /// Forget the file at `key` after it has been changed
+    pub(crate) fn invalidate(&self, key: &FileKey) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(path: &[&str]) -> FileKey {
+        FileKey {
+            uname: "relay-1".to_string(),
+            aname: String::new(),
+            path: path.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn test_walk() {
+        let names = ["files", "..", ".", "version"].map(String::from);
+        assert_eq!(key(&[]).walk(&names), key(&["version"]));
+    }
+
+    #[test]
+    fn test_entries_expire_and_invalidate() {
+        let cache = Cache::new(8, Duration::from_mins(1));
+        cache.insert(key(&["version"]), Bytes::from_static(b"a1b2c3d"));
+        cache.insert(key(&["large"]), Bytes::from_static(b"too large to cache"));
+
+        assert_eq!(
+            cache.get(&key(&["version"])),
+            Some(Bytes::from_static(b"a1b2c3d"))
+        );
+        assert_eq!(cache.get(&key(&["large"])), None);
+
+        cache.invalidate(&key(&["version"]));
+        assert_eq!(cache.get(&key(&["version"])), None);
+
+        let cache = Cache::new(8, Duration::ZERO);
+        cache.insert(key(&["version"]), Bytes::from_static(b"a1b2c3d"));
+        assert_eq!(cache.get(&key(&["version"])), None);
+    }
+}
@@ -0,0 +1,26 @@
+use std::net::SocketAddr;
+
+/// 9P server that shares one connection to an upstream server between many clients
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub(crate) struct Args {
+    /// Address to accept client connections on
+    #[clap(short, long, default_value = "127.0.0.1:5640")]
+    pub listen: SocketAddr,
+
+    /// Address of the 9P server to forward requests to
+    #[clap(short, long)]
+    pub upstream: String,
+
+    /// Connect to the upstream over TLS, expecting the certificate with this SHA-256 fingerprint
+    #[clap(long)]
+    pub upstream_fingerprint: Option<String>,
+
+    /// Largest file, in bytes, whose contents are cached for all clients
+    #[clap(long, default_value_t = 4096)]
+    pub cache_max_size: usize,
+
+    /// Seconds a cached file is served before it is read from the upstream again
+    #[clap(long, default_value_t = 10)]
+    pub cache_ttl: u64,
+}
@@ -0,0 +1,13 @@
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    StdIo(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Proto(#[from] stowage_proto::error::Error),
+
+    #[error("upstream connection closed")]
+    UpstreamClosed,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
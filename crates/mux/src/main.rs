@@ -0,0 +1,76 @@
+use crate::{
+    cache::Cache,
+    error::{Error, Result},
+    session::Session,
+    upstream::Upstream,
+};
+use clap::Parser;
+use std::{sync::Arc, time::Duration};
+use stowage_proto::{tls, transport};
+use tokio::net::TcpListener;
+use tracing::{error, info};
+
+mod cache;
+mod commands;
+mod error;
+mod session;
+mod upstream;
+
+/// msize offered to the upstream; clients get at most this much
+const MSIZE: u32 = 64 * 1024;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args = commands::Args::parse();
+    let fingerprint = args
+        .upstream_fingerprint
+        .as_deref()
+        .map(tls::parse_fingerprint)
+        .transpose()?;
+
+    let conn = match fingerprint {
+        Some(fingerprint) => tls::connect(&args.upstream, fingerprint).await?,
+        None => transport::connect_tcp(&args.upstream).await?,
+    };
+    let (upstream, routing) = Upstream::start(conn, MSIZE).await?;
+    let cache = Arc::new(Cache::new(
+        args.cache_max_size,
+        Duration::from_secs(args.cache_ttl),
+    ));
+
+    let listener = TcpListener::bind(args.listen).await?;
+    info!(
+        "multiplexing {} onto {} with msize {}",
+        args.listen,
+        args.upstream,
+        upstream.msize()
+    );
+
+    tokio::select! {
+        result = routing => result.unwrap_or(Err(Error::UpstreamClosed)),
+        result = accept(listener, upstream, cache) => result,
+    }
+}
+
+/// Serve every client that connects over the shared upstream
+async fn accept(listener: TcpListener, upstream: Arc<Upstream>, cache: Arc<Cache>) -> Result<()> {
+    let mut sessions = 0;
+    loop {
+        let (client, peer) = listener.accept().await?;
+        let id = sessions;
+        sessions += 1;
+        let session = Session::new(id, upstream.clone(), cache.clone());
+
+        tokio::spawn(async move {
+            info!("session {id} from {peer}");
+            match session.run(Box::new(client)).await {
+                Ok(()) => info!("session {id} closed"),
+                Err(e) => error!("session {id} failed: {e}"),
+            }
+        });
+    }
+}
@@ -0,0 +1,599 @@
+//! One client connection to the mux.
+//!
+//! Every fid the client creates is backed by a fid from the shared upstream pool, and
+//! each request is rewritten to use the upstream fid before it is forwarded. Tags need
+//! no table of their own: a session forwards its requests one at a time, in the order
+//! they arrive, and answers each with the client's tag once the upstream has answered.
+//! Clients still run in parallel, because the upstream connection carries the requests
+//! of all sessions at once.
+use crate::cache::{Cache, FileKey};
+use crate::error::Result;
+use crate::upstream::Upstream;
+use bytes::{Bytes, BytesMut};
+use futures::{SinkExt, StreamExt};
+use std::{collections::HashMap, sync::Arc};
+use stowage_proto::{
+    consts::{P9_IOHDRSZ, P9_NOFID},
+    error::ErrorKind,
+    transport::{connection, Connection, Transport},
+    Message, OpenMode, QidType, Rerror, Rflush, Rread, Rversion, TaggedMessage, Tattach, Tauth,
+    Tclunk, Tcreate, Topen, Tread, Tremove, Tstat, Tversion, Twalk, Twrite, Twstat,
+};
+use tracing::debug;
+
+/// A fid of the client
+struct Fid {
+    upstream: u32,
+    /// The file the fid refers to, `None` for auth fids
+    file: Option<FileKey>,
+    /// Opened only for reading a regular file, so reads may be answered from the cache
+    cacheable: bool,
+}
+
+pub(crate) struct Session {
+    id: usize,
+    upstream: Arc<Upstream>,
+    cache: Arc<Cache>,
+    fids: HashMap<u32, Fid>,
+    msize: u32,
+}
+
+impl Session {
+    pub(crate) fn new(id: usize, upstream: Arc<Upstream>, cache: Arc<Cache>) -> Self {
+        let msize = upstream.msize();
+        Self {
+            id,
+            upstream,
+            cache,
+            fids: HashMap::new(),
+            msize,
+        }
+    }
+
+    /// Serve `client` until it disconnects, then clunk whatever fids it left behind
+    pub(crate) async fn run(mut self, client: Box<dyn Transport>) -> Result<()> {
+        let mut conn = connection(client);
+        conn.codec_mut().set_msize(self.msize);
+
+        let result = self.serve(&mut conn).await;
+        let clunked = self.clunk_all().await;
+        result.and(clunked)
+    }
+
+    async fn serve(&mut self, conn: &mut Connection) -> Result<()> {
+        while let Some(request) = conn.next().await {
+            let request = request?;
+            let response = self.handle(request.message).await?;
+            conn.send(TaggedMessage::new(request.tag, response)).await?;
+            conn.codec_mut().set_msize(self.msize);
+        }
+        Ok(())
+    }
+
+    /// Answer a request, forwarding it upstream if needed. Only a lost upstream is an
+    /// error; everything else is reported to the client in an `Rerror`.
+    async fn handle(&mut self, message: Message) -> Result<Message> {
+        match message {
+            Message::Tversion(tversion) => self.version(tversion).await,
+            // the request being flushed has already been answered
+            Message::Tflush(_) => Ok(Message::Rflush(Rflush)),
+            Message::Tauth(tauth) => self.auth(tauth).await,
+            Message::Tattach(tattach) => self.attach(tattach).await,
+            Message::Twalk(twalk) => self.walk(twalk).await,
+            Message::Topen(topen) => self.open(topen).await,
+            Message::Tcreate(tcreate) => self.create(tcreate).await,
+            Message::Tread(tread) => self.read(tread).await,
+            Message::Twrite(Twrite { fid, offset, data }) => {
+                let Some(upstream) = self.modify(fid) else {
+                    return Ok(error(ErrorKind::BadFid));
+                };
+                self.upstream
+                    .call(Message::Twrite(Twrite {
+                        fid: upstream,
+                        offset,
+                        data,
+                    }))
+                    .await
+            }
+            Message::Tclunk(Tclunk { fid }) => self.clunk(fid, false).await,
+            Message::Tremove(Tremove { fid }) => self.clunk(fid, true).await,
+            Message::Tstat(Tstat { fid }) => {
+                let Some(upstream) = self.fids.get(&fid).map(|fid| fid.upstream) else {
+                    return Ok(error(ErrorKind::BadFid));
+                };
+                self.upstream
+                    .call(Message::Tstat(Tstat { fid: upstream }))
+                    .await
+            }
+            Message::Twstat(Twstat { fid, stat }) => {
+                let Some(upstream) = self.modify(fid) else {
+                    return Ok(error(ErrorKind::BadFid));
+                };
+                self.upstream
+                    .call(Message::Twstat(Twstat {
+                        fid: upstream,
+                        stat,
+                    }))
+                    .await
+            }
+            other => {
+                debug!(
+                    "session {}: unsupported {:?}",
+                    self.id,
+                    other.message_type()
+                );
+                Ok(error(ErrorKind::Unsupported))
+            }
+        }
+    }
+
+    async fn version(&mut self, tversion: Tversion) -> Result<Message> {
+        // a new version aborts everything the client had going
+        self.clunk_all().await?;
+        self.msize = tversion.msize.min(self.upstream.msize());
+        let version = if tversion.version.starts_with("9P2000") {
+            "9P2000"
+        } else {
+            "unknown"
+        };
+        Ok(Message::Rversion(Rversion {
+            msize: self.msize,
+            version: version.to_string(),
+        }))
+    }
+
+    async fn auth(&mut self, tauth: Tauth) -> Result<Message> {
+        if self.fids.contains_key(&tauth.afid) {
+            return Ok(fid_in_use());
+        }
+
+        let upstream = self.upstream.alloc_fid();
+        let response = self
+            .upstream
+            .call(Message::Tauth(Tauth {
+                afid: upstream,
+                ..tauth
+            }))
+            .await?;
+        let created = matches!(response, Message::Rauth(_));
+        self.adopt(tauth.afid, upstream, None, created);
+        Ok(response)
+    }
+
+    async fn attach(&mut self, tattach: Tattach) -> Result<Message> {
+        let afid = if tattach.afid == P9_NOFID {
+            P9_NOFID
+        } else {
+            match self.fids.get(&tattach.afid) {
+                Some(afid) => afid.upstream,
+                None => return Ok(error(ErrorKind::BadFid)),
+            }
+        };
+        if self.fids.contains_key(&tattach.fid) {
+            return Ok(fid_in_use());
+        }
+
+        let file = FileKey {
+            uname: tattach.uname.clone(),
+            aname: tattach.aname.clone(),
+            path: Vec::new(),
+        };
+        let upstream = self.upstream.alloc_fid();
+        let response = self
+            .upstream
+            .call(Message::Tattach(Tattach {
+                fid: upstream,
+                afid,
+                ..tattach
+            }))
+            .await?;
+        let created = matches!(response, Message::Rattach(_));
+        self.adopt(tattach.fid, upstream, Some(file), created);
+        Ok(response)
+    }
+
+    async fn walk(&mut self, twalk: Twalk) -> Result<Message> {
+        let Some(from) = self.fids.get(&twalk.fid) else {
+            return Ok(error(ErrorKind::BadFid));
+        };
+        let from_upstream = from.upstream;
+        let file = from.file.as_ref().map(|file| file.walk(&twalk.wnames));
+        let names = twalk.wnames.len();
+
+        if twalk.newfid == twalk.fid {
+            let response = self
+                .upstream
+                .call(Message::Twalk(Twalk {
+                    fid: from_upstream,
+                    newfid: from_upstream,
+                    wnames: twalk.wnames,
+                }))
+                .await?;
+            if matches!(&response, Message::Rwalk(rwalk) if rwalk.wqids.len() == names) {
+                if let Some(fid) = self.fids.get_mut(&twalk.fid) {
+                    fid.file = file;
+                }
+            }
+            return Ok(response);
+        }
+        if self.fids.contains_key(&twalk.newfid) {
+            return Ok(fid_in_use());
+        }
+
+        let upstream = self.upstream.alloc_fid();
+        let response = self
+            .upstream
+            .call(Message::Twalk(Twalk {
+                fid: from_upstream,
+                newfid: upstream,
+                wnames: twalk.wnames,
+            }))
+            .await?;
+        // newfid only exists once every name has been walked
+        let created = matches!(&response, Message::Rwalk(rwalk) if rwalk.wqids.len() == names);
+        self.adopt(twalk.newfid, upstream, file, created);
+        Ok(response)
+    }
+
+    async fn open(&mut self, topen: Topen) -> Result<Message> {
+        let Some(fid) = self.fids.get(&topen.fid) else {
+            return Ok(error(ErrorKind::BadFid));
+        };
+        let upstream = fid.upstream;
+
+        let mut response = self
+            .upstream
+            .call(Message::Topen(Topen {
+                fid: upstream,
+                mode: topen.mode,
+            }))
+            .await?;
+        if let Message::Ropen(ropen) = &mut response {
+            ropen.iounit = self.iounit(ropen.iounit);
+            let truncated = topen.mode.contains(OpenMode::Trunc);
+            if truncated {
+                self.modify(topen.fid);
+            }
+            if let Some(fid) = self.fids.get_mut(&topen.fid) {
+                // Read is 0, so it has to be told apart from the other access modes by value
+                fid.cacheable = topen.mode.bits() & 0x3 == OpenMode::Read as u8
+                    && !truncated
+                    && !ropen.qid.qtype.contains(QidType::Dir)
+                    && !ropen.qid.qtype.contains(QidType::Auth);
+            }
+        }
+        Ok(response)
+    }
+
+    async fn create(&mut self, tcreate: Tcreate) -> Result<Message> {
+        let Some(fid) = self.fids.get(&tcreate.fid) else {
+            return Ok(error(ErrorKind::BadFid));
+        };
+        let upstream = fid.upstream;
+        let name = tcreate.name.clone();
+
+        let mut response = self
+            .upstream
+            .call(Message::Tcreate(Tcreate {
+                fid: upstream,
+                ..tcreate
+            }))
+            .await?;
+        if let Message::Rcreate(rcreate) = &mut response {
+            rcreate.iounit = self.iounit(rcreate.iounit);
+            if let Some(fid) = self.fids.get_mut(&tcreate.fid) {
+                // the fid now refers to the new file
+                fid.file = fid.file.as_ref().map(|file| file.walk(&[name]));
+                fid.cacheable = false;
+                if let Some(file) = &fid.file {
+                    self.cache.invalidate(file);
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    async fn read(&mut self, tread: Tread) -> Result<Message> {
+        let Some(fid) = self.fids.get(&tread.fid) else {
+            return Ok(error(ErrorKind::BadFid));
+        };
+        let upstream = fid.upstream;
+
+        if let (true, Some(file)) = (fid.cacheable, fid.file.clone()) {
+            if let Some(data) = self.cached(file, upstream).await? {
+                let count = tread.count.min(self.msize.saturating_sub(P9_IOHDRSZ));
+                return Ok(Message::Rread(Rread {
+                    data: window(&data, tread.offset, count),
+                }));
+            }
+            // too large to cache, don't try again for this fid
+            if let Some(fid) = self.fids.get_mut(&tread.fid) {
+                fid.cacheable = false;
+            }
+        }
+
+        self.upstream
+            .call(Message::Tread(Tread {
+                fid: upstream,
+                ..tread
+            }))
+            .await
+    }
+
+    /// Contents of `file` from the cache, reading them through the open `upstream` fid
+    /// if they aren't cached yet
+    async fn cached(&self, file: FileKey, upstream: u32) -> Result<Option<Bytes>> {
+        if let Some(data) = self.cache.get(&file) {
+            return Ok(Some(data));
+        }
+        let data = self.fetch(upstream).await?;
+        if let Some(data) = &data {
+            self.cache.insert(file, data.clone());
+        }
+        Ok(data)
+    }
+
+    /// Read a whole file through an open upstream fid, giving up once it turns out to
+    /// be larger than the cache takes
+    async fn fetch(&self, upstream: u32) -> Result<Option<Bytes>> {
+        let max_size = self.cache.max_size();
+        let count = u32::try_from(max_size + 1)
+            .unwrap_or(u32::MAX)
+            .min(self.upstream.msize().saturating_sub(P9_IOHDRSZ));
+
+        let mut data = BytesMut::new();
+        loop {
+            let response = self
+                .upstream
+                .call(Message::Tread(Tread {
+                    fid: upstream,
+                    offset: data.len() as u64,
+                    count,
+                }))
+                .await?;
+            match response {
+                Message::Rread(rread) if rread.data.is_empty() => return Ok(Some(data.freeze())),
+                Message::Rread(rread) => {
+                    data.extend_from_slice(&rread.data);
+                    if data.len() > max_size {
+                        return Ok(None);
+                    }
+                }
+                // the uncached read that follows reports the error to the client
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    async fn clunk(&mut self, fid: u32, remove: bool) -> Result<Message> {
+        let Some(fid) = self.fids.remove(&fid) else {
+            return Ok(error(ErrorKind::BadFid));
+        };
+        let request = if remove {
+            if let Some(file) = &fid.file {
+                self.cache.invalidate(file);
+            }
+            Message::Tremove(Tremove { fid: fid.upstream })
+        } else {
+            Message::Tclunk(Tclunk { fid: fid.upstream })
+        };
+
+        let response = self.upstream.call(request).await?;
+        // the fid is gone even if the request failed
+        self.upstream.release_fid(fid.upstream);
+        Ok(response)
+    }
+
+    async fn clunk_all(&mut self) -> Result<()> {
+        for (_, fid) in self.fids.drain() {
+            self.upstream
+                .call(Message::Tclunk(Tclunk { fid: fid.upstream }))
+                .await?;
+            self.upstream.release_fid(fid.upstream);
+        }
+        Ok(())
+    }
+
+    /// Record `upstream` as the fid behind the client's `fid` if the request that was
+    /// meant to create it did, and give it back to the pool otherwise
+    fn adopt(&mut self, fid: u32, upstream: u32, file: Option<FileKey>, created: bool) {
+        if created {
+            self.fids.insert(
+                fid,
+                Fid {
+                    upstream,
+                    file,
+                    cacheable: false,
+                },
+            );
+        } else {
+            self.upstream.release_fid(upstream);
+        }
+    }
+
+    /// Look up a fid whose file is about to change and drop that file from the cache
+    fn modify(&self, fid: u32) -> Option<u32> {
+        let fid = self.fids.get(&fid)?;
+        if let Some(file) = &fid.file {
+            self.cache.invalidate(file);
+        }
+        Some(fid.upstream)
+    }
+
+    /// The upstream's iounit, limited to what fits this session's msize
+    fn iounit(&self, iounit: u32) -> u32 {
+        if iounit == 0 {
+            0
+        } else {
+            iounit.min(self.msize.saturating_sub(P9_IOHDRSZ))
+        }
+    }
+}
+
+fn error(kind: ErrorKind) -> Message {
+    Message::Rerror(kind.into())
+}
+
+fn fid_in_use() -> Message {
+    Message::Rerror(Rerror {
+        ename: "fid already in use".to_string(),
+    })
+}
+
+/// The part of `data` that a read of `count` bytes at `offset` returns
+fn window(data: &Bytes, offset: u64, count: u32) -> Bytes {
+    let start = usize::try_from(offset).map_or(data.len(), |offset| offset.min(data.len()));
+    let end = start.saturating_add(count as usize).min(data.len());
+    data.slice(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        net::TcpStream,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use stowage_proto::{blocking::Client, Qid, Rattach, Rclunk, Ropen, Rwalk};
+    use tokio::net::TcpListener;
+
+    fn qid(qtype: QidType, path: u64) -> Qid {
+        Qid {
+            qtype: qtype.into(),
+            version: 0,
+            path,
+        }
+    }
+
+    /// Upstream serving a flat directory of files that counts the reads it answers.
+    /// Like a real server, it refuses to create a fid that is already in use.
+    async fn serve_files(
+        mut conn: Connection,
+        files: HashMap<String, Bytes>,
+        reads: Arc<AtomicUsize>,
+    ) {
+        // the file each fid refers to, `None` for the root
+        let mut fids: HashMap<u32, Option<String>> = HashMap::new();
+        while let Some(Ok(request)) = conn.next().await {
+            let response = match request.message {
+                Message::Tversion(tversion) => Message::Rversion(Rversion {
+                    msize: tversion.msize,
+                    version: "9P2000".to_string(),
+                }),
+                Message::Tattach(tattach) if !fids.contains_key(&tattach.fid) => {
+                    fids.insert(tattach.fid, None);
+                    Message::Rattach(Rattach {
+                        qid: qid(QidType::Dir, 0),
+                    })
+                }
+                Message::Twalk(twalk) if !fids.contains_key(&twalk.newfid) => {
+                    match (fids.get(&twalk.fid), twalk.wnames.as_slice()) {
+                        (Some(None), [name]) if files.contains_key(name) => {
+                            fids.insert(twalk.newfid, Some(name.clone()));
+                            Message::Rwalk(Rwalk {
+                                wqids: vec![qid(QidType::File, 1)],
+                            })
+                        }
+                        (Some(_), _) => error(ErrorKind::NotFound),
+                        (None, _) => error(ErrorKind::BadFid),
+                    }
+                }
+                Message::Topen(topen) => match fids.get(&topen.fid) {
+                    Some(Some(_)) => Message::Ropen(Ropen {
+                        qid: qid(QidType::File, 1),
+                        iounit: 0,
+                    }),
+                    _ => error(ErrorKind::BadFid),
+                },
+                Message::Tread(tread) => {
+                    reads.fetch_add(1, Ordering::SeqCst);
+                    match fids.get(&tread.fid) {
+                        Some(Some(name)) => Message::Rread(Rread {
+                            data: window(&files[name], tread.offset, tread.count),
+                        }),
+                        _ => error(ErrorKind::BadFid),
+                    }
+                }
+                Message::Tclunk(tclunk) if fids.remove(&tclunk.fid).is_some() => {
+                    Message::Rclunk(Rclunk)
+                }
+                Message::Tattach(_) | Message::Twalk(_) => fid_in_use(),
+                _ => error(ErrorKind::BadFid),
+            };
+            conn.send(TaggedMessage::new(request.tag, response))
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Start a mux in front of [`serve_files`] and return its address and the read count
+    async fn start_mux(files: &[(&str, &[u8])], cache: Cache) -> (String, Arc<AtomicUsize>) {
+        let files = files
+            .iter()
+            .map(|(name, data)| (name.to_string(), Bytes::copy_from_slice(data)))
+            .collect();
+        let reads = Arc::new(AtomicUsize::new(0));
+        let (mux_side, server_side) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve_files(connection(server_side), files, reads.clone()));
+
+        let (upstream, _routing) = Upstream::start(connection(mux_side), 8192).await.unwrap();
+        let cache = Arc::new(cache);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            for id in 0.. {
+                let (client, _) = listener.accept().await.unwrap();
+                let session = Session::new(id, upstream.clone(), cache.clone());
+                tokio::spawn(session.run(Box::new(client)));
+            }
+        });
+
+        (addr, reads)
+    }
+
+    fn fetch(client: &mut Client<TcpStream>, path: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        client.fetch(path, &mut data).unwrap();
+        data
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_clients_share_the_upstream() {
+        let firmware = vec![7; 10_000];
+        let (addr, reads) = start_mux(
+            &[("version", b"a1b2c3d"), ("firmware", &firmware)],
+            Cache::new(16, Duration::from_mins(1)),
+        )
+        .await;
+
+        tokio::task::spawn_blocking(move || {
+            // both boards attach with the same fids, which only works if the mux remaps them
+            let mut first = Client::connect(&addr).unwrap();
+            let mut second = Client::connect(&addr).unwrap();
+
+            // the first board reads the file and its end, the second hits the cache
+            assert_eq!(fetch(&mut first, "version"), b"a1b2c3d");
+            assert_eq!(reads.load(Ordering::SeqCst), 2);
+            assert_eq!(fetch(&mut second, "version"), b"a1b2c3d");
+            assert_eq!(reads.load(Ordering::SeqCst), 2);
+
+            // files too large for the cache go to the upstream every time
+            assert_eq!(fetch(&mut first, "firmware"), firmware);
+            let after_first = reads.load(Ordering::SeqCst);
+            assert_eq!(fetch(&mut second, "firmware"), firmware);
+            assert!(reads.load(Ordering::SeqCst) > after_first);
+        })
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_window() {
+        let data = Bytes::from_static(b"a1b2c3d");
+        assert_eq!(window(&data, 0, 3), "a1b");
+        assert_eq!(window(&data, 5, 100), "3d");
+        assert_eq!(window(&data, 7, 100), "");
+        assert_eq!(window(&data, u64::MAX, 100), "");
+    }
+}
@@ -0,0 +1,138 @@
+//! The single connection to the upstream server that every client session shares.
+//!
+//! Sessions hand their requests to [`Upstream::call`]. A background task gives each
+//! request a tag that is free on the upstream connection and routes the response with
+//! that tag back to the session that sent it. Upstream fids are handed out from a
+//! pool shared by all sessions, so that the fids of different clients never collide.
+use crate::error::{Error, Result};
+use futures::{SinkExt, StreamExt};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use stowage_proto::{consts::P9_NOFID, transport::Connection, Message, TaggedMessage, Tversion};
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+/// Tag reserved for `Tversion`, never used for other requests
+const NOTAG: u16 = 0xFFFF;
+
+type Reply = oneshot::Sender<Message>;
+
+/// Upstream fids not in use by any session
+#[derive(Debug, Default)]
+struct FidPool {
+    next: u32,
+    free: Vec<u32>,
+}
+
+pub(crate) struct Upstream {
+    requests: mpsc::UnboundedSender<(Message, Reply)>,
+    fids: Mutex<FidPool>,
+    msize: u32,
+}
+
+impl Upstream {
+    /// Negotiate the version on `conn` and start routing requests over it. The returned
+    /// task finishes with an error once the connection is lost.
+    pub(crate) async fn start(
+        mut conn: Connection,
+        msize: u32,
+    ) -> Result<(Arc<Self>, tokio::task::JoinHandle<Result<()>>)> {
+        conn.send(TaggedMessage::new(
+            NOTAG,
+            Message::Tversion(Tversion {
+                msize,
+                version: "9P2000".to_string(),
+            }),
+        ))
+        .await?;
+        let msize = match conn.next().await.ok_or(Error::UpstreamClosed)??.message {
+            Message::Rversion(rversion) if rversion.version == "9P2000" => {
+                rversion.msize.min(msize)
+            }
+            other => {
+                return Err(stowage_proto::error::Error::Protocol(format!(
+                    "upstream doesn't speak 9P2000: {other}"
+                ))
+                .into())
+            }
+        };
+        conn.codec_mut().set_msize(msize);
+
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let upstream = Arc::new(Self {
+            requests,
+            fids: Mutex::new(FidPool::default()),
+            msize,
+        });
+        Ok((upstream, tokio::spawn(route(conn, receiver))))
+    }
+
+    /// Largest message the upstream accepts
+    pub(crate) fn msize(&self) -> u32 {
+        self.msize
+    }
+
+    /// Send `message` upstream and wait for its response
+    pub(crate) async fn call(&self, message: Message) -> Result<Message> {
+        let (reply, response) = oneshot::channel();
+        self.requests
+            .send((message, reply))
+            .map_err(|_| Error::UpstreamClosed)?;
+        response.await.map_err(|_| Error::UpstreamClosed)
+    }
+
+    /// Take an upstream fid that no session is using
+    pub(crate) fn alloc_fid(&self) -> u32 {
+        let mut pool = self.fids.lock().unwrap();
+        pool.free.pop().unwrap_or_else(|| {
+            let fid = pool.next;
+            pool.next += 1;
+            debug_assert_ne!(fid, P9_NOFID);
+            fid
+        })
+    }
+
+    /// Return an upstream fid that has been clunked, or was never created
+    pub(crate) fn release_fid(&self, fid: u32) {
+        self.fids.lock().unwrap().free.push(fid);
+    }
+}
+
+/// Forward requests to the upstream and responses back to whoever is waiting for them
+async fn route(
+    conn: Connection,
+    mut requests: mpsc::UnboundedReceiver<(Message, Reply)>,
+) -> Result<()> {
+    let (mut sink, mut stream) = conn.split();
+    let mut pending: HashMap<u16, Reply> = HashMap::new();
+    let mut next_tag: u16 = 0;
+
+    loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some((message, reply)) = request else {
+                    return Ok(());
+                };
+                while pending.contains_key(&next_tag) || next_tag == NOTAG {
+                    next_tag = next_tag.wrapping_add(1);
+                }
+                let tag = next_tag;
+                next_tag = next_tag.wrapping_add(1);
+
+                pending.insert(tag, reply);
+                sink.send(TaggedMessage::new(tag, message)).await?;
+            }
+            response = stream.next() => {
+                let response = response.ok_or(Error::UpstreamClosed)??;
+                if let Some(reply) = pending.remove(&response.tag) {
+                    // the session may have gone away in the meantime
+                    let _ = reply.send(response.message);
+                } else {
+                    warn!("upstream answered unknown tag {}", response.tag);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Throughput of moving firmware-sized data through `MessageCodec`.
+//!
+//! Each group compares the codec against the way it used to work: decoding data by
+//! copying it out of the frame, and encoding into a separate payload buffer that then
+//! gets copied behind the length prefix.
+use bytes::{Bytes, BytesMut};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::hint::black_box;
+use std::io::Cursor;
+use stowage_proto::{Dialect, Encodable, Message, MessageCodec, Rread, TaggedMessage, Twrite};
+use tokio_util::codec::{Decoder, Encoder};
+
+const SIZES: [usize; 2] = [64 * 1024, 1024 * 1024];
+
+fn rread(len: usize) -> TaggedMessage {
+    TaggedMessage::new(
+        1,
+        Message::Rread(Rread {
+            data: Bytes::from(vec![0xa5; len]),
+        }),
+    )
+}
+
+fn twrite(len: usize) -> TaggedMessage {
+    TaggedMessage::new(
+        1,
+        Message::Twrite(Twrite {
+            fid: 3,
+            offset: 0,
+            data: Bytes::from(vec![0xa5; len]),
+        }),
+    )
+}
+
+fn encoded(message: &TaggedMessage) -> BytesMut {
+    let mut buf = BytesMut::new();
+    MessageCodec::new()
+        .encode(message.clone(), &mut buf)
+        .unwrap();
+    buf
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_rread");
+    for len in SIZES {
+        let frame = encoded(&rread(len));
+        group.throughput(Throughput::Bytes(len as u64));
+
+        group.bench_function(format!("copying/{len}"), |b| {
+            b.iter(|| {
+                let mut cursor = Cursor::new(&frame[4..]);
+                black_box(TaggedMessage::decode_dialect(&mut cursor, Dialect::Plan9).unwrap())
+            });
+        });
+        group.bench_function(format!("codec/{len}"), |b| {
+            let mut codec = MessageCodec::new();
+            b.iter_batched(
+                || frame.clone(),
+                |mut buf| black_box(codec.decode(&mut buf).unwrap().unwrap()),
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_twrite");
+    for len in SIZES {
+        let message = twrite(len);
+        group.throughput(Throughput::Bytes(len as u64));
+
+        group.bench_function(format!("staged/{len}"), |b| {
+            b.iter(|| {
+                let mut payload = Vec::new();
+                message.encode(&mut payload).unwrap();
+                let mut dst = BytesMut::new();
+                dst.extend_from_slice(&u32::try_from(payload.len() + 4).unwrap().to_le_bytes());
+                dst.extend_from_slice(&payload);
+                black_box(dst)
+            });
+        });
+        group.bench_function(format!("codec/{len}"), |b| {
+            let mut codec = MessageCodec::new();
+            b.iter(|| {
+                let mut dst = BytesMut::new();
+                codec.encode(message.clone(), &mut dst).unwrap();
+                black_box(dst)
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, encode);
+criterion_main!(benches);
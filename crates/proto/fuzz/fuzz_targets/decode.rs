@@ -0,0 +1,19 @@
+//! Feed arbitrary bytes to `MessageCodec` as if they came off the network.
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use stowage_proto::{Dialect, MessageCodec};
+use tokio_util::codec::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    for dialect in [Dialect::Plan9, Dialect::Linux] {
+        let mut codec = MessageCodec::with_dialect(dialect).with_msize(64 * 1024);
+        let mut buf = BytesMut::from(data);
+
+        while let Ok(Some(message)) = codec.decode(&mut buf) {
+            // formatting is used for logging every message, so it has to cope too
+            let _ = message.to_string();
+        }
+    }
+});
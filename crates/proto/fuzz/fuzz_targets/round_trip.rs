@@ -0,0 +1,30 @@
+//! Any frame that decodes must encode again and decode to the same message.
+#![no_main]
+
+use bytes::BytesMut;
+use libfuzzer_sys::fuzz_target;
+use stowage_proto::{Dialect, MessageCodec};
+use tokio_util::codec::{Decoder, Encoder};
+
+fuzz_target!(|data: &[u8]| {
+    for dialect in [Dialect::Plan9, Dialect::Linux] {
+        let mut codec = MessageCodec::with_dialect(dialect).with_msize(64 * 1024);
+        let mut buf = BytesMut::from(data);
+
+        let Ok(Some(message)) = codec.decode(&mut buf) else {
+            continue;
+        };
+
+        let mut encoded = BytesMut::new();
+        codec
+            .encode(message.clone(), &mut encoded)
+            .expect("decoded message failed to encode");
+
+        let decoded = codec
+            .decode(&mut encoded)
+            .expect("re-encoded message failed to decode")
+            .expect("re-encoded message is incomplete");
+        assert_eq!(decoded, message);
+        assert!(encoded.is_empty());
+    }
+});
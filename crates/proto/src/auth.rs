@@ -0,0 +1,214 @@
+//! Authentication through an afid.
+//!
+//! A client that wants to authenticate sends `Tauth`, then reads and writes the afid
+//! until its [`Authenticator`] is satisfied, and finally passes the afid in `Tattach`.
+//! What is read and written is up to the authentication scheme; the exchange is
+//! described as a sequence of [`AuthStep`]s so that it doesn't depend on how the
+//! client talks to the server.
+//!
+//! [`SharedSecretAuth`] and [`SharedSecretVerifier`] implement a challenge-response
+//! scheme for a secret known to both sides: the server offers a random challenge on
+//! the afid and the client answers with `HMAC-SHA256(secret, DOMAIN || challenge || uname)`.
+use crate::error::{Error, Result};
+use alloc::{format, string::String, vec::Vec};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Length of the challenge a [`SharedSecretVerifier`] offers
+pub const CHALLENGE_LEN: u32 = 32;
+
+/// Separates these MACs from any other use of the same secret
+const DOMAIN: &[u8] = b"stowage 9p auth v1\0";
+
+/// What the client should do next with the afid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthStep {
+    /// `Tread` up to this many bytes and pass the data to the next step
+    Read(u32),
+    /// `Twrite` this data
+    Write(Vec<u8>),
+    /// The afid is ready to be used in `Tattach`
+    Done,
+}
+
+/// Client side of an authentication exchange
+pub trait Authenticator {
+    /// User to authenticate as, sent in `Tauth` and `Tattach`
+    fn uname(&self) -> &str;
+
+    /// Decide the next step. `input` holds the data returned by the previous
+    /// [`AuthStep::Read`] and is empty otherwise.
+    /// # Errors
+    /// - the server's data doesn't fit the scheme
+    fn step(&mut self, input: &[u8]) -> Result<AuthStep>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SharedSecretState {
+    ReadChallenge,
+    WriteResponse,
+    Done,
+}
+
+/// Client side of the shared secret challenge-response scheme
+#[derive(Debug, Clone)]
+pub struct SharedSecretAuth {
+    secret: Vec<u8>,
+    uname: String,
+    state: SharedSecretState,
+}
+
+impl SharedSecretAuth {
+    pub fn new(secret: impl Into<Vec<u8>>, uname: impl Into<String>) -> Self {
+        Self {
+            secret: secret.into(),
+            uname: uname.into(),
+            state: SharedSecretState::ReadChallenge,
+        }
+    }
+}
+
+impl Authenticator for SharedSecretAuth {
+    fn uname(&self) -> &str {
+        &self.uname
+    }
+
+    fn step(&mut self, input: &[u8]) -> Result<AuthStep> {
+        match self.state {
+            SharedSecretState::ReadChallenge => {
+                self.state = SharedSecretState::WriteResponse;
+                Ok(AuthStep::Read(CHALLENGE_LEN))
+            }
+            SharedSecretState::WriteResponse => {
+                if input.len() != CHALLENGE_LEN as usize {
+                    return Err(Error::Auth(format!(
+                        "expected a {CHALLENGE_LEN} byte challenge, got {} bytes",
+                        input.len()
+                    )));
+                }
+                self.state = SharedSecretState::Done;
+                Ok(AuthStep::Write(
+                    mac(&self.secret, input, &self.uname)
+                        .finalize()
+                        .into_bytes()
+                        .to_vec(),
+                ))
+            }
+            SharedSecretState::Done => Ok(AuthStep::Done),
+        }
+    }
+}
+
+/// Server side of the shared secret challenge-response scheme, one per afid
+#[derive(Debug, Clone)]
+pub struct SharedSecretVerifier {
+    secret: Vec<u8>,
+    uname: String,
+    challenge: [u8; CHALLENGE_LEN as usize],
+    verified: bool,
+}
+
+impl SharedSecretVerifier {
+    /// `challenge` must be freshly generated from a secure random source for every afid
+    pub fn new(
+        secret: impl Into<Vec<u8>>,
+        uname: impl Into<String>,
+        challenge: [u8; CHALLENGE_LEN as usize],
+    ) -> Self {
+        Self {
+            secret: secret.into(),
+            uname: uname.into(),
+            challenge,
+            verified: false,
+        }
+    }
+
+    /// Data to answer a `Tread` on the afid with
+    #[must_use]
+    pub fn challenge(&self) -> &[u8] {
+        &self.challenge
+    }
+
+    /// Check the data of a `Twrite` on the afid
+    /// # Errors
+    /// - the response was not made with the shared secret
+    pub fn respond(&mut self, response: &[u8]) -> Result<()> {
+        mac(&self.secret, &self.challenge, &self.uname)
+            .verify_slice(response)
+            .map_err(|_| Error::Auth(format!("bad response for {}", self.uname)))?;
+        self.verified = true;
+        Ok(())
+    }
+
+    /// Whether the afid may be used to attach as the user it was created for
+    #[must_use]
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+}
+
+fn mac(secret: &[u8], challenge: &[u8], uname: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(DOMAIN);
+    mac.update(challenge);
+    mac.update(uname.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `auth` against `verifier` the way a client would over the afid
+    fn exchange(auth: &mut impl Authenticator, verifier: &mut SharedSecretVerifier) -> Result<()> {
+        let mut input = Vec::new();
+        loop {
+            match auth.step(&input)? {
+                AuthStep::Read(count) => {
+                    input = verifier.challenge()[..count as usize].to_vec();
+                }
+                AuthStep::Write(data) => {
+                    verifier.respond(&data)?;
+                    input.clear();
+                }
+                AuthStep::Done => return Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_secret_exchange() {
+        let mut auth = SharedSecretAuth::new("hunter2", "relay-1");
+        let mut verifier =
+            SharedSecretVerifier::new("hunter2", "relay-1", [7; CHALLENGE_LEN as usize]);
+
+        exchange(&mut auth, &mut verifier).unwrap();
+        assert!(verifier.is_verified());
+        assert_eq!(auth.uname(), "relay-1");
+    }
+
+    #[test]
+    fn test_shared_secret_rejects_wrong_secret_or_user() {
+        let mut verifier =
+            SharedSecretVerifier::new("hunter2", "relay-1", [7; CHALLENGE_LEN as usize]);
+        let mut auth = SharedSecretAuth::new("hunter3", "relay-1");
+        assert!(matches!(
+            exchange(&mut auth, &mut verifier),
+            Err(Error::Auth(_))
+        ));
+        assert!(!verifier.is_verified());
+
+        // a response for one user can't be replayed to attach as another
+        let mut verifier =
+            SharedSecretVerifier::new("hunter2", "relay-2", [7; CHALLENGE_LEN as usize]);
+        let mut auth = SharedSecretAuth::new("hunter2", "relay-1");
+        assert!(exchange(&mut auth, &mut verifier).is_err());
+    }
+
+    #[test]
+    fn test_shared_secret_rejects_short_challenge() {
+        let mut auth = SharedSecretAuth::new("hunter2", "relay-1");
+        assert_eq!(auth.step(&[]).unwrap(), AuthStep::Read(CHALLENGE_LEN));
+        assert!(auth.step(&[1, 2, 3]).is_err());
+    }
+}
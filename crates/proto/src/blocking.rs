@@ -0,0 +1,757 @@
+//! A blocking 9P2000 client for host tools that don't run an async runtime.
+//!
+//! [`Client`] speaks over any `Read + Write` stream, usually a `std::net::TcpStream`,
+//! and sends one request at a time. Messages are framed with the same
+//! [`Encodable`]/[`Decodable`] implementations the async codec uses.
+use crate::auth::{AuthStep, Authenticator};
+use crate::consts::{P9_HEADER_SIZE, P9_IOHDRSZ, P9_MAXWELEM, P9_NOFID};
+use crate::error::{Error, ErrorKind, Result};
+use crate::wire::WireRead;
+use crate::{
+    decode_dir, read_exact_len, Dialect, Encodable, FileMode, Message, OpenMode, QidType, Stat,
+    TaggedMessage, Tattach, Tauth, Tclunk, Tcreate, Topen, Tread, Tstat, Tversion, Twalk, Twrite,
+};
+use bytes::Bytes;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// msize requested in `Tversion`
+const MSIZE: u32 = 64 * 1024;
+const VERSION_TAG: u16 = 0xFFFF;
+const TAG: u16 = 1;
+const AFID: u32 = 0;
+const ROOT_FID: u32 = 1;
+
+/// Write a message as a single frame
+/// # Errors
+/// - the message doesn't fit into `msize`
+/// - the stream fails
+pub fn write_message<W: Write>(w: &mut W, message: &TaggedMessage, msize: u32) -> Result<()> {
+    let mut frame = vec![0; 4];
+    message.encode(&mut frame)?;
+    let size = u32::try_from(frame.len())
+        .ok()
+        .filter(|size| *size <= msize)
+        .ok_or_else(|| {
+            Error::Protocol(format!(
+                "message of {} bytes exceeds msize {msize}",
+                frame.len()
+            ))
+        })?;
+    frame[..4].copy_from_slice(&size.to_le_bytes());
+    w.write_all(&frame)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// Read a single frame and decode the message in it
+/// # Errors
+/// - the frame is larger than `msize` or malformed
+/// - the stream fails or ends
+pub fn read_message<R: Read>(r: &mut R, msize: u32, dialect: Dialect) -> Result<TaggedMessage> {
+    let size = r.read_u32()?;
+    if size > msize || size < P9_HEADER_SIZE {
+        return Err(Error::Protocol(format!(
+            "message of {size} bytes doesn't fit msize {msize}"
+        )));
+    }
+    let frame = read_exact_len(r, size as usize - 4)?;
+    TaggedMessage::decode_frame(&Bytes::from(frame), dialect)
+}
+
+/// A connection attached to the root of a 9P2000 server
+pub struct Client<S> {
+    stream: S,
+    msize: u32,
+    next_fid: u32,
+}
+
+impl Client<TcpStream> {
+    /// Connect to `addr` and attach as `nobody` without authentication
+    /// # Errors
+    /// - the server can't be reached
+    /// - version negotiation or attach fails
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        Self::new(TcpStream::connect(addr)?, None)
+    }
+}
+
+impl<S: Read + Write> Client<S> {
+    /// Negotiate the version over `stream`, authenticate if the server asks for it
+    /// and `auth` is given, and attach to the root of the tree
+    /// # Errors
+    /// - the server doesn't speak 9P2000
+    /// - authentication or attach fails
+    pub fn new(stream: S, auth: Option<&mut dyn Authenticator>) -> Result<Self> {
+        let mut client = Self {
+            stream,
+            msize: MSIZE,
+            next_fid: ROOT_FID + 1,
+        };
+
+        write_message(
+            &mut client.stream,
+            &TaggedMessage::new(
+                VERSION_TAG,
+                Message::Tversion(Tversion {
+                    msize: MSIZE,
+                    version: "9P2000".to_string(),
+                }),
+            ),
+            client.msize,
+        )?;
+        match read_message(&mut client.stream, client.msize, Dialect::Plan9)?.message {
+            Message::Rversion(rversion) if rversion.version == "9P2000" => {
+                // reads and writes are sized as msize minus their header
+                if rversion.msize <= P9_IOHDRSZ {
+                    return Err(Error::Protocol(format!(
+                        "server offered msize {}, too small to carry any data",
+                        rversion.msize
+                    )));
+                }
+                client.msize = rversion.msize.min(MSIZE);
+            }
+            Message::Rversion(rversion) => {
+                return Err(Error::Protocol(format!(
+                    "server doesn't speak 9P2000, got {}",
+                    rversion.version
+                )))
+            }
+            other => return Err(unexpected("Tversion", &other)),
+        }
+
+        let (afid, uname) = client.authenticate(auth)?;
+        client.call(Message::Tattach(Tattach {
+            fid: ROOT_FID,
+            afid,
+            uname,
+            aname: String::new(),
+        }))?;
+
+        Ok(client)
+    }
+
+    /// Largest message size agreed on with the server
+    #[must_use]
+    pub fn msize(&self) -> u32 {
+        self.msize
+    }
+
+    /// Describe the file at `path`
+    /// # Errors
+    /// - the file doesn't exist
+    pub fn stat(&mut self, path: &str) -> Result<Stat> {
+        self.with_fid(path, |client, fid| {
+            match client.call(Message::Tstat(Tstat { fid }))? {
+                Message::Rstat(rstat) => Ok(rstat.stat),
+                other => Err(unexpected("Tstat", &other)),
+            }
+        })
+    }
+
+    /// List the entries of the directory at `path`
+    /// # Errors
+    /// - `path` doesn't exist or isn't a directory
+    pub fn list(&mut self, path: &str) -> Result<Vec<Stat>> {
+        self.with_fid(path, |client, fid| {
+            client.open(fid, OpenMode::Read.into(), true)?;
+
+            let mut entries = Vec::new();
+            let mut offset = 0;
+            loop {
+                let data = client.read(fid, offset)?;
+                if data.is_empty() {
+                    return Ok(entries);
+                }
+                entries.extend(decode_dir(&data)?);
+                offset += data.len() as u64;
+            }
+        })
+    }
+
+    /// Copy the contents of the file at `path` into `writer`, returning the number of bytes
+    /// # Errors
+    /// - `path` doesn't exist or is a directory
+    /// - writing to `writer` fails
+    pub fn fetch<W: Write>(&mut self, path: &str, writer: &mut W) -> Result<u64> {
+        self.with_fid(path, |client, fid| {
+            client.open(fid, OpenMode::Read.into(), false)?;
+
+            let mut offset = 0;
+            loop {
+                let data = client.read(fid, offset)?;
+                if data.is_empty() {
+                    return Ok(offset);
+                }
+                writer.write_all(&data)?;
+                offset += data.len() as u64;
+            }
+        })
+    }
+
+    /// Write everything from `reader` to the file at `path`, creating it with permissions
+    /// `perm` or truncating it if it exists. Returns the number of bytes written.
+    /// # Errors
+    /// - the parent directory doesn't exist
+    /// - the file can't be created or opened for writing
+    /// - reading from `reader` fails
+    pub fn upload<R: Read>(&mut self, path: &str, reader: &mut R, perm: u32) -> Result<u64> {
+        let (parent, name) = path
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .unwrap_or(("", path));
+        if name.is_empty() {
+            return Err(Error::Protocol(format!(
+                "{path}: no file name to upload to"
+            )));
+        }
+
+        let name = name.to_string();
+        self.with_fid(parent, |client, fid| {
+            let created = client.call(Message::Tcreate(Tcreate {
+                fid,
+                name: name.clone(),
+                perm: FileMode::from_unix_perm(perm, false),
+                mode: OpenMode::Write.into(),
+            }));
+            match created {
+                Ok(_) => client.write_all(fid, reader),
+                Err(e) if e.kind() == Some(ErrorKind::AlreadyExists) => {
+                    client.with_fid(path, |client, fid| {
+                        client.open(fid, OpenMode::Write | OpenMode::Trunc, false)?;
+                        client.write_all(fid, reader)
+                    })
+                }
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    /// Returns the afid and uname to attach with
+    fn authenticate(&mut self, auth: Option<&mut dyn Authenticator>) -> Result<(u32, String)> {
+        let uname = auth
+            .as_ref()
+            .map_or("nobody", |auth| auth.uname())
+            .to_string();
+        let response = self.call(Message::Tauth(Tauth {
+            afid: AFID,
+            uname: uname.clone(),
+            aname: String::new(),
+        }));
+
+        let auth = match (response, auth) {
+            (Ok(Message::Rauth(_)), Some(auth)) => auth,
+            (Ok(Message::Rauth(_)), None) => {
+                return Err(Error::Auth(
+                    "server requires authentication but no credentials were given".into(),
+                ))
+            }
+            (Ok(other), _) => return Err(unexpected("Tauth", &other)),
+            // expected when the server doesn't require authentication
+            (Err(Error::Server { .. }), _) => return Ok((P9_NOFID, uname)),
+            (Err(e), _) => return Err(e),
+        };
+
+        let mut input = Vec::new();
+        loop {
+            // the afid is a conversation rather than a file, so offsets are always 0
+            input = match auth.step(&input)? {
+                AuthStep::Read(count) => match self.call(Message::Tread(Tread {
+                    fid: AFID,
+                    offset: 0,
+                    count,
+                }))? {
+                    Message::Rread(rread) => rread.data.to_vec(),
+                    other => return Err(unexpected("Tread", &other)),
+                },
+                AuthStep::Write(data) => {
+                    self.call(Message::Twrite(Twrite {
+                        fid: AFID,
+                        offset: 0,
+                        data: data.into(),
+                    }))?;
+                    Vec::new()
+                }
+                AuthStep::Done => return Ok((AFID, uname)),
+            };
+        }
+    }
+
+    /// Send a request and wait for its response, turning `Rerror` into an error
+    fn call(&mut self, message: Message) -> Result<Message> {
+        write_message(
+            &mut self.stream,
+            &TaggedMessage::new(TAG, message),
+            self.msize,
+        )?;
+        let response = read_message(&mut self.stream, self.msize, Dialect::Plan9)?;
+        if response.tag != TAG {
+            return Err(Error::Protocol(format!(
+                "response tag {} doesn't match request tag {TAG}",
+                response.tag
+            )));
+        }
+        match response.message {
+            Message::Rerror(rerror) => Err(rerror.into()),
+            message => Ok(message),
+        }
+    }
+
+    /// Walk a fresh fid to `path`, run `f` on it and clunk it again
+    fn with_fid<T>(
+        &mut self,
+        path: &str,
+        f: impl FnOnce(&mut Self, u32) -> Result<T>,
+    ) -> Result<T> {
+        let fid = self.next_fid;
+        self.next_fid += 1;
+
+        let result = self.walk(fid, path).and_then(|()| f(self, fid));
+        // a failed walk leaves nothing to clunk, in which case the error is expected
+        let _ = self.call(Message::Tclunk(Tclunk { fid }));
+        result
+    }
+
+    fn walk(&mut self, fid: u32, path: &str) -> Result<()> {
+        let names: Vec<String> = path
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .map(String::from)
+            .collect();
+
+        // walking no names at all clones the root fid
+        let chunks: Vec<&[String]> = if names.is_empty() {
+            vec![&[]]
+        } else {
+            names.chunks(P9_MAXWELEM).collect()
+        };
+
+        let mut from = ROOT_FID;
+        for chunk in chunks {
+            let response = self.call(Message::Twalk(Twalk {
+                fid: from,
+                newfid: fid,
+                wnames: chunk.to_vec(),
+            }))?;
+            match response {
+                Message::Rwalk(rwalk) if rwalk.wqids.len() == chunk.len() => from = fid,
+                Message::Rwalk(_) => {
+                    return Err(Error::Server {
+                        kind: ErrorKind::NotFound,
+                        ename: format!("{path}: file does not exist"),
+                    })
+                }
+                other => return Err(unexpected("Twalk", &other)),
+            }
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, fid: u32, mode: flagset::FlagSet<OpenMode>, dir: bool) -> Result<()> {
+        match self.call(Message::Topen(Topen { fid, mode }))? {
+            Message::Ropen(ropen) if ropen.qid.qtype.contains(QidType::Dir) == dir => Ok(()),
+            Message::Ropen(_) if dir => Err(ErrorKind::NotADirectory.into()),
+            Message::Ropen(_) => Err(ErrorKind::IsADirectory.into()),
+            other => Err(unexpected("Topen", &other)),
+        }
+    }
+
+    fn read(&mut self, fid: u32, offset: u64) -> Result<Vec<u8>> {
+        match self.call(Message::Tread(Tread {
+            fid,
+            offset,
+            count: self.msize - P9_IOHDRSZ,
+        }))? {
+            Message::Rread(rread) => Ok(rread.data.to_vec()),
+            other => Err(unexpected("Tread", &other)),
+        }
+    }
+
+    fn write_all<R: Read>(&mut self, fid: u32, reader: &mut R) -> Result<u64> {
+        let mut buf = vec![0; (self.msize - P9_IOHDRSZ) as usize];
+        let mut offset = 0;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                return Ok(offset);
+            }
+
+            let mut written = 0;
+            while written < n {
+                let count = match self.call(Message::Twrite(Twrite {
+                    fid,
+                    offset,
+                    data: buf[written..n].to_vec().into(),
+                }))? {
+                    Message::Rwrite(rwrite) => rwrite.count,
+                    other => return Err(unexpected("Twrite", &other)),
+                };
+                if count == 0 {
+                    return Err(Error::Protocol("write made no progress".into()));
+                }
+                written += count as usize;
+                offset += u64::from(count);
+            }
+        }
+    }
+}
+
+fn unexpected(request: &str, response: &Message) -> Error {
+    Error::Protocol(format!(
+        "unexpected {:?} in response to {request}",
+        response.message_type()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{SharedSecretAuth, SharedSecretVerifier, CHALLENGE_LEN};
+    use crate::{
+        encode_dir, Qid, Rattach, Rauth, Rclunk, Rcreate, Ropen, Rread, Rstat, Rversion, Rwalk,
+        Rwrite,
+    };
+    use std::collections::{BTreeMap, HashMap};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Small msize so that reads, writes and listings take several messages
+    const SERVER_MSIZE: u32 = 256;
+
+    /// Just enough of a file server to exercise the client. Directories are the
+    /// parents of files; `""` is the root.
+    struct MemServer {
+        files: BTreeMap<String, Vec<u8>>,
+        fids: HashMap<u32, String>,
+        verifier: Option<SharedSecretVerifier>,
+    }
+
+    impl MemServer {
+        fn is_dir(&self, path: &str) -> bool {
+            path.is_empty()
+                || self
+                    .files
+                    .keys()
+                    .any(|file| file.starts_with(&format!("{path}/")))
+        }
+
+        fn qid(&self, path: &str) -> Qid {
+            Qid {
+                qtype: if self.is_dir(path) {
+                    QidType::Dir.into()
+                } else {
+                    QidType::File.into()
+                },
+                version: 0,
+                path: path.len() as u64,
+            }
+        }
+
+        fn stat(&self, path: &str) -> Stat {
+            let is_dir = self.is_dir(path);
+            Stat {
+                r#type: 0,
+                dev: 0,
+                qid: self.qid(path),
+                mode: FileMode::from_unix_perm(if is_dir { 0o755 } else { 0o644 }, is_dir),
+                atime: 0,
+                mtime: 0,
+                length: self.files.get(path).map_or(0, |data| data.len() as u64),
+                name: path.rsplit('/').next().unwrap_or_default().to_string(),
+                uid: "nobody".to_string(),
+                gid: "nobody".to_string(),
+                muid: String::new(),
+            }
+        }
+
+        fn children(&self, dir: &str) -> Vec<Stat> {
+            let prefix = if dir.is_empty() {
+                String::new()
+            } else {
+                format!("{dir}/")
+            };
+            let mut names: Vec<&str> = self
+                .files
+                .keys()
+                .filter_map(|file| file.strip_prefix(&prefix))
+                .map(|rest| rest.split('/').next().unwrap_or_default())
+                .collect();
+            names.dedup();
+            names
+                .into_iter()
+                .map(|name| self.stat(&format!("{prefix}{name}")))
+                .collect()
+        }
+
+        fn join(dir: &str, name: &str) -> String {
+            if dir.is_empty() {
+                name.to_string()
+            } else {
+                format!("{dir}/{name}")
+            }
+        }
+
+        #[allow(clippy::too_many_lines)]
+        fn handle(&mut self, message: Message) -> std::result::Result<Message, ErrorKind> {
+            Ok(match message {
+                Message::Tversion(tversion) => Message::Rversion(Rversion {
+                    msize: tversion.msize.min(SERVER_MSIZE),
+                    version: tversion.version,
+                }),
+                Message::Tauth(_) if self.verifier.is_some() => Message::Rauth(Rauth {
+                    aqid: Qid {
+                        qtype: QidType::Auth.into(),
+                        version: 0,
+                        path: 0,
+                    },
+                }),
+                Message::Tread(tread) if tread.fid == AFID => Message::Rread(Rread {
+                    data: self.verifier.as_ref().unwrap().challenge().to_vec().into(),
+                }),
+                Message::Twrite(twrite) if twrite.fid == AFID => {
+                    let verifier = self.verifier.as_mut().unwrap();
+                    verifier
+                        .respond(&twrite.data)
+                        .map_err(|_| ErrorKind::PermissionDenied)?;
+                    Message::Rwrite(Rwrite {
+                        count: u32::try_from(twrite.data.len()).unwrap(),
+                    })
+                }
+                Message::Tattach(tattach) => {
+                    if let Some(verifier) = &self.verifier {
+                        if tattach.afid != AFID || !verifier.is_verified() {
+                            return Err(ErrorKind::AuthRequired);
+                        }
+                    }
+                    self.fids.insert(tattach.fid, String::new());
+                    Message::Rattach(Rattach { qid: self.qid("") })
+                }
+                Message::Twalk(twalk) => {
+                    let mut path = self.fids.get(&twalk.fid).ok_or(ErrorKind::BadFid)?.clone();
+                    let mut wqids = Vec::new();
+                    for name in &twalk.wnames {
+                        let next = Self::join(&path, name);
+                        if !self.files.contains_key(&next) && !self.is_dir(&next) {
+                            break;
+                        }
+                        wqids.push(self.qid(&next));
+                        path = next;
+                    }
+                    if wqids.is_empty() && !twalk.wnames.is_empty() {
+                        return Err(ErrorKind::NotFound);
+                    }
+                    if wqids.len() == twalk.wnames.len() {
+                        self.fids.insert(twalk.newfid, path);
+                    }
+                    Message::Rwalk(Rwalk { wqids })
+                }
+                Message::Topen(topen) => {
+                    let path = self.fids.get(&topen.fid).ok_or(ErrorKind::BadFid)?.clone();
+                    if topen.mode.contains(OpenMode::Trunc) {
+                        self.files.insert(path.clone(), Vec::new());
+                    }
+                    Message::Ropen(Ropen {
+                        qid: self.qid(&path),
+                        iounit: 0,
+                    })
+                }
+                Message::Tcreate(tcreate) => {
+                    let dir = self.fids.get(&tcreate.fid).ok_or(ErrorKind::BadFid)?;
+                    let path = Self::join(dir, &tcreate.name);
+                    if self.files.contains_key(&path) {
+                        return Err(ErrorKind::AlreadyExists);
+                    }
+                    self.files.insert(path.clone(), Vec::new());
+                    self.fids.insert(tcreate.fid, path.clone());
+                    Message::Rcreate(Rcreate {
+                        qid: self.qid(&path),
+                        iounit: 0,
+                    })
+                }
+                Message::Tread(tread) => {
+                    let path = self.fids.get(&tread.fid).ok_or(ErrorKind::BadFid)?;
+                    let data = if self.is_dir(path) {
+                        encode_dir(&self.children(path), tread.offset, tread.count)
+                            .map_err(|_| ErrorKind::InvalidArgument)?
+                    } else {
+                        let data = &self.files[path];
+                        let start = usize::try_from(tread.offset).unwrap().min(data.len());
+                        let end = (start + tread.count as usize).min(data.len());
+                        data[start..end].to_vec().into()
+                    };
+                    Message::Rread(Rread { data })
+                }
+                Message::Twrite(twrite) => {
+                    let path = self.fids.get(&twrite.fid).ok_or(ErrorKind::BadFid)?;
+                    let data = self.files.get_mut(path).ok_or(ErrorKind::IsADirectory)?;
+                    let offset = usize::try_from(twrite.offset).unwrap();
+                    data.resize(data.len().max(offset + twrite.data.len()), 0);
+                    data[offset..offset + twrite.data.len()].copy_from_slice(&twrite.data);
+                    Message::Rwrite(Rwrite {
+                        count: u32::try_from(twrite.data.len()).unwrap(),
+                    })
+                }
+                Message::Tstat(tstat) => {
+                    let path = self.fids.get(&tstat.fid).ok_or(ErrorKind::BadFid)?;
+                    Message::Rstat(Rstat {
+                        stat: self.stat(path),
+                    })
+                }
+                Message::Tclunk(tclunk) => {
+                    self.fids.remove(&tclunk.fid).ok_or(ErrorKind::BadFid)?;
+                    Message::Rclunk(Rclunk)
+                }
+                _ => return Err(ErrorKind::Unsupported),
+            })
+        }
+
+        fn serve(mut self, mut stream: TcpStream) -> BTreeMap<String, Vec<u8>> {
+            while let Ok(request) = read_message(&mut stream, SERVER_MSIZE, Dialect::Plan9) {
+                let response = self
+                    .handle(request.message)
+                    .unwrap_or_else(|kind| Message::Rerror(kind.into()));
+                write_message(
+                    &mut stream,
+                    &TaggedMessage::new(request.tag, response),
+                    SERVER_MSIZE,
+                )
+                .unwrap();
+            }
+            self.files
+        }
+    }
+
+    /// Serve one connection from `files` on a loopback port, returning the files it ends with
+    fn spawn_server(
+        files: &[(&str, &[u8])],
+        verifier: Option<SharedSecretVerifier>,
+    ) -> (String, thread::JoinHandle<BTreeMap<String, Vec<u8>>>) {
+        let server = MemServer {
+            files: files
+                .iter()
+                .map(|(path, data)| ((*path).to_string(), data.to_vec()))
+                .collect(),
+            fids: HashMap::new(),
+            verifier,
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || server.serve(listener.accept().unwrap().0));
+        (addr, handle)
+    }
+
+    fn firmware() -> Vec<u8> {
+        (0..2000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_list_and_fetch() {
+        let firmware = firmware();
+        let (addr, server) = spawn_server(
+            &[
+                ("esp32/relay-controller/version", b"a1b2c3d\n"),
+                ("esp32/relay-controller/files/a1b2c3d", &firmware),
+                ("esp32/relay-controller/files/e4f5a6b", b"older"),
+            ],
+            None,
+        );
+
+        let mut client = Client::connect(&addr).unwrap();
+        assert_eq!(client.msize(), SERVER_MSIZE);
+
+        let names: Vec<String> = client
+            .list("/esp32/relay-controller")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["files", "version"]);
+        assert_eq!(
+            client.list("esp32/relay-controller/files").unwrap().len(),
+            2
+        );
+
+        let mut data = Vec::new();
+        let len = client
+            .fetch("esp32/relay-controller/files/a1b2c3d", &mut data)
+            .unwrap();
+        assert_eq!(len, firmware.len() as u64);
+        assert_eq!(data, firmware);
+
+        let stat = client.stat("esp32/relay-controller/version").unwrap();
+        assert_eq!(stat.length, 8);
+
+        assert!(matches!(
+            client.fetch("esp32/relay-controller/missing", &mut Vec::new()),
+            Err(e) if e.kind() == Some(ErrorKind::NotFound)
+        ));
+        assert!(client.list("esp32/relay-controller/version").is_err());
+
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_upload_creates_and_replaces() {
+        let firmware = firmware();
+        let (addr, server) = spawn_server(&[("files/old", b"previous contents")], None);
+
+        let mut client = Client::connect(&addr).unwrap();
+        let written = client
+            .upload("files/new", &mut firmware.as_slice(), 0o644)
+            .unwrap();
+        assert_eq!(written, firmware.len() as u64);
+        client
+            .upload("files/old", &mut &b"replaced"[..], 0o644)
+            .unwrap();
+        assert!(client
+            .upload("missing/new", &mut &b"data"[..], 0o644)
+            .is_err());
+
+        drop(client);
+        let files = server.join().unwrap();
+        assert_eq!(files["files/new"], firmware);
+        assert_eq!(files["files/old"], b"replaced");
+    }
+
+    #[test]
+    fn test_authenticated_attach() {
+        let verifier = SharedSecretVerifier::new("hunter2", "relay-1", [9; CHALLENGE_LEN as usize]);
+        let (addr, server) = spawn_server(&[("version", b"a1b2c3d")], Some(verifier.clone()));
+
+        let mut auth = SharedSecretAuth::new("hunter2", "relay-1");
+        let stream = TcpStream::connect(&addr).unwrap();
+        let mut client = Client::new(stream, Some(&mut auth)).unwrap();
+        assert_eq!(client.stat("version").unwrap().length, 7);
+        drop(client);
+        server.join().unwrap();
+
+        // without credentials the server refuses the attach
+        let (addr, server) = spawn_server(&[("version", b"a1b2c3d")], Some(verifier));
+        assert!(matches!(Client::connect(&addr), Err(Error::Auth(_))));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_rejects_tiny_msize() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut stream = listener.accept().unwrap().0;
+            let tversion = read_message(&mut stream, MSIZE, Dialect::Plan9).unwrap();
+            let rversion = Message::Rversion(Rversion {
+                msize: P9_IOHDRSZ,
+                version: "9P2000".to_string(),
+            });
+            write_message(
+                &mut stream,
+                &TaggedMessage::new(tversion.tag, rversion),
+                MSIZE,
+            )
+            .unwrap();
+        });
+
+        assert!(matches!(Client::connect(&addr), Err(Error::Protocol(_))));
+        server.join().unwrap();
+    }
+}
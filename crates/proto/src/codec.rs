@@ -0,0 +1,390 @@
+use crate::consts::P9_HEADER_SIZE;
+use crate::error::{Error, Result};
+use crate::ext::BytesMutWriteExt;
+use crate::{Dialect, Encodable, TaggedMessage};
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Largest message accepted before a `Tversion`/`Rversion` exchange has settled on an msize
+pub const DEFAULT_MSIZE: u32 = 8 * 1024 * 1024;
+
+pub struct MessageCodec {
+    dialect: Dialect,
+    msize: u32,
+}
+
+impl MessageCodec {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_dialect(Dialect::default())
+    }
+
+    #[must_use]
+    pub fn with_dialect(dialect: Dialect) -> Self {
+        Self {
+            dialect,
+            msize: DEFAULT_MSIZE,
+        }
+    }
+
+    #[must_use]
+    pub fn with_msize(mut self, msize: u32) -> Self {
+        self.set_msize(msize);
+        self
+    }
+
+    #[must_use]
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// Switch the dialect used for subsequent messages, typically after `Rversion`
+    pub fn set_dialect(&mut self, dialect: Dialect) {
+        self.dialect = dialect;
+    }
+
+    #[must_use]
+    pub fn msize(&self) -> u32 {
+        self.msize
+    }
+
+    /// Limit subsequent messages, including their size field, to `msize` bytes.
+    /// This should be set to the msize agreed upon in `Rversion`.
+    pub fn set_msize(&mut self, msize: u32) {
+        self.msize = msize;
+    }
+
+    fn check_size(&self, size: usize) -> Result<()> {
+        if size > self.msize as usize {
+            return Err(Error::Protocol(format!(
+                "message of {size} bytes exceeds msize {}",
+                self.msize
+            )));
+        }
+        if size < P9_HEADER_SIZE as usize {
+            return Err(Error::Protocol(format!(
+                "message of {size} bytes is shorter than the 9p header"
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MessageCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for MessageCodec {
+    type Item = TaggedMessage;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
+        let Some(size) = src.get(..4) else {
+            return Ok(None);
+        };
+        let size = u32::from_le_bytes([size[0], size[1], size[2], size[3]]) as usize;
+        self.check_size(size)?;
+
+        if src.len() < size {
+            src.reserve(size - src.len());
+            return Ok(None);
+        }
+        let mut frame = src.split_to(size);
+        frame.advance(4);
+        let message = TaggedMessage::decode_frame(&frame.freeze(), self.dialect)?;
+        Ok(Some(message))
+    }
+}
+
+impl Encoder<TaggedMessage> for MessageCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: TaggedMessage, dst: &mut BytesMut) -> Result<()> {
+        if !self.dialect.supports(&item.message) {
+            return Err(Error::UnsupportedMessageType(
+                item.message_type(),
+                self.dialect,
+            ));
+        }
+
+        // encode straight into `dst` and fill in the size once it is known
+        let start = dst.len();
+        dst.put_u32_le(0);
+        let result = item
+            .encode(&mut dst.write_adapter())
+            .and_then(|_| self.check_size(dst.len() - start));
+        if let Err(e) = result {
+            dst.truncate(start);
+            return Err(e);
+        }
+
+        let size = u32::try_from(dst.len() - start).expect("size was checked against msize");
+        dst[start..start + 4].copy_from_slice(&size.to_le_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::P9_MAXWELEM;
+    use crate::{Message, Rread, Rversion, Twalk, Twrite};
+    use bytes::Bytes;
+
+    fn walk(len: usize) -> TaggedMessage {
+        TaggedMessage::new(
+            1,
+            Message::Twalk(Twalk {
+                fid: 0,
+                newfid: 1,
+                wnames: (0..len).map(|i| format!("d{i}")).collect(),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_rejects_oversized_outgoing_message() {
+        let mut codec = MessageCodec::new().with_msize(64);
+        let mut buf = BytesMut::new();
+
+        let fits = TaggedMessage::new(
+            1,
+            Message::Rread(Rread {
+                data: Bytes::from(vec![0u8; 64 - 11]),
+            }),
+        );
+        codec.encode(fits, &mut buf).unwrap();
+        assert_eq!(buf.len(), 64);
+
+        let too_big = TaggedMessage::new(
+            1,
+            Message::Rread(Rread {
+                data: Bytes::from(vec![0u8; 64 - 10]),
+            }),
+        );
+        assert!(matches!(
+            codec.encode(too_big, &mut BytesMut::new()),
+            Err(Error::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_oversized_incoming_frame_from_header() {
+        let mut codec = MessageCodec::new();
+        codec.set_msize(8192);
+
+        // only the size field has arrived, but it already announces too much data
+        let mut buf = BytesMut::from(&8193u32.to_le_bytes()[..]);
+        assert!(matches!(codec.decode(&mut buf), Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_rejects_frame_shorter_than_header() {
+        let mut codec = MessageCodec::new();
+        let mut buf = BytesMut::from(&[0x06, 0x00, 0x00, 0x00, 100, 0x00][..]);
+        assert!(matches!(codec.decode(&mut buf), Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_msize_follows_negotiation() {
+        let mut codec = MessageCodec::new();
+        assert_eq!(codec.msize(), DEFAULT_MSIZE);
+
+        let rversion = TaggedMessage::new(
+            0xffff,
+            Message::Rversion(Rversion {
+                msize: 8168,
+                version: "9P2000".to_string(),
+            }),
+        );
+        let mut buf = BytesMut::new();
+        codec.encode(rversion, &mut buf).unwrap();
+
+        let Some(TaggedMessage {
+            message: Message::Rversion(rversion),
+            ..
+        }) = codec.decode(&mut buf).unwrap()
+        else {
+            panic!("expected Rversion");
+        };
+        codec.set_msize(rversion.msize);
+        assert_eq!(codec.msize(), 8168);
+
+        let mut buf = BytesMut::from(&8169u32.to_le_bytes()[..]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_walk_element_limit() {
+        let mut codec = MessageCodec::new();
+        let mut buf = BytesMut::new();
+
+        codec.encode(walk(P9_MAXWELEM), &mut buf).unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), Some(walk(P9_MAXWELEM)));
+
+        assert!(matches!(
+            codec.encode(walk(P9_MAXWELEM + 1), &mut BytesMut::new()),
+            Err(Error::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_incoming_overlong_walk() {
+        let mut encoded = BytesMut::new();
+        walk(P9_MAXWELEM + 1)
+            .message
+            .encode(&mut encoded.write_adapter())
+            .unwrap_err();
+
+        // build the frame by hand since the encoder refuses to produce it
+        let mut body = Vec::new();
+        0u32.encode(&mut body).unwrap();
+        1u32.encode(&mut body).unwrap();
+        u16::try_from(P9_MAXWELEM + 1)
+            .unwrap()
+            .encode(&mut body)
+            .unwrap();
+        for _ in 0..=P9_MAXWELEM {
+            "d".to_string().encode(&mut body).unwrap();
+        }
+
+        let size = u32::try_from(body.len() + 7).unwrap();
+        let mut frame = BytesMut::new();
+        frame.extend_from_slice(&size.to_le_bytes());
+        frame.extend_from_slice(&[110, 0x01, 0x00]);
+        frame.extend_from_slice(&body);
+
+        let mut codec = MessageCodec::new();
+        assert!(matches!(codec.decode(&mut frame), Err(Error::Protocol(_))));
+    }
+
+    /// Wrap `body` in a frame of the given message type with tag 1
+    fn frame(message_type: u8, body: &[u8]) -> BytesMut {
+        let size = u32::try_from(body.len() + 7).unwrap();
+        let mut frame = BytesMut::new();
+        frame.extend_from_slice(&size.to_le_bytes());
+        frame.extend_from_slice(&[message_type, 0x01, 0x00]);
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    fn assert_insufficient(result: &Result<Option<TaggedMessage>>) {
+        assert!(
+            matches!(result, Err(Error::InsufficientData { .. })),
+            "expected InsufficientData, got {result:?}"
+        );
+    }
+
+    #[test]
+    fn test_rread_count_larger_than_frame() {
+        // an Rread claiming 4GiB of data in a 15 byte frame used to allocate the full count
+        let mut buf = frame(117, &u32::MAX.to_le_bytes());
+        assert_insufficient(&MessageCodec::new().decode(&mut buf));
+    }
+
+    #[test]
+    fn test_twrite_count_larger_than_frame() {
+        let mut body = Vec::new();
+        body.extend_from_slice(&1u32.to_le_bytes());
+        body.extend_from_slice(&0u64.to_le_bytes());
+        body.extend_from_slice(&0xffff_fff0u32.to_le_bytes());
+        body.extend_from_slice(b"abc");
+
+        let mut buf = frame(118, &body);
+        assert_insufficient(&MessageCodec::new().decode(&mut buf));
+    }
+
+    #[test]
+    fn test_string_length_larger_than_frame() {
+        // Rerror whose ename claims 65535 bytes
+        let mut buf = frame(107, &[0xff, 0xff, b'e']);
+        assert_insufficient(&MessageCodec::new().decode(&mut buf));
+    }
+
+    #[test]
+    fn test_stat_size_larger_than_frame() {
+        // Rstat with a stat[n] that claims more than the frame holds
+        let mut buf = frame(125, &[0x02, 0x00, 0xff, 0xff, 0x00, 0x00]);
+        assert_insufficient(&MessageCodec::new().decode(&mut buf));
+    }
+
+    #[test]
+    fn test_vector_count_larger_than_frame() {
+        // an Rwalk with nwqid of 65535 and no qids used to reserve room for all of them
+        let mut buf = frame(111, &[0xff, 0xff]);
+        assert!(MessageCodec::new().decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_data_is_sliced_from_the_frame() {
+        let data = Bytes::from(vec![0xa5; 64 * 1024]);
+        let mut codec = MessageCodec::new();
+        let mut buf = BytesMut::new();
+
+        for message in [
+            Message::Rread(Rread { data: data.clone() }),
+            Message::Twrite(Twrite {
+                fid: 3,
+                offset: 4096,
+                data: data.clone(),
+            }),
+        ] {
+            codec
+                .encode(TaggedMessage::new(1, message.clone()), &mut buf)
+                .unwrap();
+            let received = buf.as_ptr_range();
+            let decoded = codec.decode(&mut buf).unwrap().unwrap().message;
+            assert_eq!(decoded, message);
+
+            let (Message::Rread(Rread { data: decoded })
+            | Message::Twrite(Twrite { data: decoded, .. })) = decoded
+            else {
+                unreachable!()
+            };
+            assert!(received.contains(&decoded.as_ptr()), "data was copied");
+        }
+    }
+
+    #[test]
+    fn test_encode_appends_to_buffered_frames() {
+        let mut codec = MessageCodec::new();
+        let mut buf = BytesMut::new();
+        let messages = [walk(2), walk(0), walk(P9_MAXWELEM)];
+
+        for message in &messages {
+            codec.encode(message.clone(), &mut buf).unwrap();
+        }
+        // a failed encode leaves the frames before it intact
+        assert!(codec.encode(walk(P9_MAXWELEM + 1), &mut buf).is_err());
+
+        for message in messages {
+            assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), message);
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_frames_arriving_in_pieces() {
+        let mut codec = MessageCodec::new();
+        let mut encoded = BytesMut::new();
+        let messages = [walk(3), walk(1)];
+        for message in &messages {
+            codec.encode(message.clone(), &mut encoded).unwrap();
+        }
+
+        // feed the frames a few bytes at a time, so that some pieces start partway
+        // through a body
+        let mut buf = BytesMut::new();
+        let mut decoded = Vec::new();
+        for piece in encoded.chunks(5) {
+            buf.extend_from_slice(piece);
+            while let Some(message) = codec.decode(&mut buf).unwrap() {
+                decoded.push(message);
+            }
+        }
+        assert_eq!(decoded, messages);
+        assert!(buf.is_empty());
+    }
+}
@@ -1 +1,32 @@
 pub const P9_NOFID: u32 = !0;
+
+/// Maximum number of path elements in a single `Twalk`/`Rwalk`
+pub const P9_MAXWELEM: usize = 16;
+
+/// Size of the `size[4] type[1] tag[2]` header that starts every message
+pub const P9_HEADER_SIZE: u32 = 7;
+
+/// Room to leave for the header of an `Rread`/`Twrite` when sizing its data to fit msize
+pub const P9_IOHDRSZ: u32 = 24;
+
+/// `n_uname` value sent in 9P2000.L `Tauth`/`Tattach` when no numeric uid is given
+pub const P9_NONUNAME: u32 = !0;
+
+/// `Tgetattr` request mask covering the fields of a classic `stat(2)`
+pub const P9_GETATTR_BASIC: u64 = 0x0000_07ff;
+/// `Tgetattr` request mask covering every field of `Rgetattr`
+pub const P9_GETATTR_ALL: u64 = 0x0000_3fff;
+
+/// `Tlock`/`Rgetlock` lock types
+pub const P9_LOCK_TYPE_RDLCK: u8 = 0;
+pub const P9_LOCK_TYPE_WRLCK: u8 = 1;
+pub const P9_LOCK_TYPE_UNLCK: u8 = 2;
+
+/// `Rlock` status values
+pub const P9_LOCK_SUCCESS: u8 = 0;
+pub const P9_LOCK_BLOCKED: u8 = 1;
+pub const P9_LOCK_ERROR: u8 = 2;
+pub const P9_LOCK_GRACE: u8 = 3;
+
+/// `Tunlinkat` flag requesting removal of a directory
+pub const P9_AT_REMOVEDIR: u32 = 0x200;
@@ -0,0 +1,265 @@
+//! Directory contents as carried in `Rread` and `Rreaddir` payloads.
+//!
+//! Reading a directory in 9P2000 returns a run of whole `Stat` records; 9P2000.L's
+//! `Treaddir` returns a run of [`Dirent`] records instead. Servers must never split
+//! a record across two reads, and a client may only continue reading from the offset
+//! where the previous read ended.
+use crate::error::{Error, Result};
+use crate::wire::{WireRead, WireWrite};
+use crate::{Decodable, Encodable, Qid, Stat};
+use alloc::{format, string::String, vec::Vec};
+use bytes::Bytes;
+
+/// Decode the `Stat` entries contained in the data of an `Rread` on a directory
+/// # Errors
+/// - the buffer ends partway through an entry
+/// - an entry is malformed
+pub fn decode_dir(data: &[u8]) -> Result<Vec<Stat>> {
+    decode_all(data)
+}
+
+/// Encode the part of a directory listing that answers a `Tread` of `count` bytes at `offset`
+///
+/// `offset` is measured in bytes of the complete encoded listing and must fall on an
+/// entry boundary. As many whole entries as fit into `count` are returned; an empty
+/// result means the end of the directory was reached.
+/// # Errors
+/// - `offset` does not fall on an entry boundary
+/// - `count` is too small to hold the next entry
+/// - an entry cannot be encoded
+pub fn encode_dir(entries: &[Stat], offset: u64, count: u32) -> Result<Bytes> {
+    let mut position = 0u64;
+    let mut data = Vec::new();
+
+    for entry in entries {
+        let mut encoded = Vec::new();
+        let len = entry.encode(&mut encoded)? as u64;
+
+        if position < offset {
+            position += len;
+            if position > offset {
+                return Err(Error::Protocol(format!(
+                    "directory read offset {offset} is not on an entry boundary"
+                )));
+            }
+            continue;
+        }
+
+        if data.len() + encoded.len() > count as usize {
+            if data.is_empty() {
+                return Err(Error::Protocol(format!(
+                    "read count {count} is too small for a {len} byte directory entry"
+                )));
+            }
+            break;
+        }
+        data.extend_from_slice(&encoded);
+        position += len;
+    }
+
+    if position < offset {
+        return Err(Error::Protocol(format!(
+            "directory read offset {offset} is past the end of the directory"
+        )));
+    }
+
+    Ok(Bytes::from(data))
+}
+
+/// A 9P2000.L directory entry as returned by `Treaddir`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dirent {
+    pub qid: Qid,
+    /// Offset to pass in the next `Treaddir` to continue after this entry
+    pub offset: u64,
+    /// File type as in the `d_type` field of `struct dirent`
+    pub r#type: u8,
+    pub name: String,
+}
+
+impl Encodable for Dirent {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+        let mut bytes_written = 0;
+        bytes_written += self.qid.encode(w)?;
+        bytes_written += self.offset.encode(w)?;
+        bytes_written += self.r#type.encode(w)?;
+        bytes_written += self.name.encode(w)?;
+        Ok(bytes_written)
+    }
+}
+
+impl Decodable for Dirent {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        Ok(Dirent {
+            qid: Qid::decode(r)?,
+            offset: u64::decode(r)?,
+            r#type: u8::decode(r)?,
+            name: String::decode(r)?,
+        })
+    }
+}
+
+/// Decode the entries contained in the data of an `Rreaddir`
+/// # Errors
+/// - the buffer ends partway through an entry
+/// - an entry is malformed
+pub fn decode_dirents(data: &[u8]) -> Result<Vec<Dirent>> {
+    decode_all(data)
+}
+
+/// Encode the entries that answer a `Treaddir` of `count` bytes at `offset`
+///
+/// Entries are expected in directory order with increasing `offset` values; those at
+/// or before the requested `offset` are skipped. An empty result marks the end of the
+/// directory.
+/// # Errors
+/// - `count` is too small to hold the next entry
+/// - an entry cannot be encoded
+pub fn encode_dirents(entries: &[Dirent], offset: u64, count: u32) -> Result<Bytes> {
+    let mut data = Vec::new();
+
+    for entry in entries.iter().filter(|entry| entry.offset > offset) {
+        let mut encoded = Vec::new();
+        let len = entry.encode(&mut encoded)?;
+
+        if data.len() + len > count as usize {
+            if data.is_empty() {
+                return Err(Error::Protocol(format!(
+                    "readdir count {count} is too small for a {len} byte directory entry"
+                )));
+            }
+            break;
+        }
+        data.extend_from_slice(&encoded);
+    }
+
+    Ok(Bytes::from(data))
+}
+
+fn decode_all<T: Decodable>(data: &[u8]) -> Result<Vec<T>> {
+    let mut cursor = data;
+    let mut entries = Vec::new();
+
+    while !cursor.is_empty() {
+        entries.push(T::decode(&mut cursor)?);
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileMode, QidType};
+    use flagset::FlagSet;
+
+    fn stat(name: &str, is_dir: bool) -> Stat {
+        let (qtype, mode): (FlagSet<QidType>, FlagSet<FileMode>) = if is_dir {
+            (QidType::Dir.into(), FileMode::from_unix_perm(0o755, true))
+        } else {
+            (QidType::File.into(), FileMode::from_unix_perm(0o644, false))
+        };
+
+        Stat {
+            r#type: 0,
+            dev: 0,
+            qid: Qid {
+                qtype,
+                version: 0,
+                path: name.len() as u64,
+            },
+            mode,
+            atime: 1_750_000_000,
+            mtime: 1_750_000_000,
+            length: 0,
+            name: name.to_string(),
+            uid: "nobody".to_string(),
+            gid: "nobody".to_string(),
+            muid: String::new(),
+        }
+    }
+
+    fn listing() -> Vec<Stat> {
+        vec![
+            stat("a1b2c3d", false),
+            stat("e4f5a6b", false),
+            stat("old", true),
+        ]
+    }
+
+    fn entry_len(entry: &Stat) -> u64 {
+        entry.encode(&mut Vec::new()).unwrap() as u64
+    }
+
+    #[test]
+    fn test_decode_dir_round_trip() {
+        let entries = listing();
+        let data = encode_dir(&entries, 0, 8192).unwrap();
+
+        assert_eq!(decode_dir(&data).unwrap(), entries);
+        assert!(decode_dir(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decode_dir_truncated_entry() {
+        let data = encode_dir(&listing(), 0, 8192).unwrap();
+        assert!(decode_dir(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_encode_dir_respects_count_and_offset() {
+        let entries = listing();
+        let first = entry_len(&entries[0]);
+        let second = entry_len(&entries[1]);
+
+        // only the first entry fits
+        let count = u32::try_from(first + second - 1).unwrap();
+        let page = encode_dir(&entries, 0, count).unwrap();
+        assert_eq!(decode_dir(&page).unwrap(), entries[..1]);
+
+        // continuing where the previous read stopped
+        let page = encode_dir(&entries, page.len() as u64, 8192).unwrap();
+        assert_eq!(decode_dir(&page).unwrap(), entries[1..]);
+
+        // reading at the end yields nothing
+        let end: u64 = entries.iter().map(entry_len).sum();
+        assert!(encode_dir(&entries, end, 8192).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_encode_dir_rejects_bad_requests() {
+        let entries = listing();
+        let first = entry_len(&entries[0]);
+
+        assert!(encode_dir(&entries, first - 1, 8192).is_err());
+        assert!(encode_dir(&entries, 100_000, 8192).is_err());
+        assert!(encode_dir(&entries, 0, u32::try_from(first - 1).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_dirents_round_trip() {
+        let entries: Vec<Dirent> = ["files", "version", "manifest"]
+            .into_iter()
+            .zip(1..)
+            .map(|(name, offset)| Dirent {
+                qid: Qid {
+                    qtype: QidType::File.into(),
+                    version: 0,
+                    path: offset,
+                },
+                offset,
+                r#type: 8,
+                name: name.to_string(),
+            })
+            .collect();
+
+        let data = encode_dirents(&entries, 0, 8192).unwrap();
+        assert_eq!(decode_dirents(&data).unwrap(), entries);
+
+        let rest = encode_dirents(&entries, 1, 8192).unwrap();
+        assert_eq!(decode_dirents(&rest).unwrap(), entries[1..]);
+
+        assert!(encode_dirents(&entries, 3, 8192).unwrap().is_empty());
+        assert!(encode_dirents(&entries, 0, 10).is_err());
+    }
+}
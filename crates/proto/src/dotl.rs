@@ -0,0 +1,929 @@
+//! Message bodies that only exist in the 9P2000.L dialect.
+//!
+//! Type numbers and wire layouts follow the Linux v9fs client and diod's
+//! `protocol.md`. 9P2000.L reuses the base `Tversion`, `Tflush`, `Twalk`, `Tread`,
+//! `Twrite`, `Tclunk` and `Tremove` messages unchanged, while `Tauth` and `Tattach`
+//! gain a trailing numeric uid and are represented here by [`Tlauth`] and [`Tlattach`].
+use crate::error::Result;
+use crate::wire::{WireRead, WireWrite};
+use crate::{Decodable, Encodable, Qid};
+use alloc::string::String;
+use bytes::Bytes;
+
+/// 9P2000.L form of `Tauth`, carrying the numeric uid of the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tlauth {
+    pub afid: u32,
+    pub uname: String,
+    pub aname: String,
+    pub n_uname: u32,
+}
+
+/// 9P2000.L form of `Tattach`, carrying the numeric uid of the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tlattach {
+    pub fid: u32,
+    pub afid: u32,
+    pub uname: String,
+    pub aname: String,
+    pub n_uname: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rlerror {
+    pub ecode: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tstatfs {
+    pub fid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rstatfs {
+    pub r#type: u32,
+    pub bsize: u32,
+    pub blocks: u64,
+    pub bfree: u64,
+    pub bavail: u64,
+    pub files: u64,
+    pub ffree: u64,
+    pub fsid: u64,
+    pub namelen: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tlopen {
+    pub fid: u32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rlopen {
+    pub qid: Qid,
+    pub iounit: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tlcreate {
+    pub fid: u32,
+    pub name: String,
+    pub flags: u32,
+    pub mode: u32,
+    pub gid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rlcreate {
+    pub qid: Qid,
+    pub iounit: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tsymlink {
+    pub fid: u32,
+    pub name: String,
+    pub symtgt: String,
+    pub gid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rsymlink {
+    pub qid: Qid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tmknod {
+    pub dfid: u32,
+    pub name: String,
+    pub mode: u32,
+    pub major: u32,
+    pub minor: u32,
+    pub gid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmknod {
+    pub qid: Qid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trename {
+    pub fid: u32,
+    pub dfid: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rrename;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Treadlink {
+    pub fid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rreadlink {
+    pub target: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tgetattr {
+    pub fid: u32,
+    pub request_mask: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rgetattr {
+    pub valid: u64,
+    pub qid: Qid,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u64,
+    pub rdev: u64,
+    pub size: u64,
+    pub blksize: u64,
+    pub blocks: u64,
+    pub atime_sec: u64,
+    pub atime_nsec: u64,
+    pub mtime_sec: u64,
+    pub mtime_nsec: u64,
+    pub ctime_sec: u64,
+    pub ctime_nsec: u64,
+    pub btime_sec: u64,
+    pub btime_nsec: u64,
+    pub gen: u64,
+    pub data_version: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tsetattr {
+    pub fid: u32,
+    pub valid: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub atime_sec: u64,
+    pub atime_nsec: u64,
+    pub mtime_sec: u64,
+    pub mtime_nsec: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rsetattr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Txattrwalk {
+    pub fid: u32,
+    pub newfid: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rxattrwalk {
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Txattrcreate {
+    pub fid: u32,
+    pub name: String,
+    pub attr_size: u64,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rxattrcreate;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Treaddir {
+    pub fid: u32,
+    pub offset: u64,
+    pub count: u32,
+}
+
+/// Directory entries in the 9P2000.L `dirent` encoding
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rreaddir {
+    pub data: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tfsync {
+    pub fid: u32,
+    pub datasync: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rfsync;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tlock {
+    pub fid: u32,
+    pub r#type: u8,
+    pub flags: u32,
+    pub start: u64,
+    pub length: u64,
+    pub proc_id: u32,
+    pub client_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rlock {
+    pub status: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tgetlock {
+    pub fid: u32,
+    pub r#type: u8,
+    pub start: u64,
+    pub length: u64,
+    pub proc_id: u32,
+    pub client_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rgetlock {
+    pub r#type: u8,
+    pub start: u64,
+    pub length: u64,
+    pub proc_id: u32,
+    pub client_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tlink {
+    pub dfid: u32,
+    pub fid: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rlink;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tmkdir {
+    pub dfid: u32,
+    pub name: String,
+    pub mode: u32,
+    pub gid: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmkdir {
+    pub qid: Qid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trenameat {
+    pub olddirfid: u32,
+    pub oldname: String,
+    pub newdirfid: u32,
+    pub newname: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rrenameat;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tunlinkat {
+    pub dirfid: u32,
+    pub name: String,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Runlinkat;
+
+/// Implements `Encodable` and `Decodable` for a struct by visiting its fields in wire order
+macro_rules! wire_struct {
+    ($name:ident { $($field:ident: $ty:ty),* $(,)? }) => {
+        impl Encodable for $name {
+            fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+                let mut bytes_written = 0;
+                $(bytes_written += self.$field.encode(w)?;)*
+                Ok(bytes_written)
+            }
+        }
+
+        impl Decodable for $name {
+            fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+                Ok($name {
+                    $($field: <$ty>::decode(r)?,)*
+                })
+            }
+        }
+    };
+    ($name:ident) => {
+        impl Encodable for $name {
+            fn encode<W: WireWrite>(&self, _w: &mut W) -> Result<usize> {
+                Ok(0)
+            }
+        }
+
+        impl Decodable for $name {
+            fn decode<R: WireRead>(_r: &mut R) -> Result<Self> {
+                Ok($name)
+            }
+        }
+    };
+}
+
+wire_struct!(Tlauth {
+    afid: u32,
+    uname: String,
+    aname: String,
+    n_uname: u32
+});
+wire_struct!(Tlattach {
+    fid: u32,
+    afid: u32,
+    uname: String,
+    aname: String,
+    n_uname: u32
+});
+wire_struct!(Rlerror { ecode: u32 });
+wire_struct!(Tstatfs { fid: u32 });
+wire_struct!(Rstatfs {
+    r#type: u32,
+    bsize: u32,
+    blocks: u64,
+    bfree: u64,
+    bavail: u64,
+    files: u64,
+    ffree: u64,
+    fsid: u64,
+    namelen: u32,
+});
+wire_struct!(Tlopen {
+    fid: u32,
+    flags: u32
+});
+wire_struct!(Rlopen {
+    qid: Qid,
+    iounit: u32
+});
+wire_struct!(Tlcreate {
+    fid: u32,
+    name: String,
+    flags: u32,
+    mode: u32,
+    gid: u32
+});
+wire_struct!(Rlcreate {
+    qid: Qid,
+    iounit: u32
+});
+wire_struct!(Tsymlink {
+    fid: u32,
+    name: String,
+    symtgt: String,
+    gid: u32
+});
+wire_struct!(Rsymlink { qid: Qid });
+wire_struct!(Tmknod {
+    dfid: u32,
+    name: String,
+    mode: u32,
+    major: u32,
+    minor: u32,
+    gid: u32
+});
+wire_struct!(Rmknod { qid: Qid });
+wire_struct!(Trename {
+    fid: u32,
+    dfid: u32,
+    name: String
+});
+wire_struct!(Rrename);
+wire_struct!(Treadlink { fid: u32 });
+wire_struct!(Rreadlink { target: String });
+wire_struct!(Tgetattr {
+    fid: u32,
+    request_mask: u64
+});
+wire_struct!(Rgetattr {
+    valid: u64,
+    qid: Qid,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    nlink: u64,
+    rdev: u64,
+    size: u64,
+    blksize: u64,
+    blocks: u64,
+    atime_sec: u64,
+    atime_nsec: u64,
+    mtime_sec: u64,
+    mtime_nsec: u64,
+    ctime_sec: u64,
+    ctime_nsec: u64,
+    btime_sec: u64,
+    btime_nsec: u64,
+    gen: u64,
+    data_version: u64,
+});
+wire_struct!(Tsetattr {
+    fid: u32,
+    valid: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    atime_sec: u64,
+    atime_nsec: u64,
+    mtime_sec: u64,
+    mtime_nsec: u64,
+});
+wire_struct!(Rsetattr);
+wire_struct!(Txattrwalk {
+    fid: u32,
+    newfid: u32,
+    name: String
+});
+wire_struct!(Rxattrwalk { size: u64 });
+wire_struct!(Txattrcreate {
+    fid: u32,
+    name: String,
+    attr_size: u64,
+    flags: u32
+});
+wire_struct!(Rxattrcreate);
+wire_struct!(Treaddir {
+    fid: u32,
+    offset: u64,
+    count: u32
+});
+wire_struct!(Rreaddir { data: Bytes });
+wire_struct!(Tfsync {
+    fid: u32,
+    datasync: u32
+});
+wire_struct!(Rfsync);
+wire_struct!(Tlock {
+    fid: u32,
+    r#type: u8,
+    flags: u32,
+    start: u64,
+    length: u64,
+    proc_id: u32,
+    client_id: String,
+});
+wire_struct!(Rlock { status: u8 });
+wire_struct!(Tgetlock {
+    fid: u32,
+    r#type: u8,
+    start: u64,
+    length: u64,
+    proc_id: u32,
+    client_id: String,
+});
+wire_struct!(Rgetlock {
+    r#type: u8,
+    start: u64,
+    length: u64,
+    proc_id: u32,
+    client_id: String,
+});
+wire_struct!(Tlink {
+    dfid: u32,
+    fid: u32,
+    name: String
+});
+wire_struct!(Rlink);
+wire_struct!(Tmkdir {
+    dfid: u32,
+    name: String,
+    mode: u32,
+    gid: u32
+});
+wire_struct!(Rmkdir { qid: Qid });
+wire_struct!(Trenameat {
+    olddirfid: u32,
+    oldname: String,
+    newdirfid: u32,
+    newname: String
+});
+wire_struct!(Rrenameat);
+wire_struct!(Tunlinkat {
+    dirfid: u32,
+    name: String,
+    flags: u32
+});
+wire_struct!(Runlinkat);
+
+#[cfg(all(test, feature = "codec"))]
+mod tests {
+    use crate::consts::{P9_GETATTR_BASIC, P9_NOFID, P9_NONUNAME};
+    use crate::error::Error;
+    use crate::{
+        Dialect, Message, MessageCodec, MessageType, QidType, TaggedMessage, Tattach, Tversion,
+    };
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    use super::*;
+
+    fn encode(codec: &mut MessageCodec, message: TaggedMessage) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        codec.encode(message, &mut buf).unwrap();
+        buf.to_vec()
+    }
+
+    fn decode(codec: &mut MessageCodec, bytes: &[u8]) -> crate::error::Result<TaggedMessage> {
+        let mut buf = BytesMut::from(bytes);
+        let message = codec.decode(&mut buf)?.expect("complete frame");
+        assert!(buf.is_empty());
+        Ok(message)
+    }
+
+    fn file_qid(path: u64) -> Qid {
+        Qid {
+            qtype: QidType::File.into(),
+            version: 0,
+            path,
+        }
+    }
+
+    #[test]
+    fn test_tlopen_bytes() {
+        let mut codec = MessageCodec::with_dialect(Dialect::Linux);
+        let message = TaggedMessage::new(1, Message::Tlopen(Tlopen { fid: 2, flags: 0o2 }));
+        let expected = [
+            0x0f, 0x00, 0x00, 0x00, // size
+            12,   // Tlopen
+            0x01, 0x00, // tag
+            0x02, 0x00, 0x00, 0x00, // fid
+            0x02, 0x00, 0x00, 0x00, // flags
+        ];
+
+        assert_eq!(encode(&mut codec, message.clone()), expected);
+        assert_eq!(decode(&mut codec, &expected).unwrap(), message);
+    }
+
+    #[test]
+    fn test_rlerror_bytes() {
+        let mut codec = MessageCodec::with_dialect(Dialect::Linux);
+        let message = TaggedMessage::new(7, Message::Rlerror(Rlerror { ecode: 2 }));
+        let expected = [
+            0x0b, 0x00, 0x00, 0x00, // size
+            7,    // Rlerror
+            0x07, 0x00, // tag
+            0x02, 0x00, 0x00, 0x00, // ecode (ENOENT)
+        ];
+
+        assert_eq!(encode(&mut codec, message.clone()), expected);
+        assert_eq!(decode(&mut codec, &expected).unwrap(), message);
+    }
+
+    #[test]
+    fn test_tgetattr_bytes() {
+        let mut codec = MessageCodec::with_dialect(Dialect::Linux);
+        let message = TaggedMessage::new(
+            3,
+            Message::Tgetattr(Tgetattr {
+                fid: 1,
+                request_mask: P9_GETATTR_BASIC,
+            }),
+        );
+        let expected = [
+            0x13, 0x00, 0x00, 0x00, // size
+            24,   // Tgetattr
+            0x03, 0x00, // tag
+            0x01, 0x00, 0x00, 0x00, // fid
+            0xff, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // request_mask
+        ];
+
+        assert_eq!(encode(&mut codec, message.clone()), expected);
+        assert_eq!(decode(&mut codec, &expected).unwrap(), message);
+    }
+
+    #[test]
+    fn test_tmkdir_bytes() {
+        let mut codec = MessageCodec::with_dialect(Dialect::Linux);
+        let message = TaggedMessage::new(
+            4,
+            Message::Tmkdir(Tmkdir {
+                dfid: 1,
+                name: "logs".to_string(),
+                mode: 0o755,
+                gid: 100,
+            }),
+        );
+        let expected = [
+            0x19, 0x00, 0x00, 0x00, // size
+            72,   // Tmkdir
+            0x04, 0x00, // tag
+            0x01, 0x00, 0x00, 0x00, // dfid
+            0x04, 0x00, b'l', b'o', b'g', b's', // name
+            0xed, 0x01, 0x00, 0x00, // mode
+            0x64, 0x00, 0x00, 0x00, // gid
+        ];
+
+        assert_eq!(encode(&mut codec, message.clone()), expected);
+        assert_eq!(decode(&mut codec, &expected).unwrap(), message);
+    }
+
+    #[test]
+    fn test_tattach_depends_on_dialect() {
+        let dotl_bytes = [
+            0x1b, 0x00, 0x00, 0x00, // size
+            104,  // Tattach
+            0x01, 0x00, // tag
+            0x00, 0x00, 0x00, 0x00, // fid
+            0xff, 0xff, 0xff, 0xff, // afid
+            0x04, 0x00, b'r', b'o', b'o', b't', // uname
+            0x00, 0x00, // aname
+            0xe8, 0x03, 0x00, 0x00, // n_uname
+        ];
+
+        let mut codec = MessageCodec::with_dialect(Dialect::Linux);
+        let message = decode(&mut codec, &dotl_bytes).unwrap();
+        assert_eq!(message.message_type(), MessageType::Tattach);
+        assert_eq!(
+            message.message,
+            Message::Tlattach(Tlattach {
+                fid: 0,
+                afid: P9_NOFID,
+                uname: "root".to_string(),
+                aname: String::new(),
+                n_uname: 1000,
+            })
+        );
+        assert_eq!(encode(&mut codec, message), dotl_bytes);
+
+        // the plain 9P2000 form shares the message type but has no n_uname, so only the
+        // dialect of the codec tells the two apart
+        let plan9 = TaggedMessage::new(
+            1,
+            Message::Tattach(Tattach {
+                fid: 0,
+                afid: P9_NOFID,
+                uname: "root".to_string(),
+                aname: String::new(),
+            }),
+        );
+        let plan9_bytes = encode(&mut MessageCodec::new(), plan9.clone());
+        assert_eq!(plan9_bytes.len(), dotl_bytes.len() - 4);
+        assert_eq!(
+            decode(&mut MessageCodec::new(), &plan9_bytes).unwrap(),
+            plan9
+        );
+    }
+
+    #[test]
+    fn test_plan9_codec_rejects_dotl_messages() {
+        let tlopen = [
+            0x0f, 0x00, 0x00, 0x00, 12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut codec = MessageCodec::new();
+        assert!(matches!(
+            decode(&mut codec, &tlopen),
+            Err(Error::UnsupportedMessageType(
+                MessageType::Tlopen,
+                Dialect::Plan9
+            ))
+        ));
+
+        let attach = TaggedMessage::new(
+            1,
+            Message::Tlattach(Tlattach {
+                fid: 0,
+                afid: P9_NOFID,
+                uname: "root".to_string(),
+                aname: String::new(),
+                n_uname: P9_NONUNAME,
+            }),
+        );
+        let mut buf = BytesMut::new();
+        assert!(matches!(
+            codec.encode(attach, &mut buf),
+            Err(Error::UnsupportedMessageType(
+                MessageType::Tattach,
+                Dialect::Plan9
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_dialect_selected_after_version_negotiation() {
+        let mut codec = MessageCodec::new();
+        let rversion = encode(
+            &mut codec,
+            TaggedMessage::new(
+                0xffff,
+                Message::Rversion(crate::Rversion {
+                    msize: 8192,
+                    version: "9P2000.L".to_string(),
+                }),
+            ),
+        );
+
+        let Message::Rversion(rversion) = decode(&mut codec, &rversion).unwrap().message else {
+            panic!("expected Rversion");
+        };
+        codec.set_dialect(Dialect::from_version(&rversion.version).unwrap());
+        assert_eq!(codec.dialect(), Dialect::Linux);
+        assert_eq!(codec.dialect().version(), "9P2000.L");
+
+        let tversion = TaggedMessage::new(
+            0xffff,
+            Message::Tversion(Tversion {
+                msize: 8192,
+                version: Dialect::Linux.version().to_string(),
+            }),
+        );
+        let bytes = encode(&mut codec, tversion.clone());
+        assert_eq!(decode(&mut codec, &bytes).unwrap(), tversion);
+    }
+
+    fn assert_round_trip(messages: Vec<Message>) {
+        let mut codec = MessageCodec::with_dialect(Dialect::Linux);
+        for (tag, message) in (1..).zip(messages) {
+            let tagged = TaggedMessage::new(tag, message);
+            let bytes = encode(&mut codec, tagged.clone());
+            assert_eq!(decode(&mut codec, &bytes).unwrap(), tagged, "{tagged}");
+        }
+    }
+
+    #[test]
+    fn test_dotl_round_trip_files() {
+        assert_round_trip(vec![
+            Message::Tlauth(Tlauth {
+                afid: 5,
+                uname: "nobody".to_string(),
+                aname: String::new(),
+                n_uname: P9_NONUNAME,
+            }),
+            Message::Tstatfs(Tstatfs { fid: 1 }),
+            Message::Rstatfs(Rstatfs {
+                r#type: 0x0102_1994,
+                bsize: 4096,
+                blocks: 1000,
+                bfree: 500,
+                bavail: 400,
+                files: 64,
+                ffree: 32,
+                fsid: 7,
+                namelen: 255,
+            }),
+            Message::Rlopen(Rlopen {
+                qid: file_qid(9),
+                iounit: 8168,
+            }),
+            Message::Tlcreate(Tlcreate {
+                fid: 1,
+                name: "status".to_string(),
+                flags: 0o1101,
+                mode: 0o644,
+                gid: 0,
+            }),
+            Message::Rlcreate(Rlcreate {
+                qid: file_qid(10),
+                iounit: 0,
+            }),
+            Message::Tsymlink(Tsymlink {
+                fid: 1,
+                name: "latest".to_string(),
+                symtgt: "files/abc123".to_string(),
+                gid: 0,
+            }),
+            Message::Rsymlink(Rsymlink { qid: file_qid(11) }),
+            Message::Tmknod(Tmknod {
+                dfid: 1,
+                name: "fifo".to_string(),
+                mode: 0o010_644,
+                major: 0,
+                minor: 0,
+                gid: 0,
+            }),
+            Message::Rmknod(Rmknod { qid: file_qid(12) }),
+            Message::Trename(Trename {
+                fid: 2,
+                dfid: 1,
+                name: "new".to_string(),
+            }),
+            Message::Rrename(Rrename),
+            Message::Treadlink(Treadlink { fid: 2 }),
+            Message::Rreadlink(Rreadlink {
+                target: "files/abc123".to_string(),
+            }),
+            Message::Tlink(Tlink {
+                dfid: 1,
+                fid: 2,
+                name: "hardlink".to_string(),
+            }),
+            Message::Rlink(Rlink),
+            Message::Rmkdir(Rmkdir { qid: file_qid(14) }),
+            Message::Trenameat(Trenameat {
+                olddirfid: 1,
+                oldname: "a".to_string(),
+                newdirfid: 1,
+                newname: "b".to_string(),
+            }),
+            Message::Rrenameat(Rrenameat),
+            Message::Tunlinkat(Tunlinkat {
+                dirfid: 1,
+                name: "b".to_string(),
+                flags: 0,
+            }),
+            Message::Runlinkat(Runlinkat),
+            Message::Treaddir(Treaddir {
+                fid: 1,
+                offset: 0,
+                count: 8168,
+            }),
+            Message::Rreaddir(Rreaddir {
+                data: Bytes::from_static(&[1, 2, 3]),
+            }),
+            Message::Tfsync(Tfsync {
+                fid: 1,
+                datasync: 1,
+            }),
+            Message::Rfsync(Rfsync),
+        ]);
+    }
+
+    #[test]
+    fn test_dotl_round_trip_attrs_and_locks() {
+        assert_round_trip(vec![
+            Message::Rgetattr(Rgetattr {
+                valid: P9_GETATTR_BASIC,
+                qid: file_qid(13),
+                mode: 0o100_644,
+                uid: 1000,
+                gid: 100,
+                nlink: 1,
+                rdev: 0,
+                size: 1_234_567,
+                blksize: 4096,
+                blocks: 2412,
+                atime_sec: 1_750_000_000,
+                atime_nsec: 1,
+                mtime_sec: 1_750_000_001,
+                mtime_nsec: 2,
+                ctime_sec: 1_750_000_002,
+                ctime_nsec: 3,
+                btime_sec: 0,
+                btime_nsec: 0,
+                gen: 0,
+                data_version: 0,
+            }),
+            Message::Tsetattr(Tsetattr {
+                fid: 2,
+                valid: 0x8,
+                mode: 0,
+                uid: 0,
+                gid: 0,
+                size: 0,
+                atime_sec: 0,
+                atime_nsec: 0,
+                mtime_sec: 0,
+                mtime_nsec: 0,
+            }),
+            Message::Rsetattr(Rsetattr),
+            Message::Txattrwalk(Txattrwalk {
+                fid: 1,
+                newfid: 2,
+                name: "user.comment".to_string(),
+            }),
+            Message::Rxattrwalk(Rxattrwalk { size: 12 }),
+            Message::Txattrcreate(Txattrcreate {
+                fid: 2,
+                name: "user.comment".to_string(),
+                attr_size: 12,
+                flags: 0,
+            }),
+            Message::Rxattrcreate(Rxattrcreate),
+            Message::Tlock(Tlock {
+                fid: 1,
+                r#type: 1,
+                flags: 0,
+                start: 0,
+                length: 0,
+                proc_id: 42,
+                client_id: "relay".to_string(),
+            }),
+            Message::Rlock(Rlock { status: 0 }),
+            Message::Tgetlock(Tgetlock {
+                fid: 1,
+                r#type: 0,
+                start: 0,
+                length: 10,
+                proc_id: 42,
+                client_id: "relay".to_string(),
+            }),
+            Message::Rgetlock(Rgetlock {
+                r#type: 2,
+                start: 0,
+                length: 10,
+                proc_id: 42,
+                client_id: "relay".to_string(),
+            }),
+        ]);
+    }
+}
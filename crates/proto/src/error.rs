@@ -1,16 +1,25 @@
+use crate::{Dialect, MessageType, Rerror, Rlerror};
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
+use std::io;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error(transparent)]
-    FlagsetInvalidBits(#[from] flagset::InvalidBits),
+    #[error("{0}")]
+    FlagsetInvalidBits(flagset::InvalidBits),
 
+    #[cfg(feature = "std")]
     #[error("io: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("invalid message type: {0}")]
     InvalidMessageType(u8),
 
+    #[error("message type {0:?} is not part of the {1:?} dialect")]
+    UnsupportedMessageType(MessageType, Dialect),
+
     #[error("invalid UTF-8 string")]
-    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    InvalidUtf8(#[from] alloc::string::FromUtf8Error),
 
     #[error("string too long: {0} bytes")]
     StringTooLong(usize),
@@ -26,6 +35,340 @@ pub enum Error {
 
     #[error("Protocol error: {0}")]
     Protocol(String),
+
+    #[error("server: {ename}")]
+    Server { kind: ErrorKind, ename: String },
+
+    #[error("authentication failed: {0}")]
+    Auth(String),
+
+    #[error("TLS: {0}")]
+    Tls(String),
+
+    #[error("Invalid trace: {0}")]
+    Trace(String),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl From<flagset::InvalidBits> for Error {
+    fn from(err: flagset::InvalidBits) -> Self {
+        Error::FlagsetInvalidBits(err)
+    }
+}
+
+impl Error {
+    /// The classification of an error reported by the server, if this is one
+    #[must_use]
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::Server { kind, .. } => Some(*kind),
+            _ => None,
+        }
+    }
+}
+
+impl From<Rerror> for Error {
+    fn from(rerror: Rerror) -> Self {
+        Error::Server {
+            kind: rerror.kind(),
+            ename: rerror.ename,
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::Server {
+            kind,
+            ename: kind.ename().to_string(),
+        }
+    }
+}
+
+/// What went wrong according to an `Rerror` or `Rlerror`.
+///
+/// Servers word their errors differently: Plan 9 file servers send strings such as
+/// "file does not exist", Linux-backed ones pass along `strerror` text or, in
+/// 9P2000.L and 9P2000.u, an errno. Clients classify whatever they receive with
+/// [`ErrorKind::from_ename`] or [`ErrorKind::from_errno`]; servers answer with
+/// [`ErrorKind::ename`] or [`ErrorKind::errno`] so that other clients recognize them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    NotFound,
+    PermissionDenied,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    DirectoryNotEmpty,
+    InvalidArgument,
+    /// The fid in the request is not in use, or is already in use for a new fid
+    BadFid,
+    StorageFull,
+    ReadOnly,
+    AuthRequired,
+    Interrupted,
+    TimedOut,
+    Unsupported,
+    Io,
+    /// Anything that isn't recognized
+    Other,
+}
+
+/// Error strings and the kinds they map to, checked in order against a lowercased
+/// ename. Canonical strings come first for each kind.
+const ENAMES: &[(&str, ErrorKind)] = &[
+    ("file does not exist", ErrorKind::NotFound),
+    ("no such file or directory", ErrorKind::NotFound),
+    ("file not found", ErrorKind::NotFound),
+    ("does not exist", ErrorKind::NotFound),
+    ("not found", ErrorKind::NotFound),
+    ("permission denied", ErrorKind::PermissionDenied),
+    ("operation not permitted", ErrorKind::PermissionDenied),
+    ("access denied", ErrorKind::PermissionDenied),
+    ("file already exists", ErrorKind::AlreadyExists),
+    ("file exists", ErrorKind::AlreadyExists),
+    ("already exists", ErrorKind::AlreadyExists),
+    ("not a directory", ErrorKind::NotADirectory),
+    ("is a directory", ErrorKind::IsADirectory),
+    ("directory not empty", ErrorKind::DirectoryNotEmpty),
+    ("fid unknown or out of range", ErrorKind::BadFid),
+    ("unknown fid", ErrorKind::BadFid),
+    ("fid already in use", ErrorKind::BadFid),
+    ("duplicate fid", ErrorKind::BadFid),
+    ("bad file descriptor", ErrorKind::BadFid),
+    ("no space left on device", ErrorKind::StorageFull),
+    ("file system full", ErrorKind::StorageFull),
+    ("read-only file system", ErrorKind::ReadOnly),
+    ("read only", ErrorKind::ReadOnly),
+    ("authentication required", ErrorKind::AuthRequired),
+    ("interrupted", ErrorKind::Interrupted),
+    ("timed out", ErrorKind::TimedOut),
+    ("operation not supported", ErrorKind::Unsupported),
+    ("function not implemented", ErrorKind::Unsupported),
+    ("not supported", ErrorKind::Unsupported),
+    ("not implemented", ErrorKind::Unsupported),
+    ("i/o error", ErrorKind::Io),
+    ("input/output error", ErrorKind::Io),
+    ("invalid argument", ErrorKind::InvalidArgument),
+    ("bad arg", ErrorKind::InvalidArgument),
+];
+
+/// Linux errno values for each kind, the first being the one servers send
+const ERRNOS: &[(u32, ErrorKind)] = &[
+    (2, ErrorKind::NotFound),           // ENOENT
+    (13, ErrorKind::PermissionDenied),  // EACCES
+    (1, ErrorKind::PermissionDenied),   // EPERM
+    (17, ErrorKind::AlreadyExists),     // EEXIST
+    (20, ErrorKind::NotADirectory),     // ENOTDIR
+    (21, ErrorKind::IsADirectory),      // EISDIR
+    (39, ErrorKind::DirectoryNotEmpty), // ENOTEMPTY
+    (22, ErrorKind::InvalidArgument),   // EINVAL
+    (9, ErrorKind::BadFid),             // EBADF
+    (28, ErrorKind::StorageFull),       // ENOSPC
+    (122, ErrorKind::StorageFull),      // EDQUOT
+    (30, ErrorKind::ReadOnly),          // EROFS
+    (4, ErrorKind::Interrupted),        // EINTR
+    (110, ErrorKind::TimedOut),         // ETIMEDOUT
+    (95, ErrorKind::Unsupported),       // EOPNOTSUPP
+    (38, ErrorKind::Unsupported),       // ENOSYS
+    (5, ErrorKind::Io),                 // EIO
+];
+
+impl ErrorKind {
+    /// Classify the ename of an `Rerror`
+    #[must_use]
+    pub fn from_ename(ename: &str) -> Self {
+        let ename = ename.to_lowercase();
+        ENAMES
+            .iter()
+            .find(|(pattern, _)| ename.contains(pattern))
+            .map_or(ErrorKind::Other, |(_, kind)| *kind)
+    }
+
+    /// Classify the errno of an `Rlerror` or 9P2000.u `Rerror`
+    #[must_use]
+    pub fn from_errno(errno: u32) -> Self {
+        ERRNOS
+            .iter()
+            .find(|(code, _)| *code == errno)
+            .map_or(ErrorKind::Other, |(_, kind)| *kind)
+    }
+
+    /// The string servers should send in an `Rerror`
+    #[must_use]
+    pub fn ename(self) -> &'static str {
+        ENAMES
+            .iter()
+            .find(|(_, kind)| *kind == self)
+            .map_or("i/o error", |(ename, _)| ename)
+    }
+
+    /// The errno servers should send in an `Rlerror`
+    #[must_use]
+    pub fn errno(self) -> u32 {
+        ERRNOS
+            .iter()
+            .find(|(_, kind)| *kind == self)
+            .map_or(5, |(errno, _)| *errno)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<ErrorKind> for io::ErrorKind {
+    fn from(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::NotFound => io::ErrorKind::NotFound,
+            ErrorKind::PermissionDenied | ErrorKind::AuthRequired => {
+                io::ErrorKind::PermissionDenied
+            }
+            ErrorKind::AlreadyExists => io::ErrorKind::AlreadyExists,
+            ErrorKind::NotADirectory => io::ErrorKind::NotADirectory,
+            ErrorKind::IsADirectory => io::ErrorKind::IsADirectory,
+            ErrorKind::DirectoryNotEmpty => io::ErrorKind::DirectoryNotEmpty,
+            ErrorKind::InvalidArgument | ErrorKind::BadFid => io::ErrorKind::InvalidInput,
+            ErrorKind::StorageFull => io::ErrorKind::StorageFull,
+            ErrorKind::ReadOnly => io::ErrorKind::ReadOnlyFilesystem,
+            ErrorKind::Interrupted => io::ErrorKind::Interrupted,
+            ErrorKind::TimedOut => io::ErrorKind::TimedOut,
+            ErrorKind::Unsupported => io::ErrorKind::Unsupported,
+            ErrorKind::Io | ErrorKind::Other => io::ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::ErrorKind> for ErrorKind {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::NotFound => ErrorKind::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+            io::ErrorKind::NotADirectory => ErrorKind::NotADirectory,
+            io::ErrorKind::IsADirectory => ErrorKind::IsADirectory,
+            io::ErrorKind::DirectoryNotEmpty => ErrorKind::DirectoryNotEmpty,
+            io::ErrorKind::InvalidInput => ErrorKind::InvalidArgument,
+            io::ErrorKind::StorageFull => ErrorKind::StorageFull,
+            io::ErrorKind::ReadOnlyFilesystem => ErrorKind::ReadOnly,
+            io::ErrorKind::Interrupted => ErrorKind::Interrupted,
+            io::ErrorKind::TimedOut => ErrorKind::TimedOut,
+            io::ErrorKind::Unsupported => ErrorKind::Unsupported,
+            _ => ErrorKind::Io,
+        }
+    }
+}
+
+impl Rerror {
+    /// Classify this error
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::from_ename(&self.ename)
+    }
+}
+
+impl From<ErrorKind> for Rerror {
+    fn from(kind: ErrorKind) -> Self {
+        Rerror {
+            ename: kind.ename().to_string(),
+        }
+    }
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+impl Rlerror {
+    /// Classify this error
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        ErrorKind::from_errno(self.ecode)
+    }
+}
+
+impl From<ErrorKind> for Rlerror {
+    fn from(kind: ErrorKind) -> Self {
+        Rlerror {
+            ecode: kind.errno(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [ErrorKind; 15] = [
+        ErrorKind::NotFound,
+        ErrorKind::PermissionDenied,
+        ErrorKind::AlreadyExists,
+        ErrorKind::NotADirectory,
+        ErrorKind::IsADirectory,
+        ErrorKind::DirectoryNotEmpty,
+        ErrorKind::InvalidArgument,
+        ErrorKind::BadFid,
+        ErrorKind::StorageFull,
+        ErrorKind::ReadOnly,
+        ErrorKind::AuthRequired,
+        ErrorKind::Interrupted,
+        ErrorKind::TimedOut,
+        ErrorKind::Unsupported,
+        ErrorKind::Io,
+    ];
+
+    #[test]
+    fn test_canonical_enames_round_trip() {
+        for kind in KINDS {
+            assert_eq!(ErrorKind::from_ename(kind.ename()), kind, "{kind:?}");
+            assert_eq!(Rerror::from(kind).kind(), kind);
+        }
+    }
+
+    #[test]
+    fn test_errnos_round_trip() {
+        for kind in KINDS {
+            // there is no errno for a server that wants authentication
+            if kind != ErrorKind::AuthRequired {
+                assert_eq!(ErrorKind::from_errno(kind.errno()), kind, "{kind:?}");
+                assert_eq!(Rlerror::from(kind).kind(), kind);
+            }
+        }
+        assert_eq!(ErrorKind::from_errno(9999), ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_classifies_common_server_strings() {
+        for (ename, kind) in [
+            ("file does not exist", ErrorKind::NotFound),
+            ("'firmware' file does not exist", ErrorKind::NotFound),
+            ("No such file or directory", ErrorKind::NotFound),
+            ("permission denied", ErrorKind::PermissionDenied),
+            ("Operation not permitted", ErrorKind::PermissionDenied),
+            (
+                "create prohibited: file already exists",
+                ErrorKind::AlreadyExists,
+            ),
+            ("File exists", ErrorKind::AlreadyExists),
+            (
+                "walk in non-directory: not a directory",
+                ErrorKind::NotADirectory,
+            ),
+            ("fid unknown or out of range", ErrorKind::BadFid),
+            ("Read-only file system", ErrorKind::ReadOnly),
+            ("Input/output error", ErrorKind::Io),
+            ("the disk caught fire", ErrorKind::Other),
+        ] {
+            assert_eq!(ErrorKind::from_ename(ename), kind, "{ename}");
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_io_error_kinds() {
+        assert_eq!(
+            io::ErrorKind::from(ErrorKind::from_ename("file does not exist")),
+            io::ErrorKind::NotFound
+        );
+        for kind in KINDS {
+            if !matches!(kind, ErrorKind::AuthRequired | ErrorKind::BadFid) {
+                assert_eq!(ErrorKind::from(io::ErrorKind::from(kind)), kind, "{kind:?}");
+            }
+        }
+    }
+}
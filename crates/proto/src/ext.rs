@@ -4,7 +4,7 @@ use std::io::{self, Write};
 /// Wrapper that implements Write for `BytesMut`
 pub struct BytesMutWriter<'a>(&'a mut BytesMut);
 
-// `byteorder::WriteBytesExt` is automatically implemented for any type that implements Write
+// `WireWrite` is implemented for any type that implements Write
 impl Write for BytesMutWriter<'_> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.0.extend_from_slice(buf);
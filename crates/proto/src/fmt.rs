@@ -3,11 +3,20 @@ use flagset::FlagSet;
 use crate::{FileMode, OpenMode, QidType};
 
 use super::{
-    Message, Qid, Rattach, Rauth, Rclunk, Rcreate, Rerror, Rflush, Ropen, Rread, Rremove, Rstat,
-    Rversion, Rwalk, Rwrite, Rwstat, Stat, TaggedMessage, Tattach, Tauth, Tclunk, Tcreate, Tflush,
-    Topen, Tread, Tremove, Tstat, Tversion, Twalk, Twrite, Twstat,
+    Message, Qid, Rattach, Rauth, Rclunk, Rcreate, Rerror, Rflush, Rfsync, Rgetattr, Rgetlock,
+    Rlcreate, Rlerror, Rlink, Rlock, Rlopen, Rmkdir, Rmknod, Ropen, Rread, Rreaddir, Rreadlink,
+    Rremove, Rrename, Rrenameat, Rsetattr, Rstat, Rstatfs, Rsymlink, Runlinkat, Rversion, Rwalk,
+    Rwrite, Rwstat, Rxattrcreate, Rxattrwalk, Stat, TaggedMessage, Tattach, Tauth, Tclunk, Tcreate,
+    Tflush, Tfsync, Tgetattr, Tgetlock, Tlattach, Tlauth, Tlcreate, Tlink, Tlock, Tlopen, Tmkdir,
+    Tmknod, Topen, Tread, Treaddir, Treadlink, Tremove, Trename, Trenameat, Tsetattr, Tstat,
+    Tstatfs, Tsymlink, Tunlinkat, Tversion, Twalk, Twrite, Twstat, Txattrcreate, Txattrwalk,
 };
-use std::fmt;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
 
 impl fmt::Display for Qid {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -46,18 +55,26 @@ fn format_data(data: &[u8]) -> String {
         return "''".to_string();
     }
 
-    let is_text = data.iter().all(|&b| {
-        b.is_ascii()
-            && (b.is_ascii_graphic()
-                || b.is_ascii_whitespace()
-                || b == b'\n'
-                || b == b'\r'
-                || b == b'\t')
-    });
+    let is_text = data
+        .iter()
+        .all(|&b| b.is_ascii_graphic() || matches!(b, b' ' | b'\n' | b'\r' | b'\t'));
 
     if is_text {
-        // format as quoted string
-        format!("'{}'", String::from_utf8_lossy(data))
+        // format as quoted string, escaped so that every message stays on one line
+        let mut result = String::with_capacity(data.len() + 2);
+        result.push('\'');
+        for &b in data {
+            match b {
+                b'\n' => result.push_str("\\n"),
+                b'\r' => result.push_str("\\r"),
+                b'\t' => result.push_str("\\t"),
+                b'\\' => result.push_str("\\\\"),
+                b'\'' => result.push_str("\\'"),
+                _ => result.push(char::from(b)),
+            }
+        }
+        result.push('\'');
+        result
     } else if data.len() <= 64 {
         // short binary data as hex
         let hex_bytes: Vec<String> = data.iter().map(|b| format!("{b:02x}")).collect();
@@ -155,27 +172,8 @@ fn format_perm(perm: FlagSet<FileMode>) -> String {
 }
 
 fn format_mode(mode: FlagSet<OpenMode>) -> String {
-    if mode.is_empty() {
-        return "0".to_string();
-    }
-
-    if mode == OpenMode::Read {
-        return "0".to_string();
-    } else if mode == OpenMode::Write {
-        return "1".to_string();
-    } else if mode == OpenMode::ReadWrite {
-        return "2".to_string();
-    } else if mode == OpenMode::Exec {
-        return "3".to_string();
-    } else if mode == OpenMode::Trunc {
-        return "16".to_string();
-    } else if mode == OpenMode::RClose {
-        return "64".to_string();
-    } else if mode == (OpenMode::Trunc | OpenMode::Write) {
-        return "17".to_string();
-    }
-
-    format!("{mode:?}")
+    // the numeric value is what plan9 prints and reads back unambiguously
+    mode.bits().to_string()
 }
 
 impl fmt::Display for Tversion {
@@ -390,6 +388,371 @@ impl fmt::Display for Rwstat {
     }
 }
 
+impl fmt::Display for Tlauth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "afid {} uname {} aname {} n_uname {}",
+            format_fid(self.afid),
+            self.uname,
+            self.aname,
+            format_fid(self.n_uname)
+        )
+    }
+}
+
+impl fmt::Display for Tlattach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} afid {} uname {} aname {} n_uname {}",
+            self.fid,
+            format_fid(self.afid),
+            self.uname,
+            self.aname,
+            format_fid(self.n_uname)
+        )
+    }
+}
+
+impl fmt::Display for Rlerror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ecode {}", self.ecode)
+    }
+}
+
+impl fmt::Display for Tstatfs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fid {}", self.fid)
+    }
+}
+
+impl fmt::Display for Rstatfs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "type {} bsize {} blocks {} bfree {} bavail {} files {} ffree {} fsid {} namelen {}",
+            self.r#type,
+            self.bsize,
+            self.blocks,
+            self.bfree,
+            self.bavail,
+            self.files,
+            self.ffree,
+            self.fsid,
+            self.namelen
+        )
+    }
+}
+
+impl fmt::Display for Tlopen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fid {} flags {:o}", self.fid, self.flags)
+    }
+}
+
+impl fmt::Display for Rlopen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "qid ({}) iounit {}", self.qid, self.iounit)
+    }
+}
+
+impl fmt::Display for Tlcreate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} name {} flags {:o} mode {:o} gid {}",
+            self.fid, self.name, self.flags, self.mode, self.gid
+        )
+    }
+}
+
+impl fmt::Display for Rlcreate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "qid ({}) iounit {}", self.qid, self.iounit)
+    }
+}
+
+impl fmt::Display for Tsymlink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} name {} symtgt {} gid {}",
+            self.fid, self.name, self.symtgt, self.gid
+        )
+    }
+}
+
+impl fmt::Display for Rsymlink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "qid ({})", self.qid)
+    }
+}
+
+impl fmt::Display for Tmknod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dfid {} name {} mode {:o} major {} minor {} gid {}",
+            self.dfid, self.name, self.mode, self.major, self.minor, self.gid
+        )
+    }
+}
+
+impl fmt::Display for Rmknod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "qid ({})", self.qid)
+    }
+}
+
+impl fmt::Display for Trename {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fid {} dfid {} name {}", self.fid, self.dfid, self.name)
+    }
+}
+
+impl fmt::Display for Rrename {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Treadlink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fid {}", self.fid)
+    }
+}
+
+impl fmt::Display for Rreadlink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "target {}", self.target)
+    }
+}
+
+impl fmt::Display for Tgetattr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fid {} request_mask {:#x}", self.fid, self.request_mask)
+    }
+}
+
+impl fmt::Display for Rgetattr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "valid {:#x} qid ({}) mode {:o} uid {} gid {} nlink {} rdev {} size {} blksize {} \
+             blocks {} atime {}.{:09} mtime {}.{:09} ctime {}.{:09} btime {}.{:09} gen {} \
+             data_version {}",
+            self.valid,
+            self.qid,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.nlink,
+            self.rdev,
+            self.size,
+            self.blksize,
+            self.blocks,
+            self.atime_sec,
+            self.atime_nsec,
+            self.mtime_sec,
+            self.mtime_nsec,
+            self.ctime_sec,
+            self.ctime_nsec,
+            self.btime_sec,
+            self.btime_nsec,
+            self.gen,
+            self.data_version
+        )
+    }
+}
+
+impl fmt::Display for Tsetattr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} valid {:#x} mode {:o} uid {} gid {} size {} atime {}.{:09} mtime {}.{:09}",
+            self.fid,
+            self.valid,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.size,
+            self.atime_sec,
+            self.atime_nsec,
+            self.mtime_sec,
+            self.mtime_nsec
+        )
+    }
+}
+
+impl fmt::Display for Rsetattr {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Txattrwalk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} newfid {} name {}",
+            self.fid, self.newfid, self.name
+        )
+    }
+}
+
+impl fmt::Display for Rxattrwalk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "size {}", self.size)
+    }
+}
+
+impl fmt::Display for Txattrcreate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} name {} size {} flags {}",
+            self.fid, self.name, self.attr_size, self.flags
+        )
+    }
+}
+
+impl fmt::Display for Rxattrcreate {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Treaddir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} offset {} count {}",
+            self.fid, self.offset, self.count
+        )
+    }
+}
+
+impl fmt::Display for Rreaddir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "count {} {}", self.data.len(), format_data(&self.data))
+    }
+}
+
+impl fmt::Display for Tfsync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fid {} datasync {}", self.fid, self.datasync)
+    }
+}
+
+impl fmt::Display for Rfsync {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Tlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} type {} flags {} start {} length {} proc_id {} client_id {}",
+            self.fid,
+            self.r#type,
+            self.flags,
+            self.start,
+            self.length,
+            self.proc_id,
+            self.client_id
+        )
+    }
+}
+
+impl fmt::Display for Rlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "status {}", self.status)
+    }
+}
+
+impl fmt::Display for Tgetlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fid {} type {} start {} length {} proc_id {} client_id {}",
+            self.fid, self.r#type, self.start, self.length, self.proc_id, self.client_id
+        )
+    }
+}
+
+impl fmt::Display for Rgetlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "type {} start {} length {} proc_id {} client_id {}",
+            self.r#type, self.start, self.length, self.proc_id, self.client_id
+        )
+    }
+}
+
+impl fmt::Display for Tlink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dfid {} fid {} name {}", self.dfid, self.fid, self.name)
+    }
+}
+
+impl fmt::Display for Rlink {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Tmkdir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dfid {} name {} mode {:o} gid {}",
+            self.dfid, self.name, self.mode, self.gid
+        )
+    }
+}
+
+impl fmt::Display for Rmkdir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "qid ({})", self.qid)
+    }
+}
+
+impl fmt::Display for Trenameat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "olddirfid {} oldname {} newdirfid {} newname {}",
+            self.olddirfid, self.oldname, self.newdirfid, self.newname
+        )
+    }
+}
+
+impl fmt::Display for Rrenameat {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Tunlinkat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "dirfid {} name {} flags {}",
+            self.dirfid, self.name, self.flags
+        )
+    }
+}
+
+impl fmt::Display for Runlinkat {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Ok(())
+    }
+}
+
 impl fmt::Display for Message {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -420,6 +783,47 @@ impl fmt::Display for Message {
             Message::Rstat(msg) => write!(f, "Rstat {msg}"),
             Message::Twstat(msg) => write!(f, "Twstat {msg}"),
             Message::Rwstat(msg) => write!(f, "Rwstat {msg}"),
+            Message::Tlauth(msg) => write!(f, "Tlauth {msg}"),
+            Message::Tlattach(msg) => write!(f, "Tlattach {msg}"),
+            Message::Rlerror(msg) => write!(f, "Rlerror {msg}"),
+            Message::Tstatfs(msg) => write!(f, "Tstatfs {msg}"),
+            Message::Rstatfs(msg) => write!(f, "Rstatfs {msg}"),
+            Message::Tlopen(msg) => write!(f, "Tlopen {msg}"),
+            Message::Rlopen(msg) => write!(f, "Rlopen {msg}"),
+            Message::Tlcreate(msg) => write!(f, "Tlcreate {msg}"),
+            Message::Rlcreate(msg) => write!(f, "Rlcreate {msg}"),
+            Message::Tsymlink(msg) => write!(f, "Tsymlink {msg}"),
+            Message::Rsymlink(msg) => write!(f, "Rsymlink {msg}"),
+            Message::Tmknod(msg) => write!(f, "Tmknod {msg}"),
+            Message::Rmknod(msg) => write!(f, "Rmknod {msg}"),
+            Message::Trename(msg) => write!(f, "Trename {msg}"),
+            Message::Rrename(msg) => write!(f, "Rrename {msg}"),
+            Message::Treadlink(msg) => write!(f, "Treadlink {msg}"),
+            Message::Rreadlink(msg) => write!(f, "Rreadlink {msg}"),
+            Message::Tgetattr(msg) => write!(f, "Tgetattr {msg}"),
+            Message::Rgetattr(msg) => write!(f, "Rgetattr {msg}"),
+            Message::Tsetattr(msg) => write!(f, "Tsetattr {msg}"),
+            Message::Rsetattr(msg) => write!(f, "Rsetattr {msg}"),
+            Message::Txattrwalk(msg) => write!(f, "Txattrwalk {msg}"),
+            Message::Rxattrwalk(msg) => write!(f, "Rxattrwalk {msg}"),
+            Message::Txattrcreate(msg) => write!(f, "Txattrcreate {msg}"),
+            Message::Rxattrcreate(msg) => write!(f, "Rxattrcreate {msg}"),
+            Message::Treaddir(msg) => write!(f, "Treaddir {msg}"),
+            Message::Rreaddir(msg) => write!(f, "Rreaddir {msg}"),
+            Message::Tfsync(msg) => write!(f, "Tfsync {msg}"),
+            Message::Rfsync(msg) => write!(f, "Rfsync {msg}"),
+            Message::Tlock(msg) => write!(f, "Tlock {msg}"),
+            Message::Rlock(msg) => write!(f, "Rlock {msg}"),
+            Message::Tgetlock(msg) => write!(f, "Tgetlock {msg}"),
+            Message::Rgetlock(msg) => write!(f, "Rgetlock {msg}"),
+            Message::Tlink(msg) => write!(f, "Tlink {msg}"),
+            Message::Rlink(msg) => write!(f, "Rlink {msg}"),
+            Message::Tmkdir(msg) => write!(f, "Tmkdir {msg}"),
+            Message::Rmkdir(msg) => write!(f, "Rmkdir {msg}"),
+            Message::Trenameat(msg) => write!(f, "Trenameat {msg}"),
+            Message::Rrenameat(msg) => write!(f, "Rrenameat {msg}"),
+            Message::Tunlinkat(msg) => write!(f, "Tunlinkat {msg}"),
+            Message::Runlinkat(msg) => write!(f, "Runlinkat {msg}"),
         }
     }
 }
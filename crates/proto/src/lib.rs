@@ -1,28 +1,59 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use crate::consts::P9_MAXWELEM;
 use crate::error::{Error, Result};
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use bytes::{Bytes, BytesMut};
-use ext::BytesMutWriteExt;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use bytes::Bytes;
 use flagset::{flags, FlagSet};
-use std::io::Cursor;
-use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
 
+pub mod auth;
+#[cfg(feature = "std")]
+pub mod blocking;
+#[cfg(feature = "codec")]
+mod codec;
 pub mod consts;
+mod dir;
+mod dotl;
 pub mod error;
+#[cfg(feature = "codec")]
 mod ext;
 mod fmt;
+mod parse;
+#[cfg(feature = "tokio")]
+mod record;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "tokio")]
+pub mod transport;
+mod wire;
+
+#[cfg(feature = "codec")]
+pub use codec::MessageCodec;
+pub use dir::{decode_dir, decode_dirents, encode_dir, encode_dirents, Dirent};
+pub use dotl::*;
+pub use parse::parse_trace;
+#[cfg(feature = "tokio")]
+pub use record::{Direction, RecordedFrame, Recorder, Recording, ReplayServer};
+pub use wire::{WireRead, WireWrite};
 
 pub trait Encodable {
     /// Encode self to writer and return the number of bytes written
     /// # Errors
     /// - implementation specific
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize>;
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize>;
 }
 
 pub trait Decodable: Sized {
     /// Decode self from reader
     /// # Errors
     /// - implementation specific
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self>;
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self>;
 }
 
 pub trait Protocol: Encodable + Decodable {}
@@ -58,6 +89,47 @@ pub enum MessageType {
     Rstat = 125,
     Twstat = 126,
     Rwstat = 127,
+
+    // 9P2000.L
+    Rlerror = 7,
+    Tstatfs = 8,
+    Rstatfs = 9,
+    Tlopen = 12,
+    Rlopen = 13,
+    Tlcreate = 14,
+    Rlcreate = 15,
+    Tsymlink = 16,
+    Rsymlink = 17,
+    Tmknod = 18,
+    Rmknod = 19,
+    Trename = 20,
+    Rrename = 21,
+    Treadlink = 22,
+    Rreadlink = 23,
+    Tgetattr = 24,
+    Rgetattr = 25,
+    Tsetattr = 26,
+    Rsetattr = 27,
+    Txattrwalk = 30,
+    Rxattrwalk = 31,
+    Txattrcreate = 32,
+    Rxattrcreate = 33,
+    Treaddir = 40,
+    Rreaddir = 41,
+    Tfsync = 50,
+    Rfsync = 51,
+    Tlock = 52,
+    Rlock = 53,
+    Tgetlock = 54,
+    Rgetlock = 55,
+    Tlink = 70,
+    Rlink = 71,
+    Tmkdir = 72,
+    Rmkdir = 73,
+    Trenameat = 74,
+    Rrenameat = 75,
+    Tunlinkat = 76,
+    Runlinkat = 77,
 }
 
 impl MessageType {
@@ -92,6 +164,45 @@ impl MessageType {
             125 => Ok(MessageType::Rstat),
             126 => Ok(MessageType::Twstat),
             127 => Ok(MessageType::Rwstat),
+            7 => Ok(MessageType::Rlerror),
+            8 => Ok(MessageType::Tstatfs),
+            9 => Ok(MessageType::Rstatfs),
+            12 => Ok(MessageType::Tlopen),
+            13 => Ok(MessageType::Rlopen),
+            14 => Ok(MessageType::Tlcreate),
+            15 => Ok(MessageType::Rlcreate),
+            16 => Ok(MessageType::Tsymlink),
+            17 => Ok(MessageType::Rsymlink),
+            18 => Ok(MessageType::Tmknod),
+            19 => Ok(MessageType::Rmknod),
+            20 => Ok(MessageType::Trename),
+            21 => Ok(MessageType::Rrename),
+            22 => Ok(MessageType::Treadlink),
+            23 => Ok(MessageType::Rreadlink),
+            24 => Ok(MessageType::Tgetattr),
+            25 => Ok(MessageType::Rgetattr),
+            26 => Ok(MessageType::Tsetattr),
+            27 => Ok(MessageType::Rsetattr),
+            30 => Ok(MessageType::Txattrwalk),
+            31 => Ok(MessageType::Rxattrwalk),
+            32 => Ok(MessageType::Txattrcreate),
+            33 => Ok(MessageType::Rxattrcreate),
+            40 => Ok(MessageType::Treaddir),
+            41 => Ok(MessageType::Rreaddir),
+            50 => Ok(MessageType::Tfsync),
+            51 => Ok(MessageType::Rfsync),
+            52 => Ok(MessageType::Tlock),
+            53 => Ok(MessageType::Rlock),
+            54 => Ok(MessageType::Tgetlock),
+            55 => Ok(MessageType::Rgetlock),
+            70 => Ok(MessageType::Tlink),
+            71 => Ok(MessageType::Rlink),
+            72 => Ok(MessageType::Tmkdir),
+            73 => Ok(MessageType::Rmkdir),
+            74 => Ok(MessageType::Trenameat),
+            75 => Ok(MessageType::Rrenameat),
+            76 => Ok(MessageType::Tunlinkat),
+            77 => Ok(MessageType::Runlinkat),
             _ => Err(Error::InvalidMessageType(value)),
         }
     }
@@ -100,6 +211,52 @@ impl MessageType {
     pub fn to_u8(self) -> u8 {
         self as u8
     }
+
+    /// Whether this message type only exists in the 9P2000.L dialect
+    #[must_use]
+    pub fn is_dotl(self) -> bool {
+        self.to_u8() < MessageType::Tversion.to_u8()
+    }
+}
+
+/// The protocol variant spoken on a connection, as agreed upon by `Tversion`/`Rversion`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    /// Plain 9P2000
+    #[default]
+    Plan9,
+    /// 9P2000.L, the Linux dialect
+    Linux,
+}
+
+impl Dialect {
+    /// Returns the dialect named by a `Tversion`/`Rversion` version string
+    #[must_use]
+    pub fn from_version(version: &str) -> Option<Self> {
+        match version {
+            "9P2000" => Some(Dialect::Plan9),
+            "9P2000.L" => Some(Dialect::Linux),
+            _ => None,
+        }
+    }
+
+    /// The version string used to request this dialect
+    #[must_use]
+    pub fn version(self) -> &'static str {
+        match self {
+            Dialect::Plan9 => "9P2000",
+            Dialect::Linux => "9P2000.L",
+        }
+    }
+
+    /// Whether `message` can be sent over a connection speaking this dialect
+    #[must_use]
+    pub fn supports(self, message: &Message) -> bool {
+        match self {
+            Dialect::Plan9 => !message.is_dotl(),
+            Dialect::Linux => true,
+        }
+    }
 }
 
 flags! {
@@ -243,6 +400,49 @@ pub enum Message {
     Rstat(Rstat),
     Twstat(Twstat),
     Rwstat(Rwstat),
+
+    // 9P2000.L
+    Tlauth(Tlauth),
+    Tlattach(Tlattach),
+    Rlerror(Rlerror),
+    Tstatfs(Tstatfs),
+    Rstatfs(Rstatfs),
+    Tlopen(Tlopen),
+    Rlopen(Rlopen),
+    Tlcreate(Tlcreate),
+    Rlcreate(Rlcreate),
+    Tsymlink(Tsymlink),
+    Rsymlink(Rsymlink),
+    Tmknod(Tmknod),
+    Rmknod(Rmknod),
+    Trename(Trename),
+    Rrename(Rrename),
+    Treadlink(Treadlink),
+    Rreadlink(Rreadlink),
+    Tgetattr(Tgetattr),
+    Rgetattr(Rgetattr),
+    Tsetattr(Tsetattr),
+    Rsetattr(Rsetattr),
+    Txattrwalk(Txattrwalk),
+    Rxattrwalk(Rxattrwalk),
+    Txattrcreate(Txattrcreate),
+    Rxattrcreate(Rxattrcreate),
+    Treaddir(Treaddir),
+    Rreaddir(Rreaddir),
+    Tfsync(Tfsync),
+    Rfsync(Rfsync),
+    Tlock(Tlock),
+    Rlock(Rlock),
+    Tgetlock(Tgetlock),
+    Rgetlock(Rgetlock),
+    Tlink(Tlink),
+    Rlink(Rlink),
+    Tmkdir(Tmkdir),
+    Rmkdir(Rmkdir),
+    Trenameat(Trenameat),
+    Rrenameat(Rrenameat),
+    Tunlinkat(Tunlinkat),
+    Runlinkat(Runlinkat),
 }
 
 impl Message {
@@ -255,9 +455,9 @@ impl Message {
         match self {
             Message::Tversion(_) => MessageType::Tversion,
             Message::Rversion(_) => MessageType::Rversion,
-            Message::Tauth(_) => MessageType::Tauth,
+            Message::Tauth(_) | Message::Tlauth(_) => MessageType::Tauth,
             Message::Rauth(_) => MessageType::Rauth,
-            Message::Tattach(_) => MessageType::Tattach,
+            Message::Tattach(_) | Message::Tlattach(_) => MessageType::Tattach,
             Message::Rattach(_) => MessageType::Rattach,
             Message::Rerror(_) => MessageType::Rerror,
             Message::Tflush(_) => MessageType::Tflush,
@@ -280,12 +480,57 @@ impl Message {
             Message::Rstat(_) => MessageType::Rstat,
             Message::Twstat(_) => MessageType::Twstat,
             Message::Rwstat(_) => MessageType::Rwstat,
+            Message::Rlerror(_) => MessageType::Rlerror,
+            Message::Tstatfs(_) => MessageType::Tstatfs,
+            Message::Rstatfs(_) => MessageType::Rstatfs,
+            Message::Tlopen(_) => MessageType::Tlopen,
+            Message::Rlopen(_) => MessageType::Rlopen,
+            Message::Tlcreate(_) => MessageType::Tlcreate,
+            Message::Rlcreate(_) => MessageType::Rlcreate,
+            Message::Tsymlink(_) => MessageType::Tsymlink,
+            Message::Rsymlink(_) => MessageType::Rsymlink,
+            Message::Tmknod(_) => MessageType::Tmknod,
+            Message::Rmknod(_) => MessageType::Rmknod,
+            Message::Trename(_) => MessageType::Trename,
+            Message::Rrename(_) => MessageType::Rrename,
+            Message::Treadlink(_) => MessageType::Treadlink,
+            Message::Rreadlink(_) => MessageType::Rreadlink,
+            Message::Tgetattr(_) => MessageType::Tgetattr,
+            Message::Rgetattr(_) => MessageType::Rgetattr,
+            Message::Tsetattr(_) => MessageType::Tsetattr,
+            Message::Rsetattr(_) => MessageType::Rsetattr,
+            Message::Txattrwalk(_) => MessageType::Txattrwalk,
+            Message::Rxattrwalk(_) => MessageType::Rxattrwalk,
+            Message::Txattrcreate(_) => MessageType::Txattrcreate,
+            Message::Rxattrcreate(_) => MessageType::Rxattrcreate,
+            Message::Treaddir(_) => MessageType::Treaddir,
+            Message::Rreaddir(_) => MessageType::Rreaddir,
+            Message::Tfsync(_) => MessageType::Tfsync,
+            Message::Rfsync(_) => MessageType::Rfsync,
+            Message::Tlock(_) => MessageType::Tlock,
+            Message::Rlock(_) => MessageType::Rlock,
+            Message::Tgetlock(_) => MessageType::Tgetlock,
+            Message::Rgetlock(_) => MessageType::Rgetlock,
+            Message::Tlink(_) => MessageType::Tlink,
+            Message::Rlink(_) => MessageType::Rlink,
+            Message::Tmkdir(_) => MessageType::Tmkdir,
+            Message::Rmkdir(_) => MessageType::Rmkdir,
+            Message::Trenameat(_) => MessageType::Trenameat,
+            Message::Rrenameat(_) => MessageType::Rrenameat,
+            Message::Tunlinkat(_) => MessageType::Tunlinkat,
+            Message::Runlinkat(_) => MessageType::Runlinkat,
         }
     }
 
     pub fn to_tagged(self, tag: u16) -> TaggedMessage {
         TaggedMessage { tag, message: self }
     }
+
+    /// Whether this message is only valid on a 9P2000.L connection
+    #[must_use]
+    pub fn is_dotl(&self) -> bool {
+        matches!(self, Message::Tlauth(_) | Message::Tlattach(_)) || self.message_type().is_dotl()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -436,66 +681,66 @@ pub struct Twstat {
 pub struct Rwstat;
 
 impl Encodable for u8 {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         w.write_u8(*self)?;
         Ok(1)
     }
 }
 
 impl Decodable for u8 {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
-        Ok(r.read_u8()?)
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        r.read_u8()
     }
 }
 
 impl Encodable for u16 {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
-        w.write_u16::<LittleEndian>(*self)?;
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+        w.write_u16(*self)?;
         Ok(2)
     }
 }
 
 impl Decodable for u16 {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
-        Ok(r.read_u16::<LittleEndian>()?)
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        r.read_u16()
     }
 }
 
 impl Encodable for u32 {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
-        w.write_u32::<LittleEndian>(*self)?;
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+        w.write_u32(*self)?;
         Ok(4)
     }
 }
 
 impl Decodable for u32 {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
-        Ok(r.read_u32::<LittleEndian>()?)
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        r.read_u32()
     }
 }
 
 impl Encodable for u64 {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
-        w.write_u64::<LittleEndian>(*self)?;
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+        w.write_u64(*self)?;
         Ok(8)
     }
 }
 
 impl Decodable for u64 {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
-        Ok(r.read_u64::<LittleEndian>()?)
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        r.read_u64()
     }
 }
 
 impl Encodable for String {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let bytes = self.as_bytes();
         if bytes.len() > u16::MAX as usize {
             return Err(Error::StringTooLong(bytes.len()));
         }
 
         let len = u16::try_from(bytes.len()).unwrap(); // safe due to check above
-        w.write_u16::<LittleEndian>(len)?;
+        w.write_u16(len)?;
         w.write_all(bytes)?;
 
         Ok(2 + bytes.len())
@@ -503,21 +748,19 @@ impl Encodable for String {
 }
 
 impl Decodable for String {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
-        let len = r.read_u16::<LittleEndian>()? as usize;
-
-        let mut string_bytes = vec![0u8; len];
-        r.read_exact(&mut string_bytes)?;
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        let len = r.read_u16()? as usize;
+        let string_bytes = read_exact_len(r, len)?;
 
         Ok(String::from_utf8(string_bytes)?)
     }
 }
 
 impl Encodable for Bytes {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let len = u32::try_from(self.len()).map_err(|_| Error::BytesTooLong(self.len()))?;
 
-        w.write_u32::<LittleEndian>(len)?;
+        w.write_u32(len)?;
         w.write_all(self)?;
 
         Ok(4 + self.len())
@@ -525,24 +768,46 @@ impl Encodable for Bytes {
 }
 
 impl Decodable for Bytes {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
-        let len = r.read_u32::<LittleEndian>()? as usize;
-
-        let mut data = vec![0u8; len];
-        r.read_exact(&mut data)?;
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        let len = r.read_u32()? as usize;
+        let data = read_exact_len(r, len)?;
 
         Ok(Bytes::from(data))
     }
 }
 
+/// Read `len` bytes whose length came off the wire.
+///
+/// The buffer only grows as data actually arrives, so a bogus length prefix can't
+/// make us allocate more than the peer has sent.
+pub(crate) fn read_exact_len<R: WireRead>(r: &mut R, len: usize) -> Result<Vec<u8>> {
+    const CHUNK: usize = 8 * 1024;
+
+    let mut data = Vec::new();
+    while data.len() < len {
+        let start = data.len();
+        data.resize(start + CHUNK.min(len - start), 0);
+        let n = r.read(&mut data[start..])?;
+        data.truncate(start + n);
+        if n == 0 {
+            return Err(Error::InsufficientData {
+                expected: len,
+                actual: start,
+            });
+        }
+    }
+
+    Ok(data)
+}
+
 impl Encodable for FlagSet<OpenMode> {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.bits().encode(w)
     }
 }
 
 impl Decodable for FlagSet<OpenMode> {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         let val = u8::decode(r)?;
         let f = FlagSet::<OpenMode>::new(val)?;
         Ok(f)
@@ -550,13 +815,13 @@ impl Decodable for FlagSet<OpenMode> {
 }
 
 impl Encodable for FlagSet<FileMode> {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.bits().encode(w)
     }
 }
 
 impl Decodable for FlagSet<FileMode> {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         let val = u32::decode(r)?;
         let f = FlagSet::<FileMode>::new(val)?;
         Ok(f)
@@ -564,13 +829,13 @@ impl Decodable for FlagSet<FileMode> {
 }
 
 impl Encodable for FlagSet<QidType> {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.bits().encode(w)
     }
 }
 
 impl Decodable for FlagSet<QidType> {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         let val = u8::decode(r)?;
         let f = FlagSet::<QidType>::new(val)?;
         Ok(f)
@@ -578,7 +843,7 @@ impl Decodable for FlagSet<QidType> {
 }
 
 impl Encodable for Qid {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
 
         bytes_written += self.qtype.encode(w)?;
@@ -590,7 +855,7 @@ impl Encodable for Qid {
 }
 
 impl Decodable for Qid {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Qid {
             qtype: FlagSet::<QidType>::decode(r)?,
             version: u32::decode(r)?,
@@ -600,7 +865,7 @@ impl Decodable for Qid {
 }
 
 impl<T: Encodable> Encodable for Vec<T> {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         if self.len() > u16::MAX as usize {
             return Err(Error::VectorTooLong(self.len()));
         }
@@ -617,9 +882,10 @@ impl<T: Encodable> Encodable for Vec<T> {
 }
 
 impl<T: Decodable> Decodable for Vec<T> {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         let len = u16::decode(r)? as usize;
-        let mut vec = Vec::with_capacity(len);
+        // the count is untrusted, so let the vector grow as elements decode
+        let mut vec = Vec::new();
 
         for _ in 0..len {
             vec.push(T::decode(r)?);
@@ -630,19 +896,19 @@ impl<T: Decodable> Decodable for Vec<T> {
 }
 
 impl Encodable for () {
-    fn encode<W: WriteBytesExt>(&self, _w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, _w: &mut W) -> Result<usize> {
         Ok(0)
     }
 }
 
 impl Decodable for () {
-    fn decode<R: ReadBytesExt>(_r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(_r: &mut R) -> Result<Self> {
         Ok(())
     }
 }
 
 impl Encodable for Tversion {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.msize.encode(w)?;
         bytes_written += self.version.encode(w)?;
@@ -651,7 +917,7 @@ impl Encodable for Tversion {
 }
 
 impl Decodable for Tversion {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tversion {
             msize: u32::decode(r)?,
             version: String::decode(r)?,
@@ -660,7 +926,7 @@ impl Decodable for Tversion {
 }
 
 impl Encodable for Rversion {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.msize.encode(w)?;
         bytes_written += self.version.encode(w)?;
@@ -669,7 +935,7 @@ impl Encodable for Rversion {
 }
 
 impl Decodable for Rversion {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Rversion {
             msize: u32::decode(r)?,
             version: String::decode(r)?,
@@ -678,7 +944,7 @@ impl Decodable for Rversion {
 }
 
 impl Encodable for Tauth {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.afid.encode(w)?;
         bytes_written += self.uname.encode(w)?;
@@ -688,7 +954,7 @@ impl Encodable for Tauth {
 }
 
 impl Decodable for Tauth {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tauth {
             afid: u32::decode(r)?,
             uname: String::decode(r)?,
@@ -698,13 +964,13 @@ impl Decodable for Tauth {
 }
 
 impl Encodable for Rauth {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.aqid.encode(w)
     }
 }
 
 impl Decodable for Rauth {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Rauth {
             aqid: Qid::decode(r)?,
         })
@@ -712,7 +978,7 @@ impl Decodable for Rauth {
 }
 
 impl Encodable for Tattach {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.fid.encode(w)?;
         bytes_written += self.afid.encode(w)?;
@@ -723,7 +989,7 @@ impl Encodable for Tattach {
 }
 
 impl Decodable for Tattach {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tattach {
             fid: u32::decode(r)?,
             afid: u32::decode(r)?,
@@ -734,13 +1000,13 @@ impl Decodable for Tattach {
 }
 
 impl Encodable for Rattach {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.qid.encode(w)
     }
 }
 
 impl Decodable for Rattach {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Rattach {
             qid: Qid::decode(r)?,
         })
@@ -748,13 +1014,13 @@ impl Decodable for Rattach {
 }
 
 impl Encodable for Rerror {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.ename.encode(w)
     }
 }
 
 impl Decodable for Rerror {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Rerror {
             ename: String::decode(r)?,
         })
@@ -762,13 +1028,13 @@ impl Decodable for Rerror {
 }
 
 impl Encodable for Tflush {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.oldtag.encode(w)
     }
 }
 
 impl Decodable for Tflush {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tflush {
             oldtag: u16::decode(r)?,
         })
@@ -776,19 +1042,21 @@ impl Decodable for Tflush {
 }
 
 impl Encodable for Rflush {
-    fn encode<W: WriteBytesExt>(&self, _w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, _w: &mut W) -> Result<usize> {
         Ok(0)
     }
 }
 
 impl Decodable for Rflush {
-    fn decode<R: ReadBytesExt>(_r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(_r: &mut R) -> Result<Self> {
         Ok(Rflush)
     }
 }
 
 impl Encodable for Twalk {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+        check_walk_len(self.wnames.len())?;
+
         let mut bytes_written = 0;
         bytes_written += self.fid.encode(w)?;
         bytes_written += self.newfid.encode(w)?;
@@ -798,31 +1066,46 @@ impl Encodable for Twalk {
 }
 
 impl Decodable for Twalk {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        let fid = u32::decode(r)?;
+        let newfid = u32::decode(r)?;
+        let wnames = Vec::<String>::decode(r)?;
+        check_walk_len(wnames.len())?;
+
         Ok(Twalk {
-            fid: u32::decode(r)?,
-            newfid: u32::decode(r)?,
-            wnames: Vec::<String>::decode(r)?,
+            fid,
+            newfid,
+            wnames,
         })
     }
 }
 
 impl Encodable for Rwalk {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+        check_walk_len(self.wqids.len())?;
         self.wqids.encode(w)
     }
 }
 
 impl Decodable for Rwalk {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
-        Ok(Rwalk {
-            wqids: Vec::<Qid>::decode(r)?,
-        })
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        let wqids = Vec::<Qid>::decode(r)?;
+        check_walk_len(wqids.len())?;
+        Ok(Rwalk { wqids })
+    }
+}
+
+fn check_walk_len(len: usize) -> Result<()> {
+    if len > P9_MAXWELEM {
+        return Err(Error::Protocol(format!(
+            "walk of {len} elements exceeds the maximum of {P9_MAXWELEM}"
+        )));
     }
+    Ok(())
 }
 
 impl Encodable for Topen {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.fid.encode(w)?;
         bytes_written += self.mode.encode(w)?;
@@ -831,7 +1114,7 @@ impl Encodable for Topen {
 }
 
 impl Decodable for Topen {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Topen {
             fid: u32::decode(r)?,
             mode: FlagSet::<OpenMode>::decode(r)?,
@@ -840,7 +1123,7 @@ impl Decodable for Topen {
 }
 
 impl Encodable for Ropen {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.qid.encode(w)?;
         bytes_written += self.iounit.encode(w)?;
@@ -849,7 +1132,7 @@ impl Encodable for Ropen {
 }
 
 impl Decodable for Ropen {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Ropen {
             qid: Qid::decode(r)?,
             iounit: u32::decode(r)?,
@@ -858,7 +1141,7 @@ impl Decodable for Ropen {
 }
 
 impl Encodable for Tcreate {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.fid.encode(w)?;
         bytes_written += self.name.encode(w)?;
@@ -869,7 +1152,7 @@ impl Encodable for Tcreate {
 }
 
 impl Decodable for Tcreate {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tcreate {
             fid: u32::decode(r)?,
             name: String::decode(r)?,
@@ -880,7 +1163,7 @@ impl Decodable for Tcreate {
 }
 
 impl Encodable for Rcreate {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.qid.encode(w)?;
         bytes_written += self.iounit.encode(w)?;
@@ -889,7 +1172,7 @@ impl Encodable for Rcreate {
 }
 
 impl Decodable for Rcreate {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Rcreate {
             qid: Qid::decode(r)?,
             iounit: u32::decode(r)?,
@@ -898,7 +1181,7 @@ impl Decodable for Rcreate {
 }
 
 impl Encodable for Tread {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.fid.encode(w)?;
         bytes_written += self.offset.encode(w)?;
@@ -908,7 +1191,7 @@ impl Encodable for Tread {
 }
 
 impl Decodable for Tread {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tread {
             fid: u32::decode(r)?,
             offset: u64::decode(r)?,
@@ -918,13 +1201,13 @@ impl Decodable for Tread {
 }
 
 impl Encodable for Rread {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.data.encode(w)
     }
 }
 
 impl Decodable for Rread {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Rread {
             data: Bytes::decode(r)?,
         })
@@ -932,7 +1215,7 @@ impl Decodable for Rread {
 }
 
 impl Encodable for Twrite {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.fid.encode(w)?;
         bytes_written += self.offset.encode(w)?;
@@ -942,7 +1225,7 @@ impl Encodable for Twrite {
 }
 
 impl Decodable for Twrite {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Twrite {
             fid: u32::decode(r)?,
             offset: u64::decode(r)?,
@@ -952,13 +1235,13 @@ impl Decodable for Twrite {
 }
 
 impl Encodable for Rwrite {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.count.encode(w)
     }
 }
 
 impl Decodable for Rwrite {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Rwrite {
             count: u32::decode(r)?,
         })
@@ -966,13 +1249,13 @@ impl Decodable for Rwrite {
 }
 
 impl Encodable for Tclunk {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.fid.encode(w)
     }
 }
 
 impl Decodable for Tclunk {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tclunk {
             fid: u32::decode(r)?,
         })
@@ -980,25 +1263,25 @@ impl Decodable for Tclunk {
 }
 
 impl Encodable for Rclunk {
-    fn encode<W: WriteBytesExt>(&self, _w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, _w: &mut W) -> Result<usize> {
         Ok(0)
     }
 }
 
 impl Decodable for Rclunk {
-    fn decode<R: ReadBytesExt>(_r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(_r: &mut R) -> Result<Self> {
         Ok(Rclunk)
     }
 }
 
 impl Encodable for Tremove {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.fid.encode(w)
     }
 }
 
 impl Decodable for Tremove {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tremove {
             fid: u32::decode(r)?,
         })
@@ -1006,25 +1289,25 @@ impl Decodable for Tremove {
 }
 
 impl Encodable for Rremove {
-    fn encode<W: WriteBytesExt>(&self, _w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, _w: &mut W) -> Result<usize> {
         Ok(0)
     }
 }
 
 impl Decodable for Rremove {
-    fn decode<R: ReadBytesExt>(_r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(_r: &mut R) -> Result<Self> {
         Ok(Rremove)
     }
 }
 
 impl Encodable for Tstat {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         self.fid.encode(w)
     }
 }
 
 impl Decodable for Tstat {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         Ok(Tstat {
             fid: u32::decode(r)?,
         })
@@ -1032,11 +1315,10 @@ impl Decodable for Tstat {
 }
 
 impl Encodable for Rstat {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         // Calculate the stat size and encode it
         let mut temp_buf = Vec::new();
-        let mut temp_writer = Cursor::new(&mut temp_buf);
-        let stat_size = self.stat.encode(&mut temp_writer)?;
+        let stat_size = self.stat.encode(&mut temp_buf)?;
 
         let stat_size = u16::try_from(stat_size).map_err(|_| Error::StringTooLong(stat_size))?;
 
@@ -1048,7 +1330,7 @@ impl Encodable for Rstat {
 }
 
 impl Decodable for Rstat {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         let _stat_size = u16::decode(r)?;
         let stat = Stat::decode(r)?;
         Ok(Rstat { stat })
@@ -1056,13 +1338,12 @@ impl Decodable for Rstat {
 }
 
 impl Encodable for Twstat {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
         bytes_written += self.fid.encode(w)?;
 
         let mut temp_buf = Vec::new();
-        let mut temp_writer = Cursor::new(&mut temp_buf);
-        let stat_size = self.stat.encode(&mut temp_writer)?;
+        let stat_size = self.stat.encode(&mut temp_buf)?;
 
         let stat_size = u16::try_from(stat_size).map_err(|_| Error::StringTooLong(stat_size))?;
 
@@ -1074,7 +1355,7 @@ impl Encodable for Twstat {
 }
 
 impl Decodable for Twstat {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         let fid = u32::decode(r)?;
         let _stat_size = u16::decode(r)?;
         let stat = Stat::decode(r)?;
@@ -1083,19 +1364,19 @@ impl Decodable for Twstat {
 }
 
 impl Encodable for Rwstat {
-    fn encode<W: WriteBytesExt>(&self, _w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, _w: &mut W) -> Result<usize> {
         Ok(0)
     }
 }
 
 impl Decodable for Rwstat {
-    fn decode<R: ReadBytesExt>(_r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(_r: &mut R) -> Result<Self> {
         Ok(Rwstat)
     }
 }
 
 impl Encodable for Message {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         match self {
             Message::Tversion(msg) => msg.encode(w),
             Message::Rversion(msg) => msg.encode(w),
@@ -1124,20 +1405,62 @@ impl Encodable for Message {
             Message::Rstat(msg) => msg.encode(w),
             Message::Twstat(msg) => msg.encode(w),
             Message::Rwstat(msg) => msg.encode(w),
+            Message::Tlauth(msg) => msg.encode(w),
+            Message::Tlattach(msg) => msg.encode(w),
+            Message::Rlerror(msg) => msg.encode(w),
+            Message::Tstatfs(msg) => msg.encode(w),
+            Message::Rstatfs(msg) => msg.encode(w),
+            Message::Tlopen(msg) => msg.encode(w),
+            Message::Rlopen(msg) => msg.encode(w),
+            Message::Tlcreate(msg) => msg.encode(w),
+            Message::Rlcreate(msg) => msg.encode(w),
+            Message::Tsymlink(msg) => msg.encode(w),
+            Message::Rsymlink(msg) => msg.encode(w),
+            Message::Tmknod(msg) => msg.encode(w),
+            Message::Rmknod(msg) => msg.encode(w),
+            Message::Trename(msg) => msg.encode(w),
+            Message::Rrename(msg) => msg.encode(w),
+            Message::Treadlink(msg) => msg.encode(w),
+            Message::Rreadlink(msg) => msg.encode(w),
+            Message::Tgetattr(msg) => msg.encode(w),
+            Message::Rgetattr(msg) => msg.encode(w),
+            Message::Tsetattr(msg) => msg.encode(w),
+            Message::Rsetattr(msg) => msg.encode(w),
+            Message::Txattrwalk(msg) => msg.encode(w),
+            Message::Rxattrwalk(msg) => msg.encode(w),
+            Message::Txattrcreate(msg) => msg.encode(w),
+            Message::Rxattrcreate(msg) => msg.encode(w),
+            Message::Treaddir(msg) => msg.encode(w),
+            Message::Rreaddir(msg) => msg.encode(w),
+            Message::Tfsync(msg) => msg.encode(w),
+            Message::Rfsync(msg) => msg.encode(w),
+            Message::Tlock(msg) => msg.encode(w),
+            Message::Rlock(msg) => msg.encode(w),
+            Message::Tgetlock(msg) => msg.encode(w),
+            Message::Rgetlock(msg) => msg.encode(w),
+            Message::Tlink(msg) => msg.encode(w),
+            Message::Rlink(msg) => msg.encode(w),
+            Message::Tmkdir(msg) => msg.encode(w),
+            Message::Rmkdir(msg) => msg.encode(w),
+            Message::Trenameat(msg) => msg.encode(w),
+            Message::Rrenameat(msg) => msg.encode(w),
+            Message::Tunlinkat(msg) => msg.encode(w),
+            Message::Runlinkat(msg) => msg.encode(w),
         }
     }
 }
 
 impl Decodable for Message {
-    fn decode<R: ReadBytesExt>(_r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(_r: &mut R) -> Result<Self> {
         Err(Error::Protocol(
-            "Message::decode called directly".to_string(),
+            "Message::decode called without a message type, use Message::decode_with_type"
+                .to_string(),
         ))
     }
 }
 
 impl Encodable for TaggedMessage {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         let mut bytes_written = 0;
 
         bytes_written += self.message.message_type().to_u8().encode(w)?;
@@ -1149,15 +1472,113 @@ impl Encodable for TaggedMessage {
 }
 
 impl Decodable for TaggedMessage {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
+        Self::decode_dialect(r, Dialect::default())
+    }
+}
+
+impl TaggedMessage {
+    /// Decode a message as it would be sent on a connection speaking `dialect`
+    /// # Errors
+    /// - the message type is unknown or not part of `dialect`
+    /// - the message body is malformed
+    pub fn decode_dialect<R: WireRead>(r: &mut R, dialect: Dialect) -> Result<Self> {
         let message_type = MessageType::from_u8(u8::decode(r)?)?;
         let tag = u16::decode(r)?;
+        let message = Message::decode_with_type_dialect(message_type, r, dialect)?;
+
+        Ok(TaggedMessage { tag, message })
+    }
+
+    /// Decode a complete frame, starting at the type byte, as received on a connection
+    /// speaking `dialect`.
+    ///
+    /// The data of `Rread` and `Twrite` is sliced out of `frame` instead of being copied,
+    /// so a large read shares the buffer it was received into.
+    /// # Errors
+    /// - the message type is unknown or not part of `dialect`
+    /// - the message body is malformed
+    pub fn decode_frame(frame: &Bytes, dialect: Dialect) -> Result<Self> {
+        let mut cursor = frame.as_ref();
+        let message_type = MessageType::from_u8(u8::decode(&mut cursor)?)?;
+        let tag = u16::decode(&mut cursor)?;
+
+        let message = match message_type {
+            MessageType::Rread => Message::Rread(Rread {
+                data: slice_data(frame, &mut cursor)?,
+            }),
+            MessageType::Twrite => Message::Twrite(Twrite {
+                fid: u32::decode(&mut cursor)?,
+                offset: u64::decode(&mut cursor)?,
+                data: slice_data(frame, &mut cursor)?,
+            }),
+            _ => Message::decode_with_type_dialect(message_type, &mut cursor, dialect)?,
+        };
+
+        Ok(TaggedMessage { tag, message })
+    }
+}
+
+/// Decode a length-prefixed data field at the cursor, the unread rest of `frame`, as a
+/// slice of `frame`
+fn slice_data(frame: &Bytes, cursor: &mut &[u8]) -> Result<Bytes> {
+    let len = u32::decode(cursor)? as usize;
+    let start = frame.len() - cursor.len();
+    let available = cursor.len();
+
+    if len > available {
+        return Err(Error::InsufficientData {
+            expected: len,
+            actual: available,
+        });
+    }
+
+    *cursor = &cursor[len..];
+    Ok(frame.slice(start..start + len))
+}
+
+impl Message {
+    /// Encode the message type followed by the message body, leaving out the size and tag
+    /// that a `TaggedMessage` would carry. The body alone is written by [`Encodable::encode`].
+    /// # Errors
+    /// - the message body cannot be encoded
+    pub fn encode_with_type<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
+        let mut bytes_written = self.message_type().to_u8().encode(w)?;
+        bytes_written += self.encode(w)?;
+        Ok(bytes_written)
+    }
+
+    /// Decode a message body whose type is already known, e.g. from a capture file or a
+    /// transport that frames messages itself
+    /// # Errors
+    /// - the message body is malformed
+    pub fn decode_with_type<R: WireRead>(message_type: MessageType, r: &mut R) -> Result<Self> {
+        Self::decode_with_type_dialect(message_type, r, Dialect::default())
+    }
+
+    /// Decode a message body whose type is already known, as sent on a connection
+    /// speaking `dialect`
+    /// # Errors
+    /// - the message type is not part of `dialect`
+    /// - the message body is malformed
+    pub fn decode_with_type_dialect<R: WireRead>(
+        message_type: MessageType,
+        r: &mut R,
+        dialect: Dialect,
+    ) -> Result<Self> {
+        if message_type.is_dotl() && dialect != Dialect::Linux {
+            return Err(Error::UnsupportedMessageType(message_type, dialect));
+        }
 
         let message = match message_type {
             MessageType::Tversion => Message::Tversion(Tversion::decode(r)?),
             MessageType::Rversion => Message::Rversion(Rversion::decode(r)?),
+            MessageType::Tauth if dialect == Dialect::Linux => Message::Tlauth(Tlauth::decode(r)?),
             MessageType::Tauth => Message::Tauth(Tauth::decode(r)?),
             MessageType::Rauth => Message::Rauth(Rauth::decode(r)?),
+            MessageType::Tattach if dialect == Dialect::Linux => {
+                Message::Tlattach(Tlattach::decode(r)?)
+            }
             MessageType::Tattach => Message::Tattach(Tattach::decode(r)?),
             MessageType::Rattach => Message::Rattach(Rattach::decode(r)?),
             MessageType::Rerror => Message::Rerror(Rerror::decode(r)?),
@@ -1181,60 +1602,48 @@ impl Decodable for TaggedMessage {
             MessageType::Rstat => Message::Rstat(Rstat::decode(r)?),
             MessageType::Twstat => Message::Twstat(Twstat::decode(r)?),
             MessageType::Rwstat => Message::Rwstat(Rwstat::decode(r)?),
+            MessageType::Rlerror => Message::Rlerror(Rlerror::decode(r)?),
+            MessageType::Tstatfs => Message::Tstatfs(Tstatfs::decode(r)?),
+            MessageType::Rstatfs => Message::Rstatfs(Rstatfs::decode(r)?),
+            MessageType::Tlopen => Message::Tlopen(Tlopen::decode(r)?),
+            MessageType::Rlopen => Message::Rlopen(Rlopen::decode(r)?),
+            MessageType::Tlcreate => Message::Tlcreate(Tlcreate::decode(r)?),
+            MessageType::Rlcreate => Message::Rlcreate(Rlcreate::decode(r)?),
+            MessageType::Tsymlink => Message::Tsymlink(Tsymlink::decode(r)?),
+            MessageType::Rsymlink => Message::Rsymlink(Rsymlink::decode(r)?),
+            MessageType::Tmknod => Message::Tmknod(Tmknod::decode(r)?),
+            MessageType::Rmknod => Message::Rmknod(Rmknod::decode(r)?),
+            MessageType::Trename => Message::Trename(Trename::decode(r)?),
+            MessageType::Rrename => Message::Rrename(Rrename::decode(r)?),
+            MessageType::Treadlink => Message::Treadlink(Treadlink::decode(r)?),
+            MessageType::Rreadlink => Message::Rreadlink(Rreadlink::decode(r)?),
+            MessageType::Tgetattr => Message::Tgetattr(Tgetattr::decode(r)?),
+            MessageType::Rgetattr => Message::Rgetattr(Rgetattr::decode(r)?),
+            MessageType::Tsetattr => Message::Tsetattr(Tsetattr::decode(r)?),
+            MessageType::Rsetattr => Message::Rsetattr(Rsetattr::decode(r)?),
+            MessageType::Txattrwalk => Message::Txattrwalk(Txattrwalk::decode(r)?),
+            MessageType::Rxattrwalk => Message::Rxattrwalk(Rxattrwalk::decode(r)?),
+            MessageType::Txattrcreate => Message::Txattrcreate(Txattrcreate::decode(r)?),
+            MessageType::Rxattrcreate => Message::Rxattrcreate(Rxattrcreate::decode(r)?),
+            MessageType::Treaddir => Message::Treaddir(Treaddir::decode(r)?),
+            MessageType::Rreaddir => Message::Rreaddir(Rreaddir::decode(r)?),
+            MessageType::Tfsync => Message::Tfsync(Tfsync::decode(r)?),
+            MessageType::Rfsync => Message::Rfsync(Rfsync::decode(r)?),
+            MessageType::Tlock => Message::Tlock(Tlock::decode(r)?),
+            MessageType::Rlock => Message::Rlock(Rlock::decode(r)?),
+            MessageType::Tgetlock => Message::Tgetlock(Tgetlock::decode(r)?),
+            MessageType::Rgetlock => Message::Rgetlock(Rgetlock::decode(r)?),
+            MessageType::Tlink => Message::Tlink(Tlink::decode(r)?),
+            MessageType::Rlink => Message::Rlink(Rlink::decode(r)?),
+            MessageType::Tmkdir => Message::Tmkdir(Tmkdir::decode(r)?),
+            MessageType::Rmkdir => Message::Rmkdir(Rmkdir::decode(r)?),
+            MessageType::Trenameat => Message::Trenameat(Trenameat::decode(r)?),
+            MessageType::Rrenameat => Message::Rrenameat(Rrenameat::decode(r)?),
+            MessageType::Tunlinkat => Message::Tunlinkat(Tunlinkat::decode(r)?),
+            MessageType::Runlinkat => Message::Runlinkat(Runlinkat::decode(r)?),
         };
 
-        Ok(TaggedMessage { tag, message })
-    }
-}
-
-pub struct MessageCodec {
-    length_codec: LengthDelimitedCodec,
-}
-
-impl MessageCodec {
-    #[must_use]
-    pub fn new() -> Self {
-        Self {
-            length_codec: LengthDelimitedCodec::builder()
-                .little_endian()
-                .length_field_length(4)
-                .length_adjustment(-4) // don't include length field in payload
-                .new_codec(),
-        }
-    }
-}
-
-impl Default for MessageCodec {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl Decoder for MessageCodec {
-    type Item = TaggedMessage;
-    type Error = Error;
-
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>> {
-        if let Some(frame) = self.length_codec.decode(src).map_err(Error::Io)? {
-            let mut cursor = Cursor::new(frame.as_ref());
-            let message = TaggedMessage::decode(&mut cursor)?;
-            Ok(Some(message))
-        } else {
-            Ok(None)
-        }
-    }
-}
-
-impl Encoder<TaggedMessage> for MessageCodec {
-    type Error = Error;
-
-    fn encode(&mut self, item: TaggedMessage, dst: &mut BytesMut) -> Result<()> {
-        let mut payload = BytesMut::new();
-        item.encode(&mut payload.write_adapter())?;
-        self.length_codec
-            .encode(payload.freeze(), dst)
-            .map_err(Error::Io)?;
-        Ok(())
+        Ok(message)
     }
 }
 
@@ -1301,23 +1710,22 @@ impl Stat {
 }
 
 impl Encodable for Stat {
-    fn encode<W: WriteBytesExt>(&self, w: &mut W) -> Result<usize> {
+    fn encode<W: WireWrite>(&self, w: &mut W) -> Result<usize> {
         // using a temporary buffer to calculate the size
         let mut temp_buf = Vec::new();
-        let mut temp_writer = Cursor::new(&mut temp_buf);
 
         // encode all fields except the size to the temporary buffer
-        self.r#type.encode(&mut temp_writer)?;
-        self.dev.encode(&mut temp_writer)?;
-        self.qid.encode(&mut temp_writer)?;
-        self.mode.encode(&mut temp_writer)?;
-        self.atime.encode(&mut temp_writer)?;
-        self.mtime.encode(&mut temp_writer)?;
-        self.length.encode(&mut temp_writer)?;
-        self.name.encode(&mut temp_writer)?;
-        self.uid.encode(&mut temp_writer)?;
-        self.gid.encode(&mut temp_writer)?;
-        self.muid.encode(&mut temp_writer)?;
+        self.r#type.encode(&mut temp_buf)?;
+        self.dev.encode(&mut temp_buf)?;
+        self.qid.encode(&mut temp_buf)?;
+        self.mode.encode(&mut temp_buf)?;
+        self.atime.encode(&mut temp_buf)?;
+        self.mtime.encode(&mut temp_buf)?;
+        self.length.encode(&mut temp_buf)?;
+        self.name.encode(&mut temp_buf)?;
+        self.uid.encode(&mut temp_buf)?;
+        self.gid.encode(&mut temp_buf)?;
+        self.muid.encode(&mut temp_buf)?;
 
         let total_size =
             u16::try_from(temp_buf.len()).map_err(|_| Error::StringTooLong(temp_buf.len()))?;
@@ -1331,12 +1739,11 @@ impl Encodable for Stat {
 }
 
 impl Decodable for Stat {
-    fn decode<R: ReadBytesExt>(r: &mut R) -> Result<Self> {
+    fn decode<R: WireRead>(r: &mut R) -> Result<Self> {
         let stat_size = u16::decode(r)? as usize;
 
-        let mut stat_data = vec![0u8; stat_size];
-        r.read_exact(&mut stat_data)?;
-        let mut stat_cursor = Cursor::new(&stat_data[..]);
+        let stat_data = read_exact_len(r, stat_size)?;
+        let mut stat_cursor = &stat_data[..];
 
         let r#type = u16::decode(&mut stat_cursor)?;
         let dev = u32::decode(&mut stat_cursor)?;
@@ -1368,5 +1775,55 @@ impl Decodable for Stat {
     }
 }
 
+#[cfg(test)]
+mod message_tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_with_type() {
+        let message = Message::Twalk(Twalk {
+            fid: 1,
+            newfid: 2,
+            wnames: vec!["files".to_string(), "a1b2c3d".to_string()],
+        });
+
+        let mut body = Vec::new();
+        message.encode(&mut body).unwrap();
+        let decoded = Message::decode_with_type(MessageType::Twalk, &mut &body[..]).unwrap();
+        assert_eq!(decoded, message);
+
+        let mut typed = Vec::new();
+        let len = message.encode_with_type(&mut typed).unwrap();
+        assert_eq!(len, body.len() + 1);
+        assert_eq!(typed[0], MessageType::Twalk.to_u8());
+        assert_eq!(&typed[1..], &body[..]);
+    }
+
+    #[test]
+    fn test_decode_with_type_respects_dialect() {
+        let message = Message::Tlauth(Tlauth {
+            afid: 1,
+            uname: "nobody".to_string(),
+            aname: String::new(),
+            n_uname: 0,
+        });
+        let mut body = Vec::new();
+        message.encode(&mut body).unwrap();
+
+        let decoded =
+            Message::decode_with_type_dialect(MessageType::Tauth, &mut &body[..], Dialect::Linux)
+                .unwrap();
+        assert_eq!(decoded, message);
+
+        assert!(matches!(
+            Message::decode_with_type(MessageType::Tgetattr, &mut &[0u8; 12][..]),
+            Err(Error::UnsupportedMessageType(
+                MessageType::Tgetattr,
+                Dialect::Plan9
+            ))
+        ));
+    }
+}
+
 // #[cfg(test)]
 // mod tests;
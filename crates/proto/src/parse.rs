@@ -0,0 +1,1115 @@
+//! Parsing of the one-line trace format produced by the `Display` impls in `fmt`.
+//!
+//! Every line has the form `<name> tag <tag> <fields>`, e.g.
+//! `Twalk tag 1 fid 2 newfid 3 nwname 2 0:files 1:a1b2c3d`. Fields are found by their
+//! keys, so string values may contain spaces as long as they don't contain the key
+//! that follows them. Parts of a message that the trace doesn't show can't be
+//! recovered: binary data longer than 64 bytes is elided, and `Tcreate` permissions
+//! only keep the directory and `rwx` bits.
+use crate::consts::P9_NOFID;
+use crate::error::{Error, Result};
+use crate::{
+    FileMode, Message, OpenMode, Qid, QidType, Rattach, Rauth, Rclunk, Rcreate, Rerror, Rflush,
+    Rfsync, Rgetattr, Rgetlock, Rlcreate, Rlerror, Rlink, Rlock, Rlopen, Rmkdir, Rmknod, Ropen,
+    Rread, Rreaddir, Rreadlink, Rremove, Rrename, Rrenameat, Rsetattr, Rstat, Rstatfs, Rsymlink,
+    Runlinkat, Rversion, Rwalk, Rwrite, Rwstat, Rxattrcreate, Rxattrwalk, Stat, TaggedMessage,
+    Tattach, Tauth, Tclunk, Tcreate, Tflush, Tfsync, Tgetattr, Tgetlock, Tlattach, Tlauth,
+    Tlcreate, Tlink, Tlock, Tlopen, Tmkdir, Tmknod, Topen, Tread, Treaddir, Treadlink, Tremove,
+    Trename, Trenameat, Tsetattr, Tstat, Tstatfs, Tsymlink, Tunlinkat, Tversion, Twalk, Twrite,
+    Twstat, Txattrcreate, Txattrwalk,
+};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use bytes::Bytes;
+use core::str::FromStr;
+use flagset::FlagSet;
+
+/// How the `Display` impl writes a qid whose fields are all "don't touch"
+const DONT_TOUCH_QID: &str = "(ffffffffffffffff 18446744073709551615 dalmA)";
+
+/// Parse a trace with one message per line, such as a test fixture.
+///
+/// Blank lines and lines starting with `#` are skipped.
+/// # Errors
+/// - a line is not a valid message trace; the error names the line number
+pub fn parse_trace(text: &str) -> Result<Vec<TaggedMessage>> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| {
+            line.parse().map_err(|e| match e {
+                Error::Trace(message) => Error::Trace(format!("line {number}: {message}")),
+                e => e,
+            })
+        })
+        .collect()
+}
+
+impl FromStr for TaggedMessage {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest
+            .strip_prefix("tag ")
+            .ok_or_else(|| trace_error(format!("missing tag in `{line}`")))?;
+        let (tag, body) = rest.split_once(' ').unwrap_or((rest, ""));
+        let tag = number(tag)?;
+
+        Ok(TaggedMessage {
+            tag,
+            message: parse_message(name, body.trim())?,
+        })
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn parse_message(name: &str, body: &str) -> Result<Message> {
+    let message = match name {
+        "Tversion" => {
+            let [msize, version] = fields(body, ["msize", "version"])?;
+            Message::Tversion(Tversion {
+                msize: number(msize)?,
+                version: quoted(version)?,
+            })
+        }
+        "Rversion" => {
+            let [msize, version] = fields(body, ["msize", "version"])?;
+            Message::Rversion(Rversion {
+                msize: number(msize)?,
+                version: quoted(version)?,
+            })
+        }
+        "Tauth" => {
+            // the afid is also printed in the position of the fid
+            let [_, afid, uname, aname] = fields(body, ["fid", "afid", "uname", "aname"])?;
+            Message::Tauth(Tauth {
+                afid: fid(afid)?,
+                uname: uname.to_string(),
+                aname: aname.to_string(),
+            })
+        }
+        "Rauth" => {
+            let [aqid] = fields(body, ["qid"])?;
+            Message::Rauth(Rauth { aqid: qid(aqid)? })
+        }
+        "Tattach" => {
+            let [fid_, afid, uname, aname] = fields(body, ["fid", "afid", "uname", "aname"])?;
+            Message::Tattach(Tattach {
+                fid: fid(fid_)?,
+                afid: fid(afid)?,
+                uname: uname.to_string(),
+                aname: aname.to_string(),
+            })
+        }
+        "Rattach" => {
+            let [qid_] = fields(body, ["qid"])?;
+            Message::Rattach(Rattach { qid: qid(qid_)? })
+        }
+        "Rerror" => {
+            let [ename] = fields(body, ["ename"])?;
+            Message::Rerror(Rerror {
+                ename: ename.to_string(),
+            })
+        }
+        "Tflush" => {
+            let [oldtag] = fields(body, ["oldtag"])?;
+            Message::Tflush(Tflush {
+                oldtag: number(oldtag)?,
+            })
+        }
+        "Rflush" => empty(body, Message::Rflush(Rflush))?,
+        "Twalk" => {
+            let [fid_, newfid, nwname] = fields(body, ["fid", "newfid", "nwname"])?;
+            Message::Twalk(Twalk {
+                fid: fid(fid_)?,
+                newfid: fid(newfid)?,
+                wnames: indexed(nwname)?.into_iter().map(str::to_string).collect(),
+            })
+        }
+        "Rwalk" => {
+            let [nwqid] = fields(body, ["nwqid"])?;
+            Message::Rwalk(Rwalk {
+                wqids: indexed(nwqid)?
+                    .into_iter()
+                    .map(qid)
+                    .collect::<Result<_>>()?,
+            })
+        }
+        "Topen" => {
+            let [fid_, mode] = fields(body, ["fid", "mode"])?;
+            Message::Topen(Topen {
+                fid: fid(fid_)?,
+                mode: open_mode(mode)?,
+            })
+        }
+        "Ropen" => {
+            let [qid_, iounit] = fields(body, ["qid", "iounit"])?;
+            Message::Ropen(Ropen {
+                qid: qid(qid_)?,
+                iounit: number(iounit)?,
+            })
+        }
+        "Tcreate" => {
+            let [fid_, name, perm_, mode] = fields(body, ["fid", "name", "perm", "mode"])?;
+            Message::Tcreate(Tcreate {
+                fid: fid(fid_)?,
+                name: name.to_string(),
+                perm: perm(perm_)?,
+                mode: open_mode(mode)?,
+            })
+        }
+        "Rcreate" => {
+            let [qid_, iounit] = fields(body, ["qid", "iounit"])?;
+            Message::Rcreate(Rcreate {
+                qid: qid(qid_)?,
+                iounit: number(iounit)?,
+            })
+        }
+        "Tread" => {
+            let [fid_, offset, count] = fields(body, ["fid", "offset", "count"])?;
+            Message::Tread(Tread {
+                fid: fid(fid_)?,
+                offset: number(offset)?,
+                count: number(count)?,
+            })
+        }
+        "Rread" => {
+            let [count] = fields(body, ["count"])?;
+            Message::Rread(Rread { data: data(count)? })
+        }
+        "Twrite" => {
+            let [fid_, offset, count] = fields(body, ["fid", "offset", "count"])?;
+            Message::Twrite(Twrite {
+                fid: fid(fid_)?,
+                offset: number(offset)?,
+                data: data(count)?,
+            })
+        }
+        "Rwrite" => {
+            let [count] = fields(body, ["count"])?;
+            Message::Rwrite(Rwrite {
+                count: number(count)?,
+            })
+        }
+        "Tclunk" => {
+            let [fid_] = fields(body, ["fid"])?;
+            Message::Tclunk(Tclunk { fid: fid(fid_)? })
+        }
+        "Rclunk" => empty(body, Message::Rclunk(Rclunk))?,
+        "Tremove" => {
+            let [fid_] = fields(body, ["fid"])?;
+            Message::Tremove(Tremove { fid: fid(fid_)? })
+        }
+        "Rremove" => empty(body, Message::Rremove(Rremove))?,
+        "Tstat" => {
+            let [fid_] = fields(body, ["fid"])?;
+            Message::Tstat(Tstat { fid: fid(fid_)? })
+        }
+        "Rstat" => Message::Rstat(Rstat { stat: stat(body)? }),
+        "Twstat" => {
+            let (fid_, stat_) = body
+                .strip_prefix("fid ")
+                .and_then(|rest| rest.split_once(' '))
+                .ok_or_else(|| trace_error(format!("expected `fid` in `{body}`")))?;
+            Message::Twstat(Twstat {
+                fid: fid(fid_)?,
+                stat: stat(stat_)?,
+            })
+        }
+        "Rwstat" => empty(body, Message::Rwstat(Rwstat))?,
+        _ => parse_dotl_message(name, body)?,
+    };
+
+    Ok(message)
+}
+
+#[allow(clippy::too_many_lines)]
+fn parse_dotl_message(name: &str, body: &str) -> Result<Message> {
+    let message = match name {
+        "Tlauth" => {
+            let [afid, uname, aname, n_uname] =
+                fields(body, ["afid", "uname", "aname", "n_uname"])?;
+            Message::Tlauth(Tlauth {
+                afid: fid(afid)?,
+                uname: uname.to_string(),
+                aname: aname.to_string(),
+                n_uname: fid(n_uname)?,
+            })
+        }
+        "Tlattach" => {
+            let [fid_, afid, uname, aname, n_uname] =
+                fields(body, ["fid", "afid", "uname", "aname", "n_uname"])?;
+            Message::Tlattach(Tlattach {
+                fid: fid(fid_)?,
+                afid: fid(afid)?,
+                uname: uname.to_string(),
+                aname: aname.to_string(),
+                n_uname: fid(n_uname)?,
+            })
+        }
+        "Rlerror" => {
+            let [ecode] = fields(body, ["ecode"])?;
+            Message::Rlerror(Rlerror {
+                ecode: number(ecode)?,
+            })
+        }
+        "Tstatfs" => {
+            let [fid_] = fields(body, ["fid"])?;
+            Message::Tstatfs(Tstatfs { fid: fid(fid_)? })
+        }
+        "Rstatfs" => {
+            let [r#type, bsize, blocks, bfree, bavail, files, ffree, fsid, namelen] = fields(
+                body,
+                [
+                    "type", "bsize", "blocks", "bfree", "bavail", "files", "ffree", "fsid",
+                    "namelen",
+                ],
+            )?;
+            Message::Rstatfs(Rstatfs {
+                r#type: number(r#type)?,
+                bsize: number(bsize)?,
+                blocks: number(blocks)?,
+                bfree: number(bfree)?,
+                bavail: number(bavail)?,
+                files: number(files)?,
+                ffree: number(ffree)?,
+                fsid: number(fsid)?,
+                namelen: number(namelen)?,
+            })
+        }
+        "Tlopen" => {
+            let [fid_, flags] = fields(body, ["fid", "flags"])?;
+            Message::Tlopen(Tlopen {
+                fid: fid(fid_)?,
+                flags: octal(flags)?,
+            })
+        }
+        "Rlopen" => {
+            let [qid_, iounit] = fields(body, ["qid", "iounit"])?;
+            Message::Rlopen(Rlopen {
+                qid: qid(qid_)?,
+                iounit: number(iounit)?,
+            })
+        }
+        "Tlcreate" => {
+            let [fid_, name, flags, mode, gid] =
+                fields(body, ["fid", "name", "flags", "mode", "gid"])?;
+            Message::Tlcreate(Tlcreate {
+                fid: fid(fid_)?,
+                name: name.to_string(),
+                flags: octal(flags)?,
+                mode: octal(mode)?,
+                gid: number(gid)?,
+            })
+        }
+        "Rlcreate" => {
+            let [qid_, iounit] = fields(body, ["qid", "iounit"])?;
+            Message::Rlcreate(Rlcreate {
+                qid: qid(qid_)?,
+                iounit: number(iounit)?,
+            })
+        }
+        "Tsymlink" => {
+            let [fid_, name, symtgt, gid] = fields(body, ["fid", "name", "symtgt", "gid"])?;
+            Message::Tsymlink(Tsymlink {
+                fid: fid(fid_)?,
+                name: name.to_string(),
+                symtgt: symtgt.to_string(),
+                gid: number(gid)?,
+            })
+        }
+        "Rsymlink" => {
+            let [qid_] = fields(body, ["qid"])?;
+            Message::Rsymlink(Rsymlink { qid: qid(qid_)? })
+        }
+        "Tmknod" => {
+            let [dfid, name, mode, major, minor, gid] =
+                fields(body, ["dfid", "name", "mode", "major", "minor", "gid"])?;
+            Message::Tmknod(Tmknod {
+                dfid: fid(dfid)?,
+                name: name.to_string(),
+                mode: octal(mode)?,
+                major: number(major)?,
+                minor: number(minor)?,
+                gid: number(gid)?,
+            })
+        }
+        "Rmknod" => {
+            let [qid_] = fields(body, ["qid"])?;
+            Message::Rmknod(Rmknod { qid: qid(qid_)? })
+        }
+        "Trename" => {
+            let [fid_, dfid, name] = fields(body, ["fid", "dfid", "name"])?;
+            Message::Trename(Trename {
+                fid: fid(fid_)?,
+                dfid: fid(dfid)?,
+                name: name.to_string(),
+            })
+        }
+        "Rrename" => empty(body, Message::Rrename(Rrename))?,
+        "Treadlink" => {
+            let [fid_] = fields(body, ["fid"])?;
+            Message::Treadlink(Treadlink { fid: fid(fid_)? })
+        }
+        "Rreadlink" => {
+            let [target] = fields(body, ["target"])?;
+            Message::Rreadlink(Rreadlink {
+                target: target.to_string(),
+            })
+        }
+        "Tgetattr" => {
+            let [fid_, request_mask] = fields(body, ["fid", "request_mask"])?;
+            Message::Tgetattr(Tgetattr {
+                fid: fid(fid_)?,
+                request_mask: hex(request_mask)?,
+            })
+        }
+        "Rgetattr" => {
+            let [valid, qid_, mode, uid, gid, nlink, rdev, size, blksize, blocks, atime, mtime, ctime, btime, gen, data_version] =
+                fields(
+                    body,
+                    [
+                        "valid",
+                        "qid",
+                        "mode",
+                        "uid",
+                        "gid",
+                        "nlink",
+                        "rdev",
+                        "size",
+                        "blksize",
+                        "blocks",
+                        "atime",
+                        "mtime",
+                        "ctime",
+                        "btime",
+                        "gen",
+                        "data_version",
+                    ],
+                )?;
+            let atime = time(atime)?;
+            let mtime = time(mtime)?;
+            let ctime = time(ctime)?;
+            let btime = time(btime)?;
+            Message::Rgetattr(Rgetattr {
+                valid: hex(valid)?,
+                qid: qid(qid_)?,
+                mode: octal(mode)?,
+                uid: number(uid)?,
+                gid: number(gid)?,
+                nlink: number(nlink)?,
+                rdev: number(rdev)?,
+                size: number(size)?,
+                blksize: number(blksize)?,
+                blocks: number(blocks)?,
+                atime_sec: atime.0,
+                atime_nsec: atime.1,
+                mtime_sec: mtime.0,
+                mtime_nsec: mtime.1,
+                ctime_sec: ctime.0,
+                ctime_nsec: ctime.1,
+                btime_sec: btime.0,
+                btime_nsec: btime.1,
+                gen: number(gen)?,
+                data_version: number(data_version)?,
+            })
+        }
+        "Tsetattr" => {
+            let [fid_, valid, mode, uid, gid, size, atime, mtime] = fields(
+                body,
+                [
+                    "fid", "valid", "mode", "uid", "gid", "size", "atime", "mtime",
+                ],
+            )?;
+            let atime = time(atime)?;
+            let mtime = time(mtime)?;
+            Message::Tsetattr(Tsetattr {
+                fid: fid(fid_)?,
+                valid: hex(valid)?,
+                mode: octal(mode)?,
+                uid: number(uid)?,
+                gid: number(gid)?,
+                size: number(size)?,
+                atime_sec: atime.0,
+                atime_nsec: atime.1,
+                mtime_sec: mtime.0,
+                mtime_nsec: mtime.1,
+            })
+        }
+        "Rsetattr" => empty(body, Message::Rsetattr(Rsetattr))?,
+        "Txattrwalk" => {
+            let [fid_, newfid, name] = fields(body, ["fid", "newfid", "name"])?;
+            Message::Txattrwalk(Txattrwalk {
+                fid: fid(fid_)?,
+                newfid: fid(newfid)?,
+                name: name.to_string(),
+            })
+        }
+        "Rxattrwalk" => {
+            let [size] = fields(body, ["size"])?;
+            Message::Rxattrwalk(Rxattrwalk {
+                size: number(size)?,
+            })
+        }
+        "Txattrcreate" => {
+            let [fid_, name, size, flags] = fields(body, ["fid", "name", "size", "flags"])?;
+            Message::Txattrcreate(Txattrcreate {
+                fid: fid(fid_)?,
+                name: name.to_string(),
+                attr_size: number(size)?,
+                flags: number(flags)?,
+            })
+        }
+        "Rxattrcreate" => empty(body, Message::Rxattrcreate(Rxattrcreate))?,
+        "Treaddir" => {
+            let [fid_, offset, count] = fields(body, ["fid", "offset", "count"])?;
+            Message::Treaddir(Treaddir {
+                fid: fid(fid_)?,
+                offset: number(offset)?,
+                count: number(count)?,
+            })
+        }
+        "Rreaddir" => {
+            let [count] = fields(body, ["count"])?;
+            Message::Rreaddir(Rreaddir { data: data(count)? })
+        }
+        "Tfsync" => {
+            let [fid_, datasync] = fields(body, ["fid", "datasync"])?;
+            Message::Tfsync(Tfsync {
+                fid: fid(fid_)?,
+                datasync: number(datasync)?,
+            })
+        }
+        "Rfsync" => empty(body, Message::Rfsync(Rfsync))?,
+        "Tlock" => {
+            let [fid_, r#type, flags, start, length, proc_id, client_id] = fields(
+                body,
+                [
+                    "fid",
+                    "type",
+                    "flags",
+                    "start",
+                    "length",
+                    "proc_id",
+                    "client_id",
+                ],
+            )?;
+            Message::Tlock(Tlock {
+                fid: fid(fid_)?,
+                r#type: number(r#type)?,
+                flags: number(flags)?,
+                start: number(start)?,
+                length: number(length)?,
+                proc_id: number(proc_id)?,
+                client_id: client_id.to_string(),
+            })
+        }
+        "Rlock" => {
+            let [status] = fields(body, ["status"])?;
+            Message::Rlock(Rlock {
+                status: number(status)?,
+            })
+        }
+        "Tgetlock" => {
+            let [fid_, r#type, start, length, proc_id, client_id] = fields(
+                body,
+                ["fid", "type", "start", "length", "proc_id", "client_id"],
+            )?;
+            Message::Tgetlock(Tgetlock {
+                fid: fid(fid_)?,
+                r#type: number(r#type)?,
+                start: number(start)?,
+                length: number(length)?,
+                proc_id: number(proc_id)?,
+                client_id: client_id.to_string(),
+            })
+        }
+        "Rgetlock" => {
+            let [r#type, start, length, proc_id, client_id] =
+                fields(body, ["type", "start", "length", "proc_id", "client_id"])?;
+            Message::Rgetlock(Rgetlock {
+                r#type: number(r#type)?,
+                start: number(start)?,
+                length: number(length)?,
+                proc_id: number(proc_id)?,
+                client_id: client_id.to_string(),
+            })
+        }
+        "Tlink" => {
+            let [dfid, fid_, name] = fields(body, ["dfid", "fid", "name"])?;
+            Message::Tlink(Tlink {
+                dfid: fid(dfid)?,
+                fid: fid(fid_)?,
+                name: name.to_string(),
+            })
+        }
+        "Rlink" => empty(body, Message::Rlink(Rlink))?,
+        "Tmkdir" => {
+            let [dfid, name, mode, gid] = fields(body, ["dfid", "name", "mode", "gid"])?;
+            Message::Tmkdir(Tmkdir {
+                dfid: fid(dfid)?,
+                name: name.to_string(),
+                mode: octal(mode)?,
+                gid: number(gid)?,
+            })
+        }
+        "Rmkdir" => {
+            let [qid_] = fields(body, ["qid"])?;
+            Message::Rmkdir(Rmkdir { qid: qid(qid_)? })
+        }
+        "Trenameat" => {
+            let [olddirfid, oldname, newdirfid, newname] =
+                fields(body, ["olddirfid", "oldname", "newdirfid", "newname"])?;
+            Message::Trenameat(Trenameat {
+                olddirfid: fid(olddirfid)?,
+                oldname: oldname.to_string(),
+                newdirfid: fid(newdirfid)?,
+                newname: newname.to_string(),
+            })
+        }
+        "Rrenameat" => empty(body, Message::Rrenameat(Rrenameat))?,
+        "Tunlinkat" => {
+            let [dirfid, name, flags] = fields(body, ["dirfid", "name", "flags"])?;
+            Message::Tunlinkat(Tunlinkat {
+                dirfid: fid(dirfid)?,
+                name: name.to_string(),
+                flags: number(flags)?,
+            })
+        }
+        "Runlinkat" => empty(body, Message::Runlinkat(Runlinkat))?,
+        _ => return Err(trace_error(format!("unknown message `{name}`"))),
+    };
+
+    Ok(message)
+}
+
+fn trace_error(message: String) -> Error {
+    Error::Trace(message)
+}
+
+/// Split `body` into the values following each of `keys`, which must appear in order.
+/// A value runs until the next key, so only the last one may be empty at the end of the line.
+fn fields<'a, const N: usize>(body: &'a str, keys: [&str; N]) -> Result<[&'a str; N]> {
+    let mut values = [""; N];
+    let mut rest = body;
+
+    for (i, key) in keys.iter().enumerate() {
+        let after = rest
+            .strip_prefix(key)
+            .filter(|after| after.is_empty() || after.starts_with(' '))
+            .ok_or_else(|| trace_error(format!("expected `{key}` in `{body}`")))?;
+        let after = after.strip_prefix(' ').unwrap_or(after);
+
+        match keys.get(i + 1) {
+            Some(next) => {
+                let end = find_key(after, next)
+                    .ok_or_else(|| trace_error(format!("expected `{next}` in `{body}`")))?;
+                values[i] = &after[..end];
+                rest = &after[end + 1..];
+            }
+            None => values[i] = after,
+        }
+    }
+
+    Ok(values)
+}
+
+/// Position of the space before `key` where `key` is a whole word
+fn find_key(haystack: &str, key: &str) -> Option<usize> {
+    let marker = format!(" {key}");
+    haystack.match_indices(&marker).map(|(i, _)| i).find(|&i| {
+        let end = i + marker.len();
+        end == haystack.len() || haystack[end..].starts_with(' ')
+    })
+}
+
+/// The `n 0:a 1:b ...` list used by `Twalk` and `Rwalk`
+fn indexed(value: &str) -> Result<Vec<&str>> {
+    let (count, mut rest) = value.split_once(' ').unwrap_or((value, ""));
+    let count: usize = number(count)?;
+    let mut items = Vec::with_capacity(count.min(crate::consts::P9_MAXWELEM));
+
+    for i in 0..count {
+        let item = rest
+            .strip_prefix(&format!("{i}:"))
+            .ok_or_else(|| trace_error(format!("expected element {i} in `{value}`")))?;
+        let end = item.find(&format!(" {}:", i + 1)).unwrap_or(item.len());
+        items.push(&item[..end]);
+        rest = item[end..].trim_start();
+    }
+
+    if !rest.is_empty() {
+        return Err(trace_error(format!(
+            "more than {count} elements in `{value}`"
+        )));
+    }
+
+    Ok(items)
+}
+
+fn empty(body: &str, message: Message) -> Result<Message> {
+    if body.is_empty() {
+        Ok(message)
+    } else {
+        Err(trace_error(format!("unexpected `{body}`")))
+    }
+}
+
+fn number<T: FromStr>(value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| trace_error(format!("invalid number `{value}`")))
+}
+
+fn octal(value: &str) -> Result<u32> {
+    u32::from_str_radix(value, 8).map_err(|_| trace_error(format!("invalid octal `{value}`")))
+}
+
+fn hex<T: TryFrom<u64>>(value: &str) -> Result<T> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    u64::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|v| T::try_from(v).ok())
+        .ok_or_else(|| trace_error(format!("invalid hex `{value}`")))
+}
+
+fn fid(value: &str) -> Result<u32> {
+    if value == "-1" {
+        Ok(P9_NOFID)
+    } else {
+        number(value)
+    }
+}
+
+/// A `sec.nsec` timestamp
+fn time(value: &str) -> Result<(u64, u64)> {
+    let (sec, nsec) = value
+        .split_once('.')
+        .ok_or_else(|| trace_error(format!("invalid time `{value}`")))?;
+    Ok((number(sec)?, number(nsec)?))
+}
+
+fn quoted(value: &str) -> Result<String> {
+    value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .map(str::to_string)
+        .ok_or_else(|| trace_error(format!("expected a quoted string, got `{value}`")))
+}
+
+/// A qid as written by its `Display` impl, including the surrounding parentheses
+fn qid(value: &str) -> Result<Qid> {
+    let invalid = || trace_error(format!("invalid qid `{value}`"));
+
+    if value == DONT_TOUCH_QID {
+        return Ok(Stat::new_dont_touch().qid);
+    }
+
+    let inner = value
+        .strip_prefix('(')
+        .and_then(|v| v.strip_suffix(')'))
+        .ok_or_else(invalid)?;
+    let mut parts = inner.splitn(3, ' ');
+    let path = parts.next().ok_or_else(invalid)?;
+    let version = parts.next().ok_or_else(invalid)?;
+
+    let mut qtype = FlagSet::<QidType>::default();
+    for c in parts.next().unwrap_or_default().trim().chars() {
+        qtype |= match c {
+            'd' => QidType::Dir,
+            'a' => QidType::Append,
+            'l' => QidType::Exclusive,
+            'm' => QidType::Mount,
+            'A' => QidType::Auth,
+            't' => QidType::Tmp,
+            _ => return Err(invalid()),
+        };
+    }
+
+    Ok(Qid {
+        qtype,
+        version: number(version)?,
+        path: u64::from_str_radix(path, 16).map_err(|_| invalid())?,
+    })
+}
+
+fn open_mode(value: &str) -> Result<FlagSet<OpenMode>> {
+    Ok(FlagSet::<OpenMode>::new(number(value)?)?)
+}
+
+/// The `drwxr-xr-x` form of `Tcreate` permissions
+fn perm(value: &str) -> Result<FlagSet<FileMode>> {
+    const BITS: [FileMode; 9] = [
+        FileMode::OwnerRead,
+        FileMode::OwnerWrite,
+        FileMode::OwnerExec,
+        FileMode::GroupRead,
+        FileMode::GroupWrite,
+        FileMode::GroupExec,
+        FileMode::OtherRead,
+        FileMode::OtherWrite,
+        FileMode::OtherExec,
+    ];
+
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() != 10 {
+        return Err(trace_error(format!("invalid permissions `{value}`")));
+    }
+
+    let mut mode = FlagSet::<FileMode>::default();
+    if chars[0] == 'd' {
+        mode |= FileMode::Dir;
+    }
+    for (c, bit) in chars[1..].iter().zip(BITS) {
+        if *c != '-' {
+            mode |= bit;
+        }
+    }
+
+    Ok(mode)
+}
+
+/// The `count <n> <data>` tail of `Rread`, `Twrite` and `Rreaddir`
+fn data(value: &str) -> Result<Bytes> {
+    let (count, data) = value.split_once(' ').unwrap_or((value, ""));
+    let count: usize = number(count)?;
+
+    let bytes = if let Some(text) = data.strip_prefix('\'') {
+        let text = text
+            .strip_suffix('\'')
+            .ok_or_else(|| trace_error(format!("unterminated data `{data}`")))?;
+        unescape(text)?
+    } else if data.ends_with("more bytes]") {
+        return Err(trace_error(format!(
+            "data of {count} bytes was truncated in the trace"
+        )));
+    } else {
+        let digits: String = data.split_whitespace().collect();
+        hex::decode(digits).map_err(|_| trace_error(format!("invalid data `{data}`")))?
+    };
+
+    if bytes.len() != count {
+        return Err(trace_error(format!(
+            "count {count} doesn't match {} bytes of data",
+            bytes.len()
+        )));
+    }
+
+    Ok(Bytes::from(bytes))
+}
+
+fn unescape(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.bytes();
+
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        bytes.push(match chars.next() {
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(b'\\') => b'\\',
+            Some(b'\'') => b'\'',
+            _ => return Err(trace_error(format!("invalid escape in `{text}`"))),
+        });
+    }
+
+    Ok(bytes)
+}
+
+/// The `stat '<name>' '<uid>' '<gid>' '<muid>' q <qid> m <mode> ...` form of a `Stat`
+fn stat(body: &str) -> Result<Stat> {
+    let [names, qid_, mode, atime, mtime, length, r#type, dev] =
+        fields(body, ["stat", "q", "m", "at", "mt", "l", "t", "d"])?;
+
+    let names: Vec<&str> = names
+        .strip_prefix('\'')
+        .and_then(|n| n.strip_suffix('\''))
+        .map(|n| n.split("' '").collect())
+        .unwrap_or_default();
+    let [name, uid, gid, muid] = names[..] else {
+        return Err(trace_error(format!("expected four names in `{body}`")));
+    };
+
+    Ok(Stat {
+        r#type: if r#type.is_empty() {
+            u16::MAX
+        } else {
+            number(r#type)?
+        },
+        dev: if dev == "-1" { u32::MAX } else { number(dev)? },
+        qid: qid(qid_)?,
+        mode: FlagSet::<FileMode>::new(octal(mode)?)?,
+        atime: if atime.is_empty() {
+            u32::MAX
+        } else {
+            number(atime)?
+        },
+        mtime: if mtime.is_empty() {
+            u32::MAX
+        } else {
+            number(mtime)?
+        },
+        length: if length == "-1" {
+            u64::MAX
+        } else {
+            number(length)?
+        },
+        name: name.to_string(),
+        uid: uid.to_string(),
+        gid: gid.to_string(),
+        muid: muid.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Format each message and parse it back
+    fn assert_round_trip(messages: &[Message]) {
+        for (tag, message) in (0..).zip(messages) {
+            let tagged = TaggedMessage::new(tag, message.clone());
+            let line = tagged.to_string();
+            let parsed: TaggedMessage = line
+                .parse()
+                .unwrap_or_else(|e| panic!("failed to parse `{line}`: {e}"));
+            assert_eq!(parsed, tagged, "`{line}`");
+        }
+    }
+
+    fn qid(path: u64, qtype: QidType) -> Qid {
+        Qid {
+            qtype: qtype.into(),
+            version: 3,
+            path,
+        }
+    }
+
+    #[test]
+    fn test_parse_fixture() {
+        let trace = "
+            # fetch the current version
+            Tversion tag 65535 msize 8192 version '9P2000'
+            Rversion tag 65535 msize 8192 version '9P2000'
+            Tattach tag 1 fid 2 afid -1 uname nobody aname
+            Twalk tag 1 fid 2 newfid 3 nwname 2 0:relay controller 1:version
+            Rwalk tag 1 nwqid 1 0:(0000000000000010 7 d)
+            Rread tag 1 count 8 'a1b2c3d\\n'
+            Rerror tag 1 ename file does not exist
+        ";
+
+        let messages = parse_trace(trace).unwrap();
+        assert_eq!(messages.len(), 7);
+        assert_eq!(
+            messages[2].message,
+            Message::Tattach(Tattach {
+                fid: 2,
+                afid: P9_NOFID,
+                uname: "nobody".to_string(),
+                aname: String::new(),
+            })
+        );
+        assert_eq!(
+            messages[3].message,
+            Message::Twalk(Twalk {
+                fid: 2,
+                newfid: 3,
+                wnames: vec!["relay controller".to_string(), "version".to_string()],
+            })
+        );
+        assert_eq!(
+            messages[5].message,
+            Message::Rread(Rread {
+                data: Bytes::from_static(b"a1b2c3d\n"),
+            })
+        );
+        assert_eq!(
+            messages[6].message,
+            Message::Rerror(Rerror {
+                ename: "file does not exist".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let err = parse_trace("Tclunk tag 1 fid 2\nTclunk tag 1 fid two\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+
+        assert!("Tbogus tag 1".parse::<TaggedMessage>().is_err());
+        assert!("Tclunk fid 1".parse::<TaggedMessage>().is_err());
+        assert!("Rread tag 1 count 3 'ab'".parse::<TaggedMessage>().is_err());
+    }
+
+    #[test]
+    fn test_truncated_data_is_rejected() {
+        let message = TaggedMessage::new(
+            1,
+            Message::Rread(Rread {
+                data: Bytes::from(vec![0u8; 100]),
+            }),
+        );
+        assert!(matches!(
+            message.to_string().parse::<TaggedMessage>(),
+            Err(Error::Trace(_))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_plan9() {
+        let mut stat = Stat::new_dont_touch();
+        stat.name = "a1b2c3d".to_string();
+        stat.mode = FileMode::from_unix_perm(0o644, false);
+
+        let file = Stat {
+            r#type: 0,
+            dev: 0,
+            qid: qid(7, QidType::File),
+            mode: FileMode::from_unix_perm(0o755, true),
+            atime: 1_750_000_000,
+            mtime: 1_750_000_001,
+            length: 0,
+            name: "files".to_string(),
+            uid: "nobody".to_string(),
+            gid: "nobody".to_string(),
+            muid: String::new(),
+        };
+
+        assert_round_trip(&[
+            Message::Tversion(Tversion {
+                msize: 8192,
+                version: "9P2000".to_string(),
+            }),
+            Message::Tauth(Tauth {
+                afid: 1,
+                uname: "nobody".to_string(),
+                aname: String::new(),
+            }),
+            Message::Rauth(Rauth {
+                aqid: qid(1, QidType::Auth),
+            }),
+            Message::Tflush(Tflush { oldtag: 3 }),
+            Message::Rflush(Rflush),
+            Message::Twalk(Twalk {
+                fid: 2,
+                newfid: 3,
+                wnames: Vec::new(),
+            }),
+            Message::Rwalk(Rwalk {
+                wqids: vec![qid(1, QidType::Dir), qid(2, QidType::File)],
+            }),
+            Message::Topen(Topen {
+                fid: 3,
+                mode: OpenMode::Write | OpenMode::Trunc,
+            }),
+            Message::Tcreate(Tcreate {
+                fid: 3,
+                name: "new file".to_string(),
+                perm: FileMode::from_unix_perm(0o640, false),
+                mode: OpenMode::ReadWrite.into(),
+            }),
+            Message::Rcreate(Rcreate {
+                qid: qid(9, QidType::File),
+                iounit: 8168,
+            }),
+            Message::Tread(Tread {
+                fid: 3,
+                offset: 8192,
+                count: 8168,
+            }),
+            Message::Rread(Rread {
+                data: Bytes::from_static(&[0xe9, 0x00, 0x01, 0xff]),
+            }),
+            Message::Twrite(Twrite {
+                fid: 3,
+                offset: 0,
+                data: Bytes::from_static(b"it's a\ttab\\"),
+            }),
+            Message::Rwrite(Rwrite { count: 11 }),
+            Message::Rstat(Rstat { stat: file }),
+            Message::Twstat(Twstat { fid: 3, stat }),
+            Message::Rwstat(Rwstat),
+        ]);
+    }
+
+    #[test]
+    fn test_round_trip_dotl() {
+        assert_round_trip(&[
+            Message::Tlattach(Tlattach {
+                fid: 0,
+                afid: P9_NOFID,
+                uname: "root".to_string(),
+                aname: "/srv".to_string(),
+                n_uname: 0,
+            }),
+            Message::Rlerror(Rlerror { ecode: 2 }),
+            Message::Tlopen(Tlopen {
+                fid: 1,
+                flags: 0o100_002,
+            }),
+            Message::Tmkdir(Tmkdir {
+                dfid: 1,
+                name: "logs".to_string(),
+                mode: 0o755,
+                gid: 100,
+            }),
+            Message::Tgetattr(Tgetattr {
+                fid: 1,
+                request_mask: crate::consts::P9_GETATTR_BASIC,
+            }),
+            Message::Rgetattr(Rgetattr {
+                valid: crate::consts::P9_GETATTR_BASIC,
+                qid: qid(4, QidType::Dir),
+                mode: 0o40_755,
+                uid: 0,
+                gid: 0,
+                nlink: 2,
+                rdev: 0,
+                size: 4096,
+                blksize: 4096,
+                blocks: 8,
+                atime_sec: 1_750_000_000,
+                atime_nsec: 5,
+                mtime_sec: 1_750_000_000,
+                mtime_nsec: 0,
+                ctime_sec: 1_750_000_000,
+                ctime_nsec: 999_999_999,
+                btime_sec: 0,
+                btime_nsec: 0,
+                gen: 0,
+                data_version: 0,
+            }),
+            Message::Treaddir(Treaddir {
+                fid: 1,
+                offset: 0,
+                count: 4096,
+            }),
+            Message::Tlock(Tlock {
+                fid: 1,
+                r#type: 1,
+                flags: 0,
+                start: 0,
+                length: 0,
+                proc_id: 42,
+                client_id: "relay-1".to_string(),
+            }),
+            Message::Trenameat(Trenameat {
+                olddirfid: 1,
+                oldname: "a".to_string(),
+                newdirfid: 1,
+                newname: "b".to_string(),
+            }),
+            Message::Runlinkat(Runlinkat),
+        ]);
+    }
+}
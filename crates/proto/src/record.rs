@@ -0,0 +1,469 @@
+//! Recording of complete 9P sessions and replaying them against a client.
+//!
+//! A [`Recording`] keeps every frame of a connection in the order it was seen, along
+//! with its direction and when it was seen relative to the start of the session. The
+//! frames are kept as raw bytes so that a recording reproduces exactly what was on
+//! the wire, including frames the codec would reject.
+//!
+//! [`ReplayServer`] plays the server side of a recording: it expects the client under
+//! test to send the recorded requests and answers each of them with the recorded
+//! responses.
+use crate::error::{Error, Result};
+use crate::wire::{WireRead, WireWrite};
+use crate::{read_exact_len, Decodable, Dialect, Encodable, Message, MessageCodec, TaggedMessage};
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Framed};
+
+/// Identifies a recording file and its format version
+const MAGIC: &[u8; 8] = b"9prec01\n";
+
+/// Largest frame accepted when reading a recording
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::ClientToServer => write!(f, "->"),
+            Direction::ServerToClient => write!(f, "<-"),
+        }
+    }
+}
+
+/// A single frame, including its `size[4]` prefix
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub direction: Direction,
+    /// Time since the start of the session
+    pub at: Duration,
+    pub data: Bytes,
+}
+
+impl RecordedFrame {
+    /// Decode the frame as it would be read on a connection speaking `dialect`
+    /// # Errors
+    /// - the frame is not a valid message
+    pub fn decode(&self, dialect: Dialect) -> Result<TaggedMessage> {
+        let mut codec = MessageCodec::with_dialect(dialect);
+        let mut buf = BytesMut::from(&self.data[..]);
+        codec
+            .decode(&mut buf)?
+            .ok_or_else(|| Error::Protocol("recorded frame is incomplete".to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Recording {
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    /// Build a recording out of messages, e.g. ones parsed from a trace fixture.
+    /// All frames are recorded at the start of the session.
+    /// # Errors
+    /// - a message cannot be encoded
+    pub fn from_messages<I>(messages: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (Direction, TaggedMessage)>,
+    {
+        let frames = messages
+            .into_iter()
+            .map(|(direction, message)| {
+                let mut body = Vec::new();
+                message.encode(&mut body)?;
+
+                let size =
+                    u32::try_from(body.len() + 4).map_err(|_| Error::BytesTooLong(body.len()))?;
+                let mut data = size.to_le_bytes().to_vec();
+                data.extend_from_slice(&body);
+
+                Ok(RecordedFrame {
+                    direction,
+                    at: Duration::ZERO,
+                    data: Bytes::from(data),
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { frames })
+    }
+
+    /// Decode every frame, following the dialect agreed on in `Rversion`
+    /// # Errors
+    /// - a frame is not a valid message
+    pub fn messages(&self) -> Result<Vec<(Direction, TaggedMessage)>> {
+        let mut dialect = Dialect::default();
+        self.frames
+            .iter()
+            .map(|frame| {
+                let message = frame.decode(dialect)?;
+                if let Message::Rversion(rversion) = &message.message {
+                    dialect = Dialect::from_version(&rversion.version).unwrap_or_default();
+                }
+                Ok((frame.direction, message))
+            })
+            .collect()
+    }
+
+    /// Write the recording in its file format
+    /// # Errors
+    /// - writing fails
+    pub fn write_to<W: WireWrite>(&self, w: &mut W) -> Result<()> {
+        w.write_all(MAGIC)?;
+        for frame in &self.frames {
+            let direction: u8 = match frame.direction {
+                Direction::ClientToServer => 0,
+                Direction::ServerToClient => 1,
+            };
+            let at = u64::try_from(frame.at.as_micros()).unwrap_or(u64::MAX);
+
+            direction.encode(w)?;
+            at.encode(w)?;
+            w.write_all(&frame.data)?;
+        }
+        Ok(())
+    }
+
+    /// Read a recording written by [`Recording::write_to`]
+    /// # Errors
+    /// - the data is not a recording
+    /// - the recording is truncated
+    pub fn read_from<R: WireRead>(r: &mut R) -> Result<Self> {
+        let magic = read_exact_len(r, MAGIC.len())?;
+        if magic != MAGIC {
+            return Err(Error::Protocol("not a 9p session recording".to_string()));
+        }
+
+        let mut frames = Vec::new();
+        loop {
+            let direction = match r.read_u8() {
+                Ok(0) => Direction::ClientToServer,
+                Ok(1) => Direction::ServerToClient,
+                Ok(other) => {
+                    return Err(Error::Protocol(format!(
+                        "invalid direction {other} in recording"
+                    )))
+                }
+                Err(Error::InsufficientData { actual: 0, .. }) => break,
+                Err(e) => return Err(e),
+            };
+            let at = Duration::from_micros(u64::decode(r)?);
+
+            let size = u32::decode(r)?;
+            let len = size as usize;
+            if !(7..=MAX_FRAME).contains(&len) {
+                return Err(Error::Protocol(format!(
+                    "invalid frame size {size} in recording"
+                )));
+            }
+            let mut data = size.to_le_bytes().to_vec();
+            data.extend(read_exact_len(r, len - 4)?);
+
+            frames.push(RecordedFrame {
+                direction,
+                at,
+                data: Bytes::from(data),
+            });
+        }
+
+        Ok(Self { frames })
+    }
+}
+
+/// Builds a [`Recording`] from the bytes seen on a connection, however they were split up
+#[derive(Debug)]
+pub struct Recorder {
+    start: Instant,
+    client: BytesMut,
+    server: BytesMut,
+    recording: Recording,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            client: BytesMut::new(),
+            server: BytesMut::new(),
+            recording: Recording::default(),
+        }
+    }
+
+    /// Add bytes seen travelling in `direction`. Each frame is timestamped when its
+    /// last byte arrives.
+    pub fn record(&mut self, direction: Direction, data: &[u8]) {
+        let buf = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+        buf.extend_from_slice(data);
+
+        while buf.len() >= 4 {
+            let size = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+            if buf.len() < size.max(4) {
+                break;
+            }
+            self.recording.frames.push(RecordedFrame {
+                direction,
+                at: self.start.elapsed(),
+                data: buf.split_to(size.max(4)).freeze(),
+            });
+        }
+    }
+
+    /// The frames recorded so far; partial frames are left out
+    #[must_use]
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+/// Plays the server side of a [`Recording`] against a client
+#[derive(Debug)]
+pub struct ReplayServer {
+    recording: Recording,
+    realtime: bool,
+}
+
+impl ReplayServer {
+    #[must_use]
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            realtime: false,
+        }
+    }
+
+    /// Wait before each response for as long as the original server took,
+    /// instead of answering immediately
+    #[must_use]
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Serve the recording on `stream` until every recorded frame has been played.
+    /// # Errors
+    /// - the client sends a message other than the recorded one
+    /// - the client disconnects before the end of the recording
+    pub async fn run<S>(self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut conn = Framed::new(stream, MessageCodec::new());
+        let mut dialect = Dialect::default();
+        let mut last = Duration::ZERO;
+
+        for (index, frame) in self.recording.frames.iter().enumerate() {
+            let expected = frame.decode(dialect)?;
+
+            match frame.direction {
+                Direction::ClientToServer => {
+                    let actual = conn.next().await.ok_or_else(|| {
+                        Error::Protocol(format!(
+                            "client disconnected, expected frame {index}: {expected}"
+                        ))
+                    })??;
+                    if actual != expected {
+                        return Err(Error::Protocol(format!(
+                            "frame {index} differs from the recording\n  expected: {expected}\n  actual:   {actual}"
+                        )));
+                    }
+                }
+                Direction::ServerToClient => {
+                    if self.realtime {
+                        tokio::time::sleep(frame.at.saturating_sub(last)).await;
+                    }
+                    // written raw so that the client sees exactly the recorded bytes
+                    conn.get_mut().write_all(&frame.data).await?;
+                }
+            }
+            last = frame.at;
+
+            if let Message::Rversion(rversion) = &expected.message {
+                dialect = Dialect::from_version(&rversion.version).unwrap_or_default();
+                conn.codec_mut().set_dialect(dialect);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_trace, Tclunk};
+    use futures::SinkExt;
+
+    const DATA_LS_CLIENT: &[u8] = include_bytes!("./testdata/ls-client.9p");
+    const DATA_LS_SERVER: &[u8] = include_bytes!("./testdata/ls-server.9p");
+
+    /// The `ls` capture as one session, each request followed by its response
+    fn ls_recording() -> Recording {
+        let mut client = Recorder::new();
+        client.record(Direction::ClientToServer, DATA_LS_CLIENT);
+        let mut server = Recorder::new();
+        server.record(Direction::ServerToClient, DATA_LS_SERVER);
+
+        let frames = client
+            .finish()
+            .frames
+            .into_iter()
+            .zip(server.finish().frames)
+            .flat_map(|(request, response)| [request, response])
+            .collect();
+        Recording { frames }
+    }
+
+    /// Send every recorded request and collect the responses
+    async fn run_client<S>(stream: S, requests: Vec<TaggedMessage>) -> Result<Vec<TaggedMessage>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut conn = Framed::new(stream, MessageCodec::new());
+        let mut responses = Vec::new();
+        for request in requests {
+            conn.send(request).await?;
+            match conn.next().await {
+                Some(response) => responses.push(response?),
+                None => break,
+            }
+        }
+        Ok(responses)
+    }
+
+    fn split(recording: &Recording, direction: Direction) -> Vec<TaggedMessage> {
+        recording
+            .messages()
+            .unwrap()
+            .into_iter()
+            .filter(|(d, _)| *d == direction)
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    #[test]
+    fn test_recording_file_round_trip() {
+        let mut recording = ls_recording();
+        for (i, frame) in (0u64..).zip(recording.frames.iter_mut()) {
+            frame.at = Duration::from_micros(i * 1500);
+        }
+
+        let mut file = Vec::new();
+        recording.write_to(&mut file).unwrap();
+        assert_eq!(Recording::read_from(&mut &file[..]).unwrap(), recording);
+
+        assert!(Recording::read_from(&mut &file[..file.len() - 1]).is_err());
+        assert!(Recording::read_from(&mut &b"not a recording"[..]).is_err());
+    }
+
+    #[test]
+    fn test_recorder_reassembles_frames() {
+        let mut recorder = Recorder::new();
+        for chunk in DATA_LS_CLIENT.chunks(5) {
+            recorder.record(Direction::ClientToServer, chunk);
+        }
+
+        let messages = recorder.finish().messages().unwrap();
+        assert_eq!(messages.len(), 12);
+        assert!(messages
+            .iter()
+            .all(|(direction, _)| *direction == Direction::ClientToServer));
+    }
+
+    #[test]
+    fn test_replay_serves_recorded_responses() {
+        let recording = ls_recording();
+        let requests = split(&recording, Direction::ClientToServer);
+        let expected = split(&recording, Direction::ServerToClient);
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (replayed, responses) = tokio_test::block_on(async {
+            futures::join!(
+                ReplayServer::new(recording).run(server),
+                run_client(client, requests)
+            )
+        });
+
+        replayed.unwrap();
+        assert_eq!(responses.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_replay_rejects_unexpected_request() {
+        let recording = ls_recording();
+        let mut requests = split(&recording, Direction::ClientToServer);
+        requests[4] = TaggedMessage::new(0, Message::Tclunk(Tclunk { fid: 7 }));
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (replayed, _) = tokio_test::block_on(async {
+            futures::join!(
+                // dropping the server end on error hangs up on the client
+                ReplayServer::new(recording).run(server),
+                run_client(client, requests)
+            )
+        });
+
+        let err = replayed.unwrap_err().to_string();
+        assert!(err.contains("frame 8"), "{err}");
+        assert!(err.contains("Tclunk tag 0 fid 7"), "{err}");
+    }
+
+    #[test]
+    fn test_replay_from_trace_fixture() {
+        let trace = parse_trace(
+            "
+            Tversion tag 65535 msize 8192 version '9P2000.L'
+            Rversion tag 65535 msize 8192 version '9P2000.L'
+            Tlattach tag 1 fid 0 afid -1 uname nobody aname  n_uname 65534
+            Rlerror tag 1 ecode 13
+            ",
+        )
+        .unwrap();
+        let directions = [Direction::ClientToServer, Direction::ServerToClient]
+            .into_iter()
+            .cycle();
+        let recording = Recording::from_messages(directions.zip(trace.clone())).unwrap();
+
+        let requests = [trace[0].clone(), trace[2].clone()];
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (replayed, responses) = tokio_test::block_on(async {
+            futures::join!(ReplayServer::new(recording).run(server), async {
+                let mut conn = Framed::new(client, MessageCodec::new());
+                conn.send(requests[0].clone()).await?;
+                let rversion = conn.next().await.unwrap()?;
+                conn.codec_mut().set_dialect(Dialect::Linux);
+                conn.send(requests[1].clone()).await?;
+                let rlerror = conn.next().await.unwrap()?;
+                Ok::<_, Error>(vec![rversion, rlerror])
+            })
+        });
+
+        replayed.unwrap();
+        assert_eq!(responses.unwrap(), vec![trace[1].clone(), trace[3].clone()]);
+    }
+}
@@ -0,0 +1,297 @@
+//! 9P over TLS.
+//!
+//! Update servers run on the local network with self-signed certificates, so instead of
+//! validating a chain to a CA the client pins the SHA-256 [`Fingerprint`] of the
+//! server's certificate. The TLS handshake itself is still verified against that
+//! certificate's key, so only the holder of the pinned key can serve the connection.
+use crate::error::{Error, Result};
+use crate::transport::{connection, Connection, Transport};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, ServerConfig, SignatureScheme};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// SHA-256 of a DER encoded certificate
+pub type Fingerprint = [u8; 32];
+
+/// Compute the fingerprint clients pin for `cert`
+#[must_use]
+pub fn fingerprint(cert: &CertificateDer<'_>) -> Fingerprint {
+    Sha256::digest(cert.as_ref()).into()
+}
+
+/// Parse a fingerprint written as hex, optionally with `:` between bytes
+/// # Errors
+/// - the text is not 32 bytes of hex
+pub fn parse_fingerprint(text: &str) -> Result<Fingerprint> {
+    let digits: String = text.trim().chars().filter(|c| *c != ':').collect();
+    let mut fingerprint = [0; 32];
+    hex::decode_to_slice(&digits, &mut fingerprint)
+        .map_err(|e| Error::Tls(format!("invalid certificate fingerprint {text:?}: {e}")))?;
+    Ok(fingerprint)
+}
+
+/// Accepts exactly the server certificate with the pinned fingerprint
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    fingerprint: Fingerprint,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        if fingerprint(end_entity) == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Client configuration that trusts only the certificate with `fingerprint`
+/// # Errors
+/// - the crypto provider doesn't support the default protocol versions
+pub fn client_config(fingerprint: Fingerprint) -> Result<Arc<ClientConfig>> {
+    let provider = Arc::new(ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier {
+            fingerprint,
+            provider,
+        }))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Server configuration presenting `cert_chain`, which starts with the pinned certificate
+/// # Errors
+/// - `key` doesn't match the certificate or isn't supported
+pub fn server_config(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> Result<Arc<ServerConfig>> {
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .map_err(tls_error)?;
+    Ok(Arc::new(config))
+}
+
+/// Load a server configuration from PEM files
+/// # Errors
+/// - a file can't be read or holds no certificate or key
+/// - the key doesn't match the certificate
+pub fn server_config_from_pem(cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>> {
+    let cert_chain = CertificateDer::pem_file_iter(cert_path)
+        .and_then(Iterator::collect::<std::result::Result<Vec<_>, _>>)
+        .map_err(|e| Error::Tls(format!("{}: {e}", cert_path.display())))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| Error::Tls(format!("{}: {e}", key_path.display())))?;
+    server_config(cert_chain, key)
+}
+
+/// Open a TLS connection to the 9P server at `addr` (`host:port`)
+/// # Errors
+/// - the server can't be reached
+/// - the server's certificate doesn't match `fingerprint`
+pub async fn connect(addr: &str, fingerprint: Fingerprint) -> Result<Connection> {
+    let stream = TcpStream::connect(addr).await?;
+    let stream = handshake(stream, addr, fingerprint).await?;
+    Ok(connection(stream))
+}
+
+/// Run the client side of the TLS handshake over an established stream
+/// # Errors
+/// - the server's certificate doesn't match `fingerprint`
+pub async fn handshake<T: Transport + 'static>(
+    stream: T,
+    addr: &str,
+    fingerprint: Fingerprint,
+) -> Result<impl Transport> {
+    let host = addr.rsplit_once(':').map_or(addr, |(host, _)| host);
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| Error::Tls(format!("invalid server name {host:?}: {e}")))?;
+
+    let connector = TlsConnector::from(client_config(fingerprint)?);
+    Ok(connector.connect(server_name, stream).await?)
+}
+
+/// Server side of TLS for 9P listeners
+#[derive(Clone)]
+pub struct Acceptor {
+    acceptor: TlsAcceptor,
+}
+
+impl Acceptor {
+    #[must_use]
+    pub fn new(config: Arc<ServerConfig>) -> Self {
+        Self {
+            acceptor: TlsAcceptor::from(config),
+        }
+    }
+
+    /// Run the server side of the TLS handshake on an accepted stream
+    /// # Errors
+    /// - the handshake fails
+    pub async fn accept<T: Transport + 'static>(&self, stream: T) -> Result<impl Transport> {
+        Ok(self.acceptor.accept(stream).await?)
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn tls_error(e: rustls::Error) -> Error {
+    Error::Tls(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Direction, Message, Recording, ReplayServer, TaggedMessage, Tversion};
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+
+    fn self_signed() -> (CertificateDer<'static>, PrivateKeyDer<'static>) {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        (
+            cert.cert.der().clone(),
+            PrivateKeyDer::try_from(cert.key_pair.serialize_der()).unwrap(),
+        )
+    }
+
+    fn version(msize: u32) -> Message {
+        Message::Tversion(Tversion {
+            msize,
+            version: "9P2000".to_string(),
+        })
+    }
+
+    /// Serve a single connection over TLS that answers a Tversion
+    async fn serve_once(listener: TcpListener, acceptor: Acceptor) -> crate::error::Result<()> {
+        let recording = Recording::from_messages([
+            (
+                Direction::ClientToServer,
+                TaggedMessage::new(0xFFFF, version(8192)),
+            ),
+            (
+                Direction::ServerToClient,
+                TaggedMessage::new(
+                    0xFFFF,
+                    Message::Rversion(crate::Rversion {
+                        msize: 8192,
+                        version: "9P2000".to_string(),
+                    }),
+                ),
+            ),
+        ])?;
+        let (stream, _) = listener.accept().await?;
+        let stream = acceptor.accept(stream).await?;
+        ReplayServer::new(recording).run(stream).await
+    }
+
+    #[test]
+    fn test_pinned_connection() {
+        tokio_test::block_on(async {
+            let (cert, key) = self_signed();
+            let pin = fingerprint(&cert);
+            let acceptor = Acceptor::new(server_config(vec![cert], key).unwrap());
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = format!("localhost:{}", listener.local_addr().unwrap().port());
+
+            let client = async {
+                let mut conn = connect(&addr, pin).await.unwrap();
+                conn.send(TaggedMessage::new(0xFFFF, version(8192)))
+                    .await
+                    .unwrap();
+                conn.next().await.unwrap().unwrap()
+            };
+            let (served, response) = futures::join!(serve_once(listener, acceptor), client);
+
+            served.unwrap();
+            assert!(matches!(response.message, Message::Rversion(_)));
+        });
+    }
+
+    #[test]
+    fn test_rejects_unpinned_certificate() {
+        tokio_test::block_on(async {
+            let (cert, key) = self_signed();
+            let (other, _) = self_signed();
+            let acceptor = Acceptor::new(server_config(vec![cert], key).unwrap());
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = format!("localhost:{}", listener.local_addr().unwrap().port());
+
+            let (served, client) = futures::join!(
+                serve_once(listener, acceptor),
+                connect(&addr, fingerprint(&other))
+            );
+
+            assert!(client.is_err());
+            assert!(served.is_err());
+        });
+    }
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let (cert, _) = self_signed();
+        let pin = fingerprint(&cert);
+
+        assert_eq!(parse_fingerprint(&hex::encode(pin)).unwrap(), pin);
+        let colons: Vec<String> = pin.iter().map(|b| format!("{b:02X}")).collect();
+        assert_eq!(parse_fingerprint(&colons.join(":")).unwrap(), pin);
+        assert!(parse_fingerprint("abcd").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//! Byte streams that 9P connections run over.
+//!
+//! 9P only needs a reliable, ordered stream underneath [`MessageCodec`]. Clients and
+//! servers hold a [`Connection`] so that the same message handling works whether the
+//! stream is plain TCP or TLS (see the `tls` feature).
+use crate::error::Result;
+use crate::MessageCodec;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_util::codec::Framed;
+
+/// A byte stream that can carry 9P messages
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+/// 9P messages framed over any [`Transport`]
+pub type Connection = Framed<Box<dyn Transport>, MessageCodec>;
+
+/// Frame `transport` with a default [`MessageCodec`]
+pub fn connection(transport: impl Transport + 'static) -> Connection {
+    Framed::new(Box::new(transport), MessageCodec::new())
+}
+
+/// Open a plaintext connection to a 9P server
+/// # Errors
+/// - the server can't be reached
+pub async fn connect_tcp(addr: impl ToSocketAddrs) -> Result<Connection> {
+    let stream = TcpStream::connect(addr).await?;
+    Ok(connection(stream))
+}
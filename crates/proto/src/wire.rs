@@ -0,0 +1,167 @@
+//! Byte sources and sinks that messages are decoded from and encoded into.
+//!
+//! [`WireRead`] and [`WireWrite`] stand in for `std::io::Read` and `std::io::Write` so
+//! that [`Encodable`](crate::Encodable) and [`Decodable`](crate::Decodable) work
+//! without `std`. With the `std` feature every `std::io` reader and writer implements
+//! them; without it byte slices can be read from and `Vec<u8>` written to.
+use crate::error::{Error, Result};
+#[cfg(not(feature = "std"))]
+use alloc::vec::Vec;
+
+/// Source of little-endian 9P fields
+pub trait WireRead {
+    /// Read up to `buf.len()` bytes, returning how many were read and 0 once there is
+    /// no more data
+    /// # Errors
+    /// - the underlying source fails
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Fill `buf` completely
+    /// # Errors
+    /// - the data ends before `buf` is full
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..])? {
+                0 => {
+                    return Err(Error::InsufficientData {
+                        expected: buf.len(),
+                        actual: filled,
+                    })
+                }
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    /// # Errors
+    /// - the data ends early
+    fn read_u8(&mut self) -> Result<u8> {
+        let mut buf = [0; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// # Errors
+    /// - the data ends early
+    fn read_u16(&mut self) -> Result<u16> {
+        let mut buf = [0; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    /// # Errors
+    /// - the data ends early
+    fn read_u32(&mut self) -> Result<u32> {
+        let mut buf = [0; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// # Errors
+    /// - the data ends early
+    fn read_u64(&mut self) -> Result<u64> {
+        let mut buf = [0; 8];
+        self.read_exact(&mut buf)?;
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+/// Sink for little-endian 9P fields
+pub trait WireWrite {
+    /// Write all of `buf`
+    /// # Errors
+    /// - the underlying sink fails
+    fn write_all(&mut self, buf: &[u8]) -> Result<()>;
+
+    /// # Errors
+    /// - the underlying sink fails
+    fn write_u8(&mut self, val: u8) -> Result<()> {
+        self.write_all(&[val])
+    }
+
+    /// # Errors
+    /// - the underlying sink fails
+    fn write_u16(&mut self, val: u16) -> Result<()> {
+        self.write_all(&val.to_le_bytes())
+    }
+
+    /// # Errors
+    /// - the underlying sink fails
+    fn write_u32(&mut self, val: u32) -> Result<()> {
+        self.write_all(&val.to_le_bytes())
+    }
+
+    /// # Errors
+    /// - the underlying sink fails
+    fn write_u64(&mut self, val: u64) -> Result<()> {
+        self.write_all(&val.to_le_bytes())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read + ?Sized> WireRead for R {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            match std::io::Read::read(self, buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                result => return Ok(result?),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write + ?Sized> WireWrite for W {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        Ok(std::io::Write::write_all(self, buf)?)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl WireRead for &[u8] {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = buf.len().min(self.len());
+        let (head, rest) = self.split_at(n);
+        buf[..n].copy_from_slice(head);
+        *self = rest;
+        Ok(n)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl WireWrite for Vec<u8> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_little_endian_round_trip() {
+        let mut buf = Vec::new();
+        buf.write_u8(1).unwrap();
+        buf.write_u16(0x0302).unwrap();
+        buf.write_u32(0x0706_0504).unwrap();
+        buf.write_u64(0x0f0e_0d0c_0b0a_0908).unwrap();
+        assert_eq!(buf, (1..16).collect::<Vec<u8>>());
+
+        let mut r = &buf[..];
+        assert_eq!(r.read_u8().unwrap(), 1);
+        assert_eq!(r.read_u16().unwrap(), 0x0302);
+        assert_eq!(r.read_u32().unwrap(), 0x0706_0504);
+        assert_eq!(r.read_u64().unwrap(), 0x0f0e_0d0c_0b0a_0908);
+        assert!(matches!(
+            r.read_u8(),
+            Err(Error::InsufficientData {
+                expected: 1,
+                actual: 0
+            })
+        ));
+    }
+}
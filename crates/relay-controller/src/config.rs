@@ -6,6 +6,16 @@ pub struct Config {
     pub hostname: Option<String>,
     pub wifi_ssid: String,
     pub wifi_pass: String,
+    /// Shared secret used to authenticate to the update server
+    pub ota_secret: Option<String>,
+    /// Shared secret that images pushed over HTTP are authenticated with
+    pub ota_push_secret: Option<String>,
+    /// SHA-256 of the update server's certificate, as hex
+    pub ota_fingerprint: Option<String>,
+    /// Public half of the release key firmware images must be signed with, as hex
+    pub ota_public_key: Option<String>,
+    /// Release channel to follow, `stable` unless set
+    pub ota_channel: Option<String>,
 }
 
 impl Config {
@@ -15,6 +25,11 @@ impl Config {
         let password_key = "password";
         let device_namespace = EspNvs::new(partition, "device", false)?;
         let hostname_key = "hostname";
+        let ota_secret_key = "ota_secret";
+        let ota_push_secret_key = "ota_push_secret";
+        let ota_fingerprint_key = "ota_cert_sha256";
+        let ota_public_key_key = "ota_public_key";
+        let ota_channel_key = "ota_channel";
 
         let mut buf = [0; 100];
         let wifi_ssid = {
@@ -40,11 +55,41 @@ impl Config {
                 .get_str(hostname_key, &mut buf)?
                 .map(String::from)
         };
+        let ota_secret = {
+            device_namespace
+                .get_str(ota_secret_key, &mut buf)?
+                .map(String::from)
+        };
+        let ota_push_secret = {
+            device_namespace
+                .get_str(ota_push_secret_key, &mut buf)?
+                .map(String::from)
+        };
+        let ota_fingerprint = {
+            device_namespace
+                .get_str(ota_fingerprint_key, &mut buf)?
+                .map(String::from)
+        };
+        let ota_public_key = {
+            device_namespace
+                .get_str(ota_public_key_key, &mut buf)?
+                .map(String::from)
+        };
+        let ota_channel = {
+            device_namespace
+                .get_str(ota_channel_key, &mut buf)?
+                .map(String::from)
+        };
 
         Ok(Self {
             hostname,
             wifi_ssid,
             wifi_pass,
+            ota_secret,
+            ota_push_secret,
+            ota_fingerprint,
+            ota_public_key,
+            ota_channel,
         })
     }
 }
@@ -1,13 +1,22 @@
+use firmware::policy::Refusal;
+use stowage_proto::{error::ErrorKind, Rerror};
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error(transparent)]
     Esp(#[from] esp_idf_svc::sys::EspError),
     #[error("missing firmware info for running slot")]
     FirmwareInfoMissing,
+    #[error(transparent)]
+    Firmware(#[from] firmware::error::Error),
     #[error("hostname is too long")]
     HostnameTooLong,
+    #[error("invalid settings: {0}")]
+    InvalidSettings(String),
     #[error("missing required configuration: {0}")]
     MissingConfig(String),
+    #[error("file not found: {0}")]
+    NotFound(String),
     #[error(transparent)]
     StdIo(#[from] std::io::Error),
     #[error("wifi ssid is too long")]
@@ -16,12 +25,50 @@ pub enum Error {
     StowageProto(#[from] stowage_proto::error::Error),
     #[error("wifi password is too long")]
     PasswordTooLong,
-    #[error("the upstream version can't be compared to the current firmware's")]
-    UpstreamVersionInvalid,
+    #[error("not installing {version}: {refusal}")]
+    Refused { version: String, refusal: Refusal },
+    #[error("connection closed")]
+    ConnectionClosed,
     #[error("error writing esp update")]
     EspUpdateError,
+    #[error("{context}: {ename}")]
+    Remote {
+        context: &'static str,
+        kind: ErrorKind,
+        ename: String,
+    },
     #[error("{0}")]
     Other(String),
 }
 
+impl Error {
+    /// An `Rerror` from a 9P server, received while doing `context`
+    pub fn remote(context: &'static str, err: Rerror) -> Self {
+        Error::Remote {
+            context,
+            kind: err.kind(),
+            ename: err.ename,
+        }
+    }
+
+    /// What went wrong on the server, if the error came from one
+    pub fn remote_kind(&self) -> Option<ErrorKind> {
+        match self {
+            Error::Remote { kind, .. } => Some(*kind),
+            Error::StowageProto(err) => err.kind(),
+            _ => None,
+        }
+    }
+
+    /// Whether the connection was lost, so that the same request may succeed on a
+    /// new one
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::StdIo(_) | Error::ConnectionClosed => true,
+            Error::StowageProto(err) => matches!(err, stowage_proto::error::Error::Io(_)),
+            _ => false,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
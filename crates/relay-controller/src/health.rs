@@ -0,0 +1,230 @@
+//! Confirms that a freshly installed firmware works before the bootloader gives up
+//! the previous one.
+//!
+//! With rollback enabled in the bootloader, the first boot of a new image runs in an
+//! unverified slot. If [`BootHealth::confirm`] sees Wi-Fi and the HTTP server come up
+//! within [`DEADLINE`], and every relay pin reads back the state it was set to, the
+//! slot is marked valid; otherwise it is marked invalid and the board reboots into the
+//! previous image. A crash or reset before then rolls back as well. Every boot is
+//! recorded in NVS.
+use crate::{
+    error::{Error, Result},
+    ota::{running_slot, running_version},
+    relay::RelayController,
+    wifi::WifiState,
+};
+use esp_idf_svc::{
+    nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault},
+    ota::EspOta,
+    timer::{EspTimerService, Task},
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// How long a new image has to become healthy
+const DEADLINE: Duration = Duration::from_secs(180);
+
+/// How often the checks are repeated until then
+const POLL: Duration = Duration::from_secs(2);
+
+const NAMESPACE: &str = "device";
+const KEY: &str = "boot_history";
+
+/// Boots kept in the history
+const HISTORY_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootEvent {
+    /// The slot was already valid
+    Valid,
+    /// A new image passed the health check
+    Confirmed,
+    /// A new image failed the health check and the previous one is being restored
+    RolledBack,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootRecord {
+    /// Counts every recorded boot
+    pub boot: u32,
+    pub slot: String,
+    pub version: String,
+    pub event: BootEvent,
+    /// The checks that failed, for a rollback
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failing: Vec<String>,
+}
+
+impl fmt::Display for BootRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let event = match self.event {
+            BootEvent::Valid => "valid",
+            BootEvent::Confirmed => "confirmed",
+            BootEvent::RolledBack => "rolled back",
+        };
+        write!(f, "{} {} {} {event}", self.boot, self.slot, self.version)?;
+        if !self.failing.is_empty() {
+            write!(f, ": {} failed", self.failing.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The last [`HISTORY_LEN`] boots, oldest first
+pub struct BootHistory {
+    nvs: Mutex<EspNvs<NvsDefault>>,
+    records: Mutex<Vec<BootRecord>>,
+}
+
+impl BootHistory {
+    pub fn load(partition: EspDefaultNvsPartition) -> Result<Self> {
+        let nvs = EspNvs::new(partition, NAMESPACE, true)?;
+        let records = match nvs.str_len(KEY)? {
+            Some(len) => {
+                let mut buf = vec![0; len];
+                let stored = nvs.get_str(KEY, &mut buf)?.unwrap_or_default();
+                serde_json::from_str(stored).unwrap_or_else(|e| {
+                    warn!("discarding unreadable boot history: {e}");
+                    Vec::new()
+                })
+            }
+            None => Vec::new(),
+        };
+        Ok(Self {
+            nvs: Mutex::new(nvs),
+            records: Mutex::new(records),
+        })
+    }
+
+    pub fn records(&self) -> Vec<BootRecord> {
+        self.records
+            .lock()
+            .map(|records| records.clone())
+            .unwrap_or_default()
+    }
+
+    fn record(
+        &self,
+        slot: String,
+        version: String,
+        event: BootEvent,
+        failing: Vec<String>,
+    ) -> Result<()> {
+        let poisoned = || Error::Other("boot history is poisoned".into());
+        let mut records = self.records.lock().map_err(|_| poisoned())?;
+        let boot = records
+            .last()
+            .map_or(0, |record| record.boot.wrapping_add(1));
+        let record = BootRecord {
+            boot,
+            slot,
+            version,
+            event,
+            failing,
+        };
+        info!("boot {record}");
+        records.push(record);
+        let excess = records.len().saturating_sub(HISTORY_LEN);
+        records.drain(..excess);
+
+        let json = serde_json::to_string(&*records).map_err(|e| Error::Other(e.to_string()))?;
+        self.nvs
+            .lock()
+            .map_err(|_| poisoned())?
+            .set_str(KEY, &json)?;
+        Ok(())
+    }
+}
+
+/// What has to work for a boot to count as healthy
+pub struct BootHealth {
+    wifi_state: Arc<WifiState>,
+    relay_controller: Arc<RelayController>,
+    http_bound: AtomicBool,
+    history: BootHistory,
+}
+
+impl BootHealth {
+    pub fn new(
+        wifi_state: Arc<WifiState>,
+        relay_controller: Arc<RelayController>,
+        history: BootHistory,
+    ) -> Self {
+        Self {
+            wifi_state,
+            relay_controller,
+            http_bound: AtomicBool::new(false),
+            history,
+        }
+    }
+
+    /// Called by the HTTP server once it listens
+    pub fn set_http_bound(&self) {
+        self.http_bound.store(true, Ordering::SeqCst);
+    }
+
+    pub fn history(&self) -> &BootHistory {
+        &self.history
+    }
+
+    /// Names of the checks that don't pass yet
+    async fn failing(&self) -> Vec<String> {
+        let mut failing = Vec::new();
+        if self.wifi_state.ip_addr().await.is_none() {
+            failing.push("wifi".to_string());
+        }
+        if !self.http_bound.load(Ordering::SeqCst) {
+            failing.push("http".to_string());
+        }
+        let mismatched = self.relay_controller.mismatched();
+        if !mismatched.is_empty() {
+            failing.push(format!("relays {mismatched:?}"));
+        }
+        failing
+    }
+
+    /// Wait for the running image to become healthy if it hasn't been confirmed yet,
+    /// then mark it valid or roll back. Returns once the slot is valid.
+    /// # Errors
+    /// - the slot can't be read or marked
+    /// - the image is unhealthy and rolling back failed
+    pub async fn confirm(&self, ota: &mut EspOta, timer: &EspTimerService<Task>) -> Result<()> {
+        let (label, unverified) = running_slot()?;
+        let version = running_version();
+
+        if !unverified {
+            return self
+                .history
+                .record(label, version, BootEvent::Valid, Vec::new());
+        }
+
+        info!("{label} runs {version} for the first time, checking its health");
+        let mut timer = timer.timer_async()?;
+        let started = Instant::now();
+        loop {
+            let failing = self.failing().await;
+            if failing.is_empty() {
+                ota.mark_running_slot_valid()?;
+                return self
+                    .history
+                    .record(label, version, BootEvent::Confirmed, Vec::new());
+            }
+            if started.elapsed() >= DEADLINE {
+                error!("{version} is unhealthy, rolling back: {failing:?}");
+                self.history
+                    .record(label, version, BootEvent::RolledBack, failing)?;
+                return Err(ota.mark_running_slot_invalid_and_reboot().into());
+            }
+            timer.after(POLL).await?;
+        }
+    }
+}
@@ -1,19 +1,33 @@
 use crate::{
-    config::Config, error::Result, ota::OtaHandler, relay::RelayController, server::run_server,
+    config::Config,
+    error::Result,
+    health::{BootHealth, BootHistory},
+    ota::{OtaHandler, UpdatePolicy, UpdateServer},
+    relay::RelayController,
+    relayfs::run_relayfs,
+    schedule::run_schedules,
+    server::run_server,
+    settings::SettingsStore,
+    telemetry::Telemetry,
 };
 use esp_idf_hal::prelude::Peripherals;
-use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs, timer::EspTaskTimerService};
+use esp_idf_svc::{eventloop::EspSystemEventLoop, nvs, sntp::EspSntp, timer::EspTaskTimerService};
+use firmware::{signature::parse_verifying_key, version::Channel};
 use log::{error, info};
-use plan9::Plan9Connection;
 use std::sync::Arc;
+use stowage_proto::{auth::SharedSecretAuth, tls::parse_fingerprint};
 use wifi::WifiConnection;
 
 mod config;
 mod error;
+mod health;
 mod ota;
-mod plan9;
 mod relay;
+mod relayfs;
+mod schedule;
 mod server;
+mod settings;
+mod telemetry;
 mod wifi;
 
 fn main() {
@@ -69,6 +83,10 @@ async fn async_main() -> std::result::Result<(), Box<dyn std::error::Error>> {
         peripherals.pins.gpio13.into(),
     ])?;
     let relay_controller = Arc::new(relay_controller);
+    let settings = Arc::new(SettingsStore::load(
+        nvs_default_partition.clone(),
+        relay_controller.clone(),
+    )?);
 
     info!("iniializing networking");
     // initialize network before starting the server
@@ -81,17 +99,84 @@ async fn async_main() -> std::result::Result<(), Box<dyn std::error::Error>> {
     )
     .await?;
 
+    let update_server = UpdateServer {
+        addr: "nas:4501".into(),
+        fingerprint: config
+            .ota_fingerprint
+            .as_deref()
+            .map(parse_fingerprint)
+            .transpose()?,
+        auth: config.ota_secret.as_ref().map(|secret| {
+            let uname = config.hostname.as_deref().unwrap_or("nobody");
+            SharedSecretAuth::new(secret.as_bytes(), uname)
+        }),
+    };
+    // a key in nvs takes precedence over one built into the firmware
+    let release_key = config
+        .ota_public_key
+        .as_deref()
+        .or(option_env!("OTA_PUBLIC_KEY"))
+        .map(|key| parse_verifying_key(key.as_bytes()))
+        .transpose()?;
+    let channel = match config.ota_channel.as_deref().map(str::parse::<Channel>) {
+        Some(Ok(channel)) => channel,
+        Some(Err(e)) => {
+            error!("{e}, following the stable channel");
+            Channel::Stable
+        }
+        None => Channel::Stable,
+    };
+    info!("following the {channel} channel");
+    let health = Arc::new(BootHealth::new(
+        wifi_connection.state.clone(),
+        relay_controller.clone(),
+        BootHistory::load(nvs_default_partition.clone())?,
+    ));
     let mut ota_handler = OtaHandler::new(
-        "nas:4501".into(),
+        update_server.clone(),
         "/esp32/relay-controller".into(),
         timer.clone(),
+        settings.clone(),
+        config.hostname.clone(),
+        UpdatePolicy {
+            channel,
+            release_key,
+        },
+        health.clone(),
     )
     .await?;
 
+    // boards without a hostname report under their MAC address
+    let board = config
+        .hostname
+        .clone()
+        .unwrap_or_else(|| wifi_connection.state.mac_address.replace(':', ""));
+    let mut telemetry = Telemetry::new(
+        update_server,
+        format!("/esp32/relay-controller/status/{board}"),
+        timer.clone(),
+        relay_controller.clone(),
+        wifi_connection.state.clone(),
+        ota_handler.status(),
+        settings.clone(),
+    );
+
+    // schedules need the time of day
+    let _sntp = EspSntp::new_default()?;
+
     tokio::try_join!(
-        run_server(wifi_connection.state.clone(), relay_controller),
+        run_server(
+            wifi_connection.state.clone(),
+            relay_controller.clone(),
+            health,
+            ota_handler.push_target(),
+            config.ota_push_secret.clone()
+        ),
+        run_relayfs(relay_controller.clone()),
+        run_schedules(settings, relay_controller, timer),
         wifi_connection.connect(),
         ota_handler.run(),
+        telemetry.run(),
     )?;
 
     Ok(())
@@ -1,88 +1,529 @@
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    health::BootHealth,
+    schedule::unix_time,
+    settings::SettingsStore,
+};
 use embedded_svc::ota::OtaUpdate;
-use esp_idf_hal::io::Write;
+use esp_idf_hal::io::{ErrorType, Write};
 use esp_idf_svc::{
-    ota::{EspOta, EspOtaUpdate, SlotState},
+    ota::{EspOta, EspOtaUpdate},
+    sys::{
+        esp, esp_app_get_description, esp_ota_get_running_partition, esp_ota_get_state_partition,
+        esp_ota_img_states_t, esp_ota_img_states_t_ESP_OTA_IMG_NEW,
+        esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY, ESP_ERR_NOT_FOUND, ESP_ERR_NOT_SUPPORTED,
+    },
     timer::{EspTimerService, Task},
 };
-use futures::{SinkExt, StreamExt};
-use log::info;
-use std::{str::FromStr, time::Duration};
+use firmware::{
+    policy::Policy,
+    rollout::{Admission, Rollout},
+    signature::{Manifest, SignedManifest, VerifyingKey},
+    version::Channel,
+    Progress, VerifyingWriter,
+};
+use futures::{SinkExt, Stream, StreamExt};
+use log::{info, warn};
+use std::{
+    ffi::CStr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
 use stowage_proto::{
-    consts::P9_NOFID, Decodable, FileMode, Message, MessageCodec, OpenMode, QidType, Stat,
-    TaggedMessage, Tattach, Tauth, Tclunk, Tcreate, Topen, Tread, Tstat, Tversion, Twalk, Twrite,
-    Twstat,
+    auth::{AuthStep, Authenticator, SharedSecretAuth},
+    consts::{P9_IOHDRSZ, P9_MAXWELEM, P9_NOFID},
+    decode_dir,
+    error::ErrorKind,
+    tls::{self, Fingerprint},
+    transport::{self, Connection},
+    Decodable, FileMode, Message, OpenMode, QidType, Stat, TaggedMessage, Tattach, Tauth, Tclunk,
+    Tcreate, Topen, Tread, Tstat, Tversion, Twalk, Twrite, Twstat,
 };
-use tokio::net::{TcpListener, TcpStream};
-use tokio_util::codec::{Decoder, Framed};
 
-type Connection = Framed<TcpStream, MessageCodec>;
+/// Where the update server is and how to connect to it
+#[derive(Debug, Clone)]
+pub struct UpdateServer {
+    pub addr: String,
+    /// Certificate to expect from the server; without one the connection is plaintext
+    pub fingerprint: Option<Fingerprint>,
+    /// Credentials for servers that require authentication
+    pub auth: Option<SharedSecretAuth>,
+}
+
+impl UpdateServer {
+    /// Connect and attach to the root of the server's tree as fid [`ROOT_FID`].
+    /// Returns the connection and the negotiated msize.
+    pub(crate) async fn connect(&self, tag: u16) -> Result<(Connection, u32)> {
+        let mut conn = match self.fingerprint {
+            Some(fingerprint) => tls::connect(&self.addr, fingerprint).await?,
+            None => transport::connect_tcp(&self.addr).await?,
+        };
+        // each connection runs its own exchange
+        let mut auth = self.auth.clone();
+        let msize = perform_handshake(
+            &mut conn,
+            tag,
+            auth.as_mut().map(|auth| auth as &mut dyn Authenticator),
+        )
+        .await?;
+        Ok((conn, msize))
+    }
+}
+
+/// Fid that [`UpdateServer::connect`] attaches to the root of the tree
+pub(crate) const ROOT_FID: u32 = 2;
+
+/// Times a download is resumed after losing the connection before the update is
+/// given up
+const MAX_RESUMES: u32 = 10;
+
+/// How long to wait before reconnecting to resume a download
+const RESUME_DELAY: Duration = Duration::from_secs(5);
+
+/// Downloads are logged every time they pass a multiple of this many percent
+const LOG_STEP: u8 = 10;
+
+/// What the update handler has been doing, for reporting elsewhere
+#[derive(Debug, Default)]
+pub struct OtaStatus {
+    running_version: Mutex<Option<String>>,
+    last_result: Mutex<Option<String>>,
+    boot_history: Mutex<Option<String>>,
+    progress: Mutex<Option<Progress>>,
+}
+
+impl OtaStatus {
+    /// Version of the firmware in the running slot, once the handler has read it
+    pub fn running_version(&self) -> Option<String> {
+        self.running_version.lock().ok()?.clone()
+    }
+
+    /// Outcome of the most recent update check
+    pub fn last_result(&self) -> Option<String> {
+        self.last_result.lock().ok()?.clone()
+    }
+
+    /// The recorded boots, one per line, once the running slot has been checked
+    pub fn boot_history(&self) -> Option<String> {
+        self.boot_history.lock().ok()?.clone()
+    }
+
+    /// Progress of the current download, or of the last one if none is running
+    pub fn progress(&self) -> Option<Progress> {
+        *self.progress.lock().ok()?
+    }
+
+    fn set_progress(&self, progress: Progress) {
+        if let Ok(mut current) = self.progress.lock() {
+            *current = Some(progress);
+        }
+    }
+
+    fn set_boot_history(&self, history: String) {
+        if let Ok(mut boot_history) = self.boot_history.lock() {
+            *boot_history = Some(history);
+        }
+    }
+
+    fn set_running_version(&self, version: String) {
+        if let Ok(mut running_version) = self.running_version.lock() {
+            *running_version = Some(version);
+        }
+    }
+
+    fn set_last_result(&self, result: String) {
+        if let Ok(mut last_result) = self.last_result.lock() {
+            *last_result = Some(result);
+        }
+    }
+}
+
+/// The board's OTA slots. Whoever holds the lock is the only one updating.
+pub type SharedOta = Arc<futures::lock::Mutex<EspOta>>;
+
+/// An image pushed to the board over HTTP rather than fetched from the update
+/// server, with what it is checked against
+pub struct PushedImage {
+    pub signed: SignedManifest,
+    pub len: u64,
+}
+
+/// What the HTTP server needs to report on updates and install pushed images
+#[derive(Clone)]
+pub struct PushTarget {
+    pub ota: SharedOta,
+    pub status: Arc<OtaStatus>,
+    pub policy: UpdatePolicy,
+    pub settings: Arc<SettingsStore>,
+}
+
+impl PushTarget {
+    /// Checks the signature of `image` and whether the board installs it, then
+    /// writes it to the update partition as `chunks` arrive, with the same checks as
+    /// a download from the update server. The board boots into the image on its next
+    /// restart.
+    /// # Errors
+    /// - no release key is configured, or the image isn't signed by it
+    /// - the board doesn't install the version, see [`UpdatePolicy::admit`]
+    /// - the upload fails or doesn't match the digest or length
+    /// - flash failure
+    pub async fn install<S, B, E>(
+        &self,
+        ota: &mut EspOta,
+        image: PushedImage,
+        chunks: S,
+    ) -> Result<()>
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        E: std::fmt::Display,
+    {
+        let result = self.write_image(ota, image, chunks).await;
+        match &result {
+            Ok(()) => self
+                .status
+                .set_last_result("pushed image installed, rebooting".into()),
+            Err(e) => {
+                warn!("pushed image rejected: {e}");
+                self.status.set_last_result(format!("push failed: {e}"));
+            }
+        }
+        result
+    }
+
+    async fn write_image<S, B, E>(
+        &self,
+        ota: &mut EspOta,
+        image: PushedImage,
+        mut chunks: S,
+    ) -> Result<()>
+    where
+        S: Stream<Item = std::result::Result<B, E>> + Unpin,
+        B: AsRef<[u8]>,
+        E: std::fmt::Display,
+    {
+        let manifest = self
+            .policy
+            .admit(&image.signed, self.settings.current().allow_downgrade)?;
+
+        info!(
+            "receiving pushed image of {}, {} bytes",
+            manifest.version, image.len
+        );
+        self.status.set_last_result("receiving pushed image".into());
+        let mut update = ota.initiate_update()?;
+        let mut writer = ProgressWriter::new(
+            VerifyingWriter::new(&mut update, manifest.digest).with_len(image.len),
+            image.len,
+            self.status.clone(),
+        );
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.map_err(|e| Error::Other(format!("upload failed: {e}")))?;
+            writer
+                .write_all(chunk.as_ref())
+                .map_err(|_| Error::EspUpdateError)?;
+        }
+        info!("received pushed image: {}", writer.progress);
+        writer.inner.finish()?;
+        update.complete()?;
+        info!("pushed image is signed by the release key and intact");
+        Ok(())
+    }
+}
+
+/// What an update check found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Check {
+    UpToDate,
+    /// A newer version is being rolled out, but not to this board yet
+    Held {
+        version: String,
+        admission: Admission,
+    },
+    /// The release to install, with its verified manifest
+    Update(Manifest),
+}
+
+/// Which images a board installs
+#[derive(Debug, Clone, Default)]
+pub struct UpdatePolicy {
+    pub channel: Channel,
+    /// Key that images must be signed with; without one no update is installed
+    pub release_key: Option<VerifyingKey>,
+}
+
+impl UpdatePolicy {
+    /// Checks that `signed` is signed by the release key and that the board installs
+    /// the release it describes over the running image. Images from the update
+    /// server and pushed ones both go through here.
+    /// # Errors
+    /// - no release key is configured, or the manifest isn't signed by it
+    /// - [`Error::Refused`] if the board doesn't install the release
+    pub fn admit<'a>(
+        &self,
+        signed: &'a SignedManifest,
+        allow_downgrade: bool,
+    ) -> Result<&'a Manifest> {
+        let release_key = self
+            .release_key
+            .as_ref()
+            .ok_or_else(|| Error::MissingConfig("device.ota_public_key".into()))?;
+        let manifest = signed.verify(release_key)?;
+        let policy = Policy {
+            channel: self.channel,
+            allow_downgrade,
+        };
+        policy
+            .check(&running_version(), manifest)
+            .map_err(|refusal| Error::Refused {
+                version: manifest.version.clone(),
+                refusal,
+            })?;
+        Ok(manifest)
+    }
+}
 
 // OTA update handler over 9p protocol
 pub struct OtaHandler {
-    addr: String,
+    server: UpdateServer,
     path: String,
     timer: EspTimerService<Task>,
+    status: Arc<OtaStatus>,
+    settings: Arc<SettingsStore>,
+    /// Name the board's settings are kept under, in `config/` on the server
+    hostname: Option<String>,
+    policy: UpdatePolicy,
+    health: Arc<BootHealth>,
+    ota: SharedOta,
 }
 
 impl OtaHandler {
-    pub async fn new(addr: String, path: String, timer: EspTimerService<Task>) -> Result<Self> {
-        Ok(Self { addr, path, timer })
+    pub async fn new(
+        server: UpdateServer,
+        path: String,
+        timer: EspTimerService<Task>,
+        settings: Arc<SettingsStore>,
+        hostname: Option<String>,
+        policy: UpdatePolicy,
+        health: Arc<BootHealth>,
+    ) -> Result<Self> {
+        Ok(Self {
+            server,
+            path,
+            timer,
+            status: Arc::new(OtaStatus::default()),
+            settings,
+            hostname,
+            policy,
+            health,
+            ota: Arc::new(futures::lock::Mutex::new(EspOta::new()?)),
+        })
+    }
+
+    pub fn status(&self) -> Arc<OtaStatus> {
+        self.status.clone()
     }
 
-    /// Launches a new task that continually checks for firmware updates.
-    /// Only returns in case of an error.
+    pub fn push_target(&self) -> PushTarget {
+        PushTarget {
+            ota: self.ota.clone(),
+            status: self.status.clone(),
+            policy: self.policy.clone(),
+            settings: self.settings.clone(),
+        }
+    }
+
+    /// Launches a new task that continually checks for firmware updates, once the
+    /// running image has been confirmed healthy. Only returns in case of an error.
+    /// An unhealthy image never returns; the board reboots into the previous one.
+    /// Checks wait while an image is being pushed.
     /// # Errors
     /// - IO failure
     pub async fn run(&mut self) -> Result<()> {
         let mut timer = self.timer.timer_async()?;
 
-        let mut ota = EspOta::new()?;
+        let shared_ota = self.ota.clone();
+        let mut ota = shared_ota.lock().await;
+        self.health.confirm(&mut ota, &self.timer).await?;
+        let boots: Vec<String> = self
+            .health
+            .history()
+            .records()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        self.status.set_boot_history(boots.join("\n"));
+        self.status.set_running_version(running_version());
+        drop(ota);
         loop {
-            timer.after(Duration::from_secs(16)).await?;
+            let interval = self.settings.current().ota_interval;
+            timer.after(Duration::from_secs(interval)).await?;
 
-            match self.check_update(&mut ota).await {
-                Ok(Some(version)) => self.perform_update(&mut ota, &version).await?,
-                Ok(None) => {
+            let result = match self.pull_settings().await {
+                Ok(true) => "applied".to_string(),
+                Ok(false) => "unchanged".to_string(),
+                Err(Error::NotFound(_)) => "no settings on the server".to_string(),
+                Err(e) => {
+                    warn!("settings pull failed: {e}");
+                    e.to_string()
+                }
+            };
+            self.settings.set_last_result(result);
+
+            let mut ota = shared_ota.lock().await;
+            let result = match self.check_update().await {
+                Ok(Check::Update(manifest)) => {
+                    let version = &manifest.version;
+                    self.status
+                        .set_last_result(format!("updating to {version}"));
+                    self.perform_update(&mut ota, &manifest).await?;
+                    format!("update to {version} aborted")
+                }
+                Ok(Check::UpToDate) => {
                     info!("firmware already up to date");
-                    false
+                    "up to date".to_string()
+                }
+                Ok(Check::Held { version, admission }) => {
+                    info!("holding back {version}: {admission}");
+                    format!("rollout of {version}: {admission}")
+                }
+                Err(e) if e.remote_kind() == Some(ErrorKind::PermissionDenied) => {
+                    info!("update server refused access, check the ota credentials: {e}");
+                    format!("refused: {e}")
                 }
                 Err(e) => {
                     info!("update check failed: {e:?}");
-                    false
+                    format!("check failed: {e}")
                 }
             };
+            self.status.set_last_result(result);
         }
     }
 
-    pub async fn check_update(&mut self, ota: &mut EspOta) -> Result<Option<String>> {
+    /// Fetches the board's settings from `config/<hostname>` and applies them.
+    /// Returns whether they changed.
+    pub async fn pull_settings(&self) -> Result<bool> {
+        let Some(hostname) = &self.hostname else {
+            return Err(Error::MissingConfig("device.hostname".into()));
+        };
+        let mut settings_buf = Vec::new();
+        cat_file(
+            &self.server,
+            &format!("{}/config/{hostname}", self.path),
+            &mut settings_buf,
+        )
+        .await?;
+        self.settings.update(&settings_buf)
+    }
+
+    /// The version published for the board's channel: the one being rolled out if
+    /// there is a rollout manifest, otherwise the one in the version file
+    pub async fn upstream_version(&self) -> Result<(String, Option<Rollout>)> {
+        let channel = self.policy.channel;
+        let mut rollout_buf = Vec::new();
+        match cat_file(
+            &self.server,
+            &format!("{}/{}", self.path, channel.rollout_file()),
+            &mut rollout_buf,
+        )
+        .await
+        {
+            Ok(()) => {
+                let rollout = Rollout::parse(&rollout_buf)?;
+                return Ok((rollout.version.clone(), Some(rollout)));
+            }
+            Err(Error::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+
         let mut version_buf = Vec::new();
-        let upstream_version = cat_file(
-            &self.addr,
-            &format!("{}/version", self.path),
+        cat_file(
+            &self.server,
+            &format!("{}/{}", self.path, channel.version_file()),
             &mut version_buf,
         )
         .await?;
-        let upstream_version = String::from_utf8_lossy(&version_buf);
-        let upstream_version = upstream_version.trim();
+        let version = String::from_utf8_lossy(&version_buf).trim().to_string();
+        Ok((version, None))
+    }
+
+    pub async fn check_update(&mut self) -> Result<Check> {
+        let (upstream_version, rollout) = self.upstream_version().await?;
+        let current_version = running_version();
+        if upstream_version == current_version {
+            return Ok(Check::UpToDate);
+        }
+        if self.policy.release_key.is_none() {
+            info!("no release key configured, refusing to update");
+            return Ok(Check::UpToDate);
+        }
 
-        let current_version = get_running_version(&ota)?;
-        let current_version = current_version.to_string();
+        let builds = self.available_builds().await?;
+        let manifest_name = format!("{upstream_version}.manifest");
+        for name in [&upstream_version, &manifest_name] {
+            if !builds.iter().any(|build| &build.name == name) {
+                info!("no {name} in {}/files, refusing to update", self.path);
+                return Ok(Check::UpToDate);
+            }
+        }
+        // the manifest covers the digest, so it can be checked before the download
+        let mut manifest_buf = Vec::new();
+        cat_file(
+            &self.server,
+            &format!("{}/files/{manifest_name}", self.path),
+            &mut manifest_buf,
+        )
+        .await?;
+        let signed = SignedManifest::parse(&manifest_buf)?;
+        let manifest = match self
+            .policy
+            .admit(&signed, self.settings.current().allow_downgrade)
+        {
+            Ok(manifest) => manifest,
+            Err(e @ Error::Refused { .. }) => {
+                info!("{e}");
+                return Ok(Check::UpToDate);
+            }
+            Err(e) => return Err(e),
+        };
+        // the version files aren't signed, so what they named has to match what was
+        if manifest.version != upstream_version {
+            return Err(Error::Other(format!(
+                "{manifest_name} is signed for {}",
+                manifest.version
+            )));
+        }
 
-        match current_version.eq(upstream_version) {
-            true => Ok(None),
-            false => {
-                info!("update {current_version} -> {upstream_version}");
-                Ok(Some(upstream_version.to_string()))
+        if let Some(rollout) = rollout {
+            let admission = rollout.admit(self.hostname.as_deref(), unix_time(SystemTime::now()));
+            if admission != Admission::Admitted {
+                return Ok(Check::Held {
+                    version: rollout.version,
+                    admission,
+                });
             }
         }
+
+        info!("update {current_version} -> {upstream_version}");
+        Ok(Check::Update(manifest.clone()))
     }
 
-    pub async fn perform_update(&mut self, ota: &mut EspOta, version: &str) -> Result<bool> {
+    /// Lists the files published in the `files/` directory of the update server: the
+    /// firmware images and their digests
+    pub async fn available_builds(&self) -> Result<Vec<Stat>> {
+        let entries = list_dir(&self.server, &format!("{}/files", self.path)).await?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| !entry.qid.qtype.contains(QidType::Dir))
+            .collect())
+    }
+
+    pub async fn perform_update(&mut self, ota: &mut EspOta, manifest: &Manifest) -> Result<bool> {
         info!("initiating update");
         let mut update = ota.initiate_update()?;
 
-        match self.download_update(&mut update, version).await {
+        match self.download_update(&mut update, manifest).await {
             Ok(_) => {
                 info!("update complete, rebooting");
                 update.complete()?;
@@ -100,28 +541,149 @@ impl OtaHandler {
     pub async fn download_update(
         &mut self,
         update: &mut EspOtaUpdate<'_>,
-        version: &str,
+        manifest: &Manifest,
     ) -> Result<bool> {
-        let firmware_path = format!("{}/files/{}", self.path, version);
-        info!("downloading {firmware_path}");
-        cat_file(&self.addr, &firmware_path, update).await?;
+        let version = &manifest.version;
+        let firmware_path = format!("{}/files/{version}", self.path);
+        let digest = manifest.digest;
+        let size = self
+            .available_builds()
+            .await?
+            .into_iter()
+            .find(|build| &build.name == version)
+            .ok_or_else(|| Error::NotFound(firmware_path.clone()))?
+            .length;
+
+        info!("downloading {firmware_path}, {size} bytes");
+        let mut writer = ProgressWriter::new(
+            VerifyingWriter::new(update, digest).with_len(size),
+            size,
+            self.status.clone(),
+        );
+        let mut timer = self.timer.timer_async()?;
+        loop {
+            let offset = writer.inner.written();
+            match cat_file_at(&self.server, &firmware_path, offset, &mut writer).await {
+                Ok(()) => break,
+                Err(e) if e.is_transient() && writer.progress.resumes < MAX_RESUMES => {
+                    warn!(
+                        "download interrupted at {} bytes: {e}",
+                        writer.inner.written()
+                    );
+                    timer.after(RESUME_DELAY).await?;
+                    writer.progress.resumes += 1;
+                    self.status.set_progress(writer.progress);
+                    info!(
+                        "resuming {firmware_path} at {} bytes, attempt {}/{MAX_RESUMES}",
+                        writer.inner.written(),
+                        writer.progress.resumes
+                    );
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        info!("downloaded {firmware_path}: {}", writer.progress);
+        writer.inner.finish()?;
+        info!("{firmware_path} is signed by the release key and intact");
         Ok(true)
     }
 }
 
-fn get_running_version(ota: &EspOta) -> Result<heapless::String<24>> {
-    Ok(ota
-        .get_running_slot()?
-        .firmware
-        .ok_or(Error::FirmwareInfoMissing)?
-        .version)
+/// Reports how much has been written to `inner`, to the status and the log
+struct ProgressWriter<W> {
+    inner: VerifyingWriter<W>,
+    progress: Progress,
+    started: Instant,
+    status: Arc<OtaStatus>,
+}
+
+impl<W> ProgressWriter<W> {
+    fn new(inner: VerifyingWriter<W>, total: u64, status: Arc<OtaStatus>) -> Self {
+        let progress = Progress::new(total);
+        status.set_progress(progress);
+        Self {
+            inner,
+            progress,
+            started: Instant::now(),
+            status,
+        }
+    }
+}
+
+impl<W: Write> ErrorType for ProgressWriter<W> {
+    type Error = W::Error;
+}
+
+impl<W: Write> Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::result::Result<usize, Self::Error> {
+        let written = self.inner.write(buf)?;
+        let previous = self.progress.written;
+        self.progress.written = self.inner.written();
+        self.progress.elapsed = self.started.elapsed();
+        if self.progress.crossed(previous, LOG_STEP) {
+            info!("download {}", self.progress);
+        }
+        self.status.set_progress(self.progress);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::result::Result<(), Self::Error> {
+        self.inner.flush()
+    }
+}
+
+/// Version of the running image, from its app description. `git describe` makes
+/// versions up to the description's 31 bytes, while [`EspOta::get_running_slot`]
+/// fails on any over 24.
+pub(crate) fn running_version() -> String {
+    // SAFETY: the running app's description is static, and its version nul-terminated
+    let version = unsafe { CStr::from_ptr((*esp_app_get_description()).version.as_ptr()) };
+    match version.to_string_lossy() {
+        version if version.is_empty() => "unknown".to_string(),
+        version => version.into_owned(),
+    }
+}
+
+/// Label of the running slot and whether its image is still unverified, read without
+/// the version that can make [`EspOta::get_running_slot`] fail
+pub(crate) fn running_slot() -> Result<(String, bool)> {
+    // SAFETY: partitions handed out by the IDF are static
+    let partition =
+        unsafe { esp_ota_get_running_partition().as_ref() }.ok_or(Error::FirmwareInfoMissing)?;
+    // SAFETY: the label is nul-terminated
+    let label = unsafe { CStr::from_ptr(partition.label.as_ptr()) }
+        .to_string_lossy()
+        .into_owned();
+
+    let mut state: esp_ota_img_states_t = Default::default();
+    // SAFETY: both pointers are valid for the call
+    let err = unsafe { esp_ota_get_state_partition(partition, &mut state) };
+    // the factory app and slots without a state are never unverified
+    if err == ESP_ERR_NOT_FOUND || err == ESP_ERR_NOT_SUPPORTED {
+        return Ok((label, false));
+    }
+    esp!(err)?;
+    #[allow(non_upper_case_globals)]
+    let unverified = matches!(
+        state,
+        esp_ota_img_states_t_ESP_OTA_IMG_NEW | esp_ota_img_states_t_ESP_OTA_IMG_PENDING_VERIFY
+    );
+    Ok((label, unverified))
+}
+
+async fn cat_file<W: Write>(server: &UpdateServer, path: &str, writer: &mut W) -> Result<()> {
+    cat_file_at(server, path, 0, writer).await
 }
 
-async fn cat_file<W: Write>(addr: &str, path: &str, writer: &mut W) -> Result<()> {
-    let stream = TcpStream::connect(addr).await?;
-    let mut conn = Framed::new(stream, MessageCodec::new());
+/// Like [`cat_file`], skipping the first `offset` bytes of the file
+async fn cat_file_at<W: Write>(
+    server: &UpdateServer,
+    path: &str,
+    offset: u64,
+    writer: &mut W,
+) -> Result<()> {
     let tag: u16 = 1;
-    let msize = perform_handshake(&mut conn, tag).await?;
+    let (mut conn, msize) = server.connect(tag).await?;
 
     let mut root_fid = 2;
     let components = parse_path_components(&path);
@@ -132,7 +694,7 @@ async fn cat_file<W: Write>(addr: &str, path: &str, writer: &mut W) -> Result<()
 
     let walk_success = walk_to_path(&mut conn, tag, root_fid, root_fid + 1, &path).await?;
     if !walk_success {
-        return Err(Error::Other(format!("file not found: {}", path)));
+        return Err(Error::NotFound(path.to_string()));
     }
     root_fid += 1;
 
@@ -158,19 +720,63 @@ async fn cat_file<W: Write>(addr: &str, path: &str, writer: &mut W) -> Result<()
             }
         }
         Message::Rerror(err) => {
-            return Err(Error::Other(format!("failed to open file: {}", err.ename)));
+            return Err(Error::remote("failed to open file", err));
         }
         _ => return Err(Error::Other("unexpected response to Topen".into())),
     }
 
-    read_file(&mut conn, tag, root_fid, msize, writer).await?;
+    read_file(&mut conn, tag, root_fid, msize, offset, writer).await?;
 
     cleanup_fid(&mut conn, tag, root_fid).await?;
 
     Ok(())
 }
 
-fn parse_path_components(path: &str) -> Vec<String> {
+async fn list_dir(server: &UpdateServer, path: &str) -> Result<Vec<Stat>> {
+    let tag: u16 = 1;
+    let (mut conn, msize) = server.connect(tag).await?;
+
+    let mut root_fid = 2;
+    let walk_success = walk_to_path(&mut conn, tag, root_fid, root_fid + 1, &path).await?;
+    if !walk_success {
+        return Err(Error::Other(format!("directory not found: {}", path)));
+    }
+    root_fid += 1;
+
+    let open_msg = Topen {
+        fid: root_fid,
+        mode: OpenMode::Read.into(),
+    };
+    send_message(
+        &mut conn,
+        TaggedMessage {
+            message: Message::Topen(open_msg),
+            tag,
+        },
+    )
+    .await?;
+
+    let response = receive_message(&mut conn).await?;
+    match response.message {
+        Message::Ropen(ropen) => {
+            if !ropen.qid.qtype.contains(QidType::Dir) {
+                return Err(Error::Other(format!("ls: {}: Not a directory", path)));
+            }
+        }
+        Message::Rerror(err) => {
+            return Err(Error::remote("failed to open directory", err));
+        }
+        _ => return Err(Error::Other("unexpected response to Topen".into())),
+    }
+
+    let entries = read_dir(&mut conn, tag, root_fid, msize).await?;
+
+    cleanup_fid(&mut conn, tag, root_fid).await?;
+
+    Ok(entries)
+}
+
+pub(crate) fn parse_path_components(path: &str) -> Vec<String> {
     path.split('/')
         .filter(|s| !s.is_empty())
         .map(std::string::ToString::to_string)
@@ -182,16 +788,10 @@ async fn read_file<W: Write>(
     tag: u16,
     fid: u32,
     msize: u32,
+    mut offset: u64,
     writer: &mut W,
 ) -> Result<()> {
-    let protocol_overhead = 100;
-    let max_count = if msize > protocol_overhead {
-        msize - protocol_overhead
-    } else {
-        4096
-    };
-
-    let mut offset: u64 = 0;
+    let max_count = msize - P9_IOHDRSZ;
 
     loop {
         let tread = TaggedMessage::new(
@@ -219,7 +819,7 @@ async fn read_file<W: Write>(
                 offset += l;
             }
             Message::Rerror(err) => {
-                return Err(Error::Other(format!("Failed to read file: {}", err.ename)));
+                return Err(Error::remote("Failed to read file", err));
             }
             _ => return Err(Error::Other("Unexpected response to Tread".into())),
         }
@@ -228,7 +828,46 @@ async fn read_file<W: Write>(
     Ok(())
 }
 
-async fn cleanup_fid(conn: &mut Connection, tag: u16, fid: u32) -> Result<()> {
+async fn read_dir(conn: &mut Connection, tag: u16, fid: u32, msize: u32) -> Result<Vec<Stat>> {
+    let max_count = msize - P9_IOHDRSZ;
+
+    let mut offset: u64 = 0;
+    let mut entries = Vec::new();
+
+    loop {
+        let tread = TaggedMessage::new(
+            tag,
+            Message::Tread(Tread {
+                fid,
+                offset,
+                count: max_count,
+            }),
+        );
+
+        send_message(conn, tread).await?;
+        let response = receive_message(conn).await?;
+
+        match response.message {
+            Message::Rread(rread) => {
+                if rread.data.is_empty() {
+                    break; // end of directory
+                }
+
+                // each read holds whole entries, so it can be decoded on its own
+                entries.extend(decode_dir(&rread.data)?);
+                offset += rread.data.len() as u64;
+            }
+            Message::Rerror(err) => {
+                return Err(Error::remote("Failed to read directory", err));
+            }
+            _ => return Err(Error::Other("Unexpected response to Tread".into())),
+        }
+    }
+
+    Ok(entries)
+}
+
+pub(crate) async fn cleanup_fid(conn: &mut Connection, tag: u16, fid: u32) -> Result<()> {
     let clunk_msg = Tclunk { fid };
     let tagged = TaggedMessage {
         message: Message::Tclunk(clunk_msg),
@@ -242,10 +881,10 @@ async fn cleanup_fid(conn: &mut Connection, tag: u16, fid: u32) -> Result<()> {
         match response.message {
             Message::Rclunk(_) => {}
             Message::Rerror(err) => {
-                eprintln!("warning: Failed to clunk fid {}: {}", fid, err.ename);
+                warn!("failed to clunk fid {fid}: {}", err.ename);
             }
             _ => {
-                eprintln!("warning: Unexpected response to Tclunk for fid {fid}");
+                warn!("unexpected response to Tclunk for fid {fid}");
             }
         }
     }
@@ -253,16 +892,22 @@ async fn cleanup_fid(conn: &mut Connection, tag: u16, fid: u32) -> Result<()> {
     Ok(())
 }
 
-async fn perform_handshake(conn: &mut Connection, tag: u16) -> Result<u32> {
+async fn perform_handshake(
+    conn: &mut Connection,
+    tag: u16,
+    auth: Option<&mut dyn Authenticator>,
+) -> Result<u32> {
     let msize = perform_version_negotiation(conn).await?;
-    perform_authentication(conn, tag).await?;
-    attach_to_filesystem(conn, tag).await?;
+    let (afid, uname) = perform_authentication(conn, tag, auth).await?;
+    attach_to_filesystem(conn, tag, afid, uname).await?;
     Ok(msize)
 }
 
 async fn perform_version_negotiation(conn: &mut Connection) -> Result<u32> {
     let version_tag = 0xFFFF;
     let mut msize = 8192;
+    // the server's frames can't be larger than what we offer, even before it answers
+    conn.codec_mut().set_msize(msize);
 
     let version_msg = Message::Tversion(Tversion {
         msize,
@@ -284,26 +929,38 @@ async fn perform_version_negotiation(conn: &mut Connection) -> Result<u32> {
                     rversion.version
                 )));
             }
+            // reads are sized as msize minus their header
+            if rversion.msize <= P9_IOHDRSZ {
+                return Err(Error::Other(format!(
+                    "server offered msize {}, too small to carry any data",
+                    rversion.msize
+                )));
+            }
             msize = std::cmp::min(msize, rversion.msize);
-            println!(
-                "negotiated version: {} with msize: {}",
-                rversion.version, msize
-            );
+            conn.codec_mut().set_msize(msize);
+            info!("negotiated {} with msize {msize}", rversion.version);
             Ok(msize)
         }
-        Message::Rerror(err) => Err(Error::Other(format!(
-            "version negotiation failed: {}",
-            err.ename
-        ))),
+        Message::Rerror(err) => Err(Error::remote("version negotiation failed", err)),
         _ => Err(Error::Other("unexpected response to Tversion".into())),
     }
 }
 
-async fn perform_authentication(conn: &mut Connection, tag: u16) -> Result<()> {
+/// Authenticate through an afid if the server asks for it.
+/// Returns the afid and uname to attach with.
+async fn perform_authentication(
+    conn: &mut Connection,
+    tag: u16,
+    auth: Option<&mut dyn Authenticator>,
+) -> Result<(u32, String)> {
     let afid = 1;
+    let uname = auth
+        .as_ref()
+        .map_or("nobody", |auth| auth.uname())
+        .to_string();
     let auth_msg = Tauth {
         afid,
-        uname: String::from("nobody"),
+        uname: uname.clone(),
         aname: String::new(),
     };
     let tagged = TaggedMessage {
@@ -315,23 +972,68 @@ async fn perform_authentication(conn: &mut Connection, tag: u16) -> Result<()> {
 
     let response = receive_message(conn).await?;
     match response.message {
-        Message::Rauth(_) => Err(Error::Other(
-            "authentication required but not supported by this client".into(),
-        )),
+        Message::Rauth(_) => {
+            let Some(auth) = auth else {
+                return Err(Error::Other(
+                    "authentication required but no credentials are configured".into(),
+                ));
+            };
+            exchange_auth(conn, tag, afid, auth).await?;
+            Ok((afid, uname))
+        }
         Message::Rerror(_) => {
             // expected when auth is not required
-            Ok(())
+            Ok((P9_NOFID, uname))
         }
         _ => Err(Error::Other("unexpected response to Tauth".into())),
     }
 }
 
-async fn attach_to_filesystem(conn: &mut Connection, tag: u16) -> Result<()> {
-    let root_fid = 2;
+/// Read and write the afid as directed by the authenticator until it is done
+async fn exchange_auth(
+    conn: &mut Connection,
+    tag: u16,
+    afid: u32,
+    auth: &mut dyn Authenticator,
+) -> Result<()> {
+    let mut input = Vec::new();
+    loop {
+        // the afid is a conversation rather than a file, so offsets are always 0
+        let message = match auth.step(&input)? {
+            AuthStep::Read(count) => Message::Tread(Tread {
+                fid: afid,
+                offset: 0,
+                count,
+            }),
+            AuthStep::Write(data) => Message::Twrite(Twrite {
+                fid: afid,
+                offset: 0,
+                data: data.into(),
+            }),
+            AuthStep::Done => return Ok(()),
+        };
+        send_message(conn, TaggedMessage { message, tag }).await?;
+
+        let response = receive_message(conn).await?;
+        input = match response.message {
+            Message::Rread(rread) => rread.data.to_vec(),
+            Message::Rwrite(_) => Vec::new(),
+            Message::Rerror(err) => return Err(Error::remote("authentication failed", err)),
+            _ => return Err(Error::Other("unexpected response on afid".into())),
+        };
+    }
+}
+
+async fn attach_to_filesystem(
+    conn: &mut Connection,
+    tag: u16,
+    afid: u32,
+    uname: String,
+) -> Result<()> {
     let attach_msg = Tattach {
-        fid: root_fid,
-        afid: P9_NOFID,
-        uname: String::from("nobody"),
+        fid: ROOT_FID,
+        afid,
+        uname,
         aname: String::new(),
     };
     let tagged = TaggedMessage {
@@ -344,27 +1046,24 @@ async fn attach_to_filesystem(conn: &mut Connection, tag: u16) -> Result<()> {
     let response = receive_message(conn).await?;
     match response.message {
         Message::Rattach(_) => Ok(()),
-        Message::Rerror(err) => Err(Error::Other(format!(
-            "Failed to attach to filesystem: {}",
-            err.ename
-        ))),
+        Message::Rerror(err) => Err(Error::remote("Failed to attach to filesystem", err)),
         _ => Err(Error::Other("Unexpected response to Tattach".into())),
     }
 }
 
-async fn send_message(conn: &mut Connection, message: TaggedMessage) -> Result<()> {
+pub(crate) async fn send_message(conn: &mut Connection, message: TaggedMessage) -> Result<()> {
     conn.send(message).await.map_err(Error::from)
 }
 
-async fn receive_message(conn: &mut Connection) -> Result<TaggedMessage> {
+pub(crate) async fn receive_message(conn: &mut Connection) -> Result<TaggedMessage> {
     match conn.next().await {
         Some(Ok(msg)) => Ok(msg),
         Some(Err(e)) => Err(Error::from(e)),
-        None => Err(Error::Other("Connection closed".into())),
+        None => Err(Error::ConnectionClosed),
     }
 }
 
-async fn walk_to_path(
+pub(crate) async fn walk_to_path(
     conn: &mut Connection,
     tag: u16,
     base_fid: u32,
@@ -377,10 +1076,32 @@ async fn walk_to_path(
         return Ok(true); // root path
     }
 
+    // a single Twalk may carry at most P9_MAXWELEM names, so longer paths are walked
+    // in steps with every step after the first starting from the new fid
+    for (i, chunk) in components.chunks(P9_MAXWELEM).enumerate() {
+        let fid = if i == 0 { base_fid } else { new_fid };
+        if !walk_once(conn, tag, fid, new_fid, chunk).await? {
+            if i > 0 {
+                cleanup_fid(conn, tag, new_fid).await?;
+            }
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+pub(crate) async fn walk_once(
+    conn: &mut Connection,
+    tag: u16,
+    fid: u32,
+    new_fid: u32,
+    wnames: &[String],
+) -> Result<bool> {
     let walk_msg = Twalk {
-        fid: base_fid,
+        fid,
         newfid: new_fid,
-        wnames: components.clone(),
+        wnames: wnames.to_vec(),
     };
     let tagged = TaggedMessage {
         message: Message::Twalk(walk_msg),
@@ -391,7 +1112,7 @@ async fn walk_to_path(
 
     let response = receive_message(conn).await?;
     match response.message {
-        Message::Rwalk(rwalk) => Ok(rwalk.wqids.len() == components.len()),
+        Message::Rwalk(rwalk) => Ok(rwalk.wqids.len() == wnames.len()),
         Message::Rerror(_) => Ok(false),
         _ => Err(Error::Other("Unexpected response to Twalk".into())),
     }
@@ -1,5 +1,5 @@
 use esp_idf_hal::{
-    gpio::{Output, PinDriver},
+    gpio::{InputOutput, PinDriver},
     sys::EspError,
 };
 use esp_idf_svc::hal::gpio::AnyIOPin;
@@ -9,10 +9,11 @@ use std::sync::{
     Arc, Mutex,
 };
 
-type Driver = PinDriver<'static, AnyIOPin, Output>;
+/// Input as well as output, so that the level of a pin can be read back
+type Driver = PinDriver<'static, AnyIOPin, InputOutput>;
 
 fn pin_driver(pin: AnyIOPin) -> Result<Arc<Mutex<Option<Driver>>>, EspError> {
-    let mut driver = PinDriver::output(pin)?;
+    let mut driver = PinDriver::input_output(pin)?;
     driver.set_low()?;
     Ok(Arc::new(Mutex::new(Some(driver))))
 }
@@ -20,6 +21,7 @@ fn pin_driver(pin: AnyIOPin) -> Result<Arc<Mutex<Option<Driver>>>, EspError> {
 pub struct RelayController {
     relays: Vec<Arc<Mutex<Option<Driver>>>>,
     states: Arc<Vec<AtomicBool>>,
+    names: Arc<Mutex<Vec<String>>>,
 }
 
 impl RelayController {
@@ -27,20 +29,28 @@ impl RelayController {
     pub fn new(pins: Vec<AnyIOPin>) -> Result<Self, EspError> {
         let mut relays = Vec::with_capacity(pins.len());
         let mut states = Vec::with_capacity(pins.len());
+        let mut names = Vec::with_capacity(pins.len());
 
-        for pin in pins {
+        for (idx, pin) in pins.into_iter().enumerate() {
             relays.push(pin_driver(pin)?);
             states.push(AtomicBool::new(false));
+            names.push(Self::default_name(idx));
         }
 
         let controller = Self {
             relays,
             states: Arc::new(states),
+            names: Arc::new(Mutex::new(names)),
         };
 
         Ok(controller)
     }
 
+    /// Name of a relay until it is given another
+    pub fn default_name(relay_id: usize) -> String {
+        format!("relay{relay_id}")
+    }
+
     pub fn get_state(&self, relay_id: usize) -> Option<bool> {
         self.states
             .get(relay_id)
@@ -55,6 +65,23 @@ impl RelayController {
             .collect()
     }
 
+    /// Relays whose pin doesn't read back the state they were last set to
+    pub fn mismatched(&self) -> Vec<usize> {
+        self.relays
+            .iter()
+            .zip(self.states.iter())
+            .enumerate()
+            .filter(|(_, (relay, state))| {
+                let level = relay
+                    .lock()
+                    .ok()
+                    .and_then(|guard| guard.as_ref().map(PinDriver::is_high));
+                level != Some(state.load(Ordering::SeqCst))
+            })
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
     pub fn set_state(&self, relay_id: usize, active: bool) -> Option<bool> {
         let state = self.states.get(relay_id)?;
         let previous_state = state.swap(active, Ordering::SeqCst);
@@ -94,6 +121,24 @@ impl RelayController {
         let current_state = self.get_state(relay_id)?;
         self.set_state(relay_id, !current_state)
     }
+
+    pub fn get_name(&self, relay_id: usize) -> Option<String> {
+        self.names.lock().ok()?.get(relay_id).cloned()
+    }
+
+    /// Renames a relay, returning false if it doesn't exist
+    pub fn set_name(&self, relay_id: usize, name: &str) -> bool {
+        let Ok(mut names) = self.names.lock() else {
+            return false;
+        };
+        match names.get_mut(relay_id) {
+            Some(current) => {
+                name.clone_into(current);
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 impl Clone for RelayController {
@@ -101,6 +146,7 @@ impl Clone for RelayController {
         Self {
             relays: self.relays.clone(),
             states: Arc::clone(&self.states),
+            names: Arc::clone(&self.names),
         }
     }
 }
@@ -0,0 +1,443 @@
+//! The relays as a 9P file tree, so that hosts can mount the board and switch relays
+//! with plain file operations.
+//!
+//! ```text
+//! relays/<n>/state  "on" or "off"; write on, off or toggle
+//! relays/<n>/name   a name for the relay, kept until the board restarts
+//! relays/<n>/ctl    write commands, one per line: on, off, toggle or name <name>
+//! ```
+//!
+//! Only 9P2000 is spoken. Linux hosts mount it with
+//! `mount -t 9p -o trans=tcp,version=9p2000,port=564 <board> /mnt/relays`.
+use crate::error::Result;
+use crate::relay::RelayController;
+use crate::settings::valid_relay_name;
+use flagset::FlagSet;
+use futures::{SinkExt, StreamExt};
+use log::{info, warn};
+use std::{collections::HashMap, sync::Arc};
+use stowage_proto::{
+    consts::{P9_IOHDRSZ, P9_MAXWELEM, P9_NOFID},
+    encode_dir,
+    error::ErrorKind,
+    transport, FileMode, Message, OpenMode, Qid, QidType, Rattach, Rclunk, Rflush, Ropen, Rread,
+    Rstat, Rversion, Rwalk, Rwrite, Rwstat, Stat, TaggedMessage, Tread, Twalk, Twrite,
+};
+use tokio::net::TcpListener;
+
+/// Standard 9P port
+const PORT: u16 = 564;
+
+/// Largest msize offered to clients
+const MSIZE: u32 = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RelayFile {
+    State,
+    Name,
+    Ctl,
+}
+
+const RELAY_FILES: [RelayFile; 3] = [RelayFile::State, RelayFile::Name, RelayFile::Ctl];
+
+impl RelayFile {
+    fn name(self) -> &'static str {
+        match self {
+            RelayFile::State => "state",
+            RelayFile::Name => "name",
+            RelayFile::Ctl => "ctl",
+        }
+    }
+}
+
+/// A file or directory in the tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Root,
+    Relays,
+    Relay(usize),
+    File(usize, RelayFile),
+}
+
+impl Node {
+    fn is_dir(self) -> bool {
+        !matches!(self, Node::File(..))
+    }
+
+    fn qid(self) -> Qid {
+        let path = match self {
+            Node::Root => 0,
+            Node::Relays => 1,
+            Node::Relay(relay) => (relay as u64 + 1) << 4,
+            Node::File(relay, file) => ((relay as u64 + 1) << 4) | (file as u64 + 1),
+        };
+        Qid {
+            qtype: if self.is_dir() {
+                QidType::Dir.into()
+            } else {
+                QidType::File.into()
+            },
+            version: 0,
+            path,
+        }
+    }
+
+    fn name(self) -> String {
+        match self {
+            Node::Root => "/".to_string(),
+            Node::Relays => "relays".to_string(),
+            Node::Relay(relay) => relay.to_string(),
+            Node::File(_, file) => file.name().to_string(),
+        }
+    }
+
+    fn parent(self) -> Node {
+        match self {
+            Node::Root | Node::Relays => Node::Root,
+            Node::Relay(_) => Node::Relays,
+            Node::File(relay, _) => Node::Relay(relay),
+        }
+    }
+
+    fn children(self, relays: usize) -> Vec<Node> {
+        match self {
+            Node::Root => vec![Node::Relays],
+            Node::Relays => (0..relays).map(Node::Relay).collect(),
+            Node::Relay(relay) => RELAY_FILES
+                .iter()
+                .map(|file| Node::File(relay, *file))
+                .collect(),
+            Node::File(..) => Vec::new(),
+        }
+    }
+
+    fn walk(self, name: &str, relays: usize) -> Option<Node> {
+        match name {
+            ".." => Some(self.parent()),
+            name => self
+                .children(relays)
+                .into_iter()
+                .find(|child| child.name() == name),
+        }
+    }
+
+    /// Whether the file may be opened with `mode`
+    fn allows(self, mode: FlagSet<OpenMode>) -> bool {
+        match access(mode) {
+            OpenMode::Exec => false,
+            _ if self.is_dir() => access(mode) == OpenMode::Read && !mode.contains(OpenMode::Trunc),
+            _ => true,
+        }
+    }
+}
+
+/// The access part of an open mode, without `Trunc` and `RClose`
+fn access(mode: FlagSet<OpenMode>) -> OpenMode {
+    match mode.bits() & 0x3 {
+        0 => OpenMode::Read,
+        1 => OpenMode::Write,
+        2 => OpenMode::ReadWrite,
+        _ => OpenMode::Exec,
+    }
+}
+
+/// A fid of the connected client
+#[derive(Debug, Clone, Copy)]
+struct Fid {
+    node: Node,
+    open: Option<FlagSet<OpenMode>>,
+}
+
+/// Serve the relay tree to every host that connects
+pub async fn run_relayfs(relay_controller: Arc<RelayController>) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", PORT)).await?;
+    info!("9P server listening on port {PORT}");
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let relay_controller = relay_controller.clone();
+        tokio::spawn(async move {
+            info!("9P connection from {peer}");
+            if let Err(e) = serve(stream, relay_controller).await {
+                warn!("9P connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+async fn serve(
+    stream: tokio::net::TcpStream,
+    relay_controller: Arc<RelayController>,
+) -> Result<()> {
+    let mut conn = transport::connection(stream);
+    conn.codec_mut().set_msize(MSIZE);
+    let mut session = Session {
+        relay_controller,
+        fids: HashMap::new(),
+        msize: MSIZE,
+    };
+
+    while let Some(request) = conn.next().await {
+        let request = request?;
+        let response = session
+            .handle(request.message)
+            .unwrap_or_else(|kind| Message::Rerror(kind.into()));
+        conn.send(TaggedMessage::new(request.tag, response)).await?;
+        conn.codec_mut().set_msize(session.msize);
+    }
+    Ok(())
+}
+
+struct Session {
+    relay_controller: Arc<RelayController>,
+    fids: HashMap<u32, Fid>,
+    msize: u32,
+}
+
+impl Session {
+    fn relays(&self) -> usize {
+        self.relay_controller.get_all_states().len()
+    }
+
+    fn fid(&self, fid: u32) -> std::result::Result<Fid, ErrorKind> {
+        self.fids.get(&fid).copied().ok_or(ErrorKind::BadFid)
+    }
+
+    fn handle(&mut self, message: Message) -> std::result::Result<Message, ErrorKind> {
+        match message {
+            Message::Tversion(tversion) => {
+                self.fids.clear();
+                self.msize = tversion.msize.min(MSIZE);
+                let version = if tversion.version.starts_with("9P2000") {
+                    "9P2000"
+                } else {
+                    "unknown"
+                };
+                Ok(Message::Rversion(Rversion {
+                    msize: self.msize,
+                    version: version.to_string(),
+                }))
+            }
+            Message::Tflush(_) => Ok(Message::Rflush(Rflush)),
+            Message::Tattach(tattach) => {
+                if tattach.afid != P9_NOFID {
+                    return Err(ErrorKind::Unsupported);
+                }
+                self.insert(
+                    tattach.fid,
+                    Fid {
+                        node: Node::Root,
+                        open: None,
+                    },
+                )?;
+                Ok(Message::Rattach(Rattach {
+                    qid: Node::Root.qid(),
+                }))
+            }
+            Message::Twalk(twalk) => self.walk(&twalk),
+            Message::Topen(topen) => {
+                let fid = self.fid(topen.fid)?;
+                if fid.open.is_some() {
+                    return Err(ErrorKind::InvalidArgument);
+                }
+                if !fid.node.allows(topen.mode) {
+                    return Err(ErrorKind::PermissionDenied);
+                }
+                self.fids.insert(
+                    topen.fid,
+                    Fid {
+                        open: Some(topen.mode),
+                        ..fid
+                    },
+                );
+                Ok(Message::Ropen(Ropen {
+                    qid: fid.node.qid(),
+                    iounit: 0,
+                }))
+            }
+            Message::Tread(tread) => self.read(&tread),
+            Message::Twrite(twrite) => self.write(&twrite),
+            Message::Tclunk(tclunk) => {
+                self.fids.remove(&tclunk.fid).ok_or(ErrorKind::BadFid)?;
+                Ok(Message::Rclunk(Rclunk))
+            }
+            Message::Tremove(tremove) => {
+                // the fid is clunked even though nothing can be removed
+                self.fids.remove(&tremove.fid).ok_or(ErrorKind::BadFid)?;
+                Err(ErrorKind::PermissionDenied)
+            }
+            Message::Tstat(tstat) => {
+                let fid = self.fid(tstat.fid)?;
+                Ok(Message::Rstat(Rstat {
+                    stat: self.stat(fid.node),
+                }))
+            }
+            Message::Twstat(twstat) => {
+                // hosts truncate files before writing them, which means nothing here;
+                // renaming is refused
+                let fid = self.fid(twstat.fid)?;
+                let name = twstat.stat.name;
+                if !name.is_empty() && name != fid.node.name() {
+                    return Err(ErrorKind::PermissionDenied);
+                }
+                Ok(Message::Rwstat(Rwstat))
+            }
+            Message::Tauth(_) | Message::Tcreate(_) => Err(ErrorKind::PermissionDenied),
+            _ => Err(ErrorKind::Unsupported),
+        }
+    }
+
+    fn walk(&mut self, twalk: &Twalk) -> std::result::Result<Message, ErrorKind> {
+        let from = self.fid(twalk.fid)?;
+        if from.open.is_some() || twalk.wnames.len() > P9_MAXWELEM {
+            return Err(ErrorKind::InvalidArgument);
+        }
+
+        let mut node = from.node;
+        let mut wqids = Vec::new();
+        for name in &twalk.wnames {
+            match node.walk(name, self.relays()) {
+                Some(next) => {
+                    node = next;
+                    wqids.push(node.qid());
+                }
+                None if wqids.is_empty() => return Err(ErrorKind::NotFound),
+                None => break,
+            }
+        }
+
+        if wqids.len() == twalk.wnames.len() {
+            let fid = Fid { node, open: None };
+            if twalk.newfid == twalk.fid {
+                self.fids.insert(twalk.fid, fid);
+            } else {
+                self.insert(twalk.newfid, fid)?;
+            }
+        }
+        Ok(Message::Rwalk(Rwalk { wqids }))
+    }
+
+    fn read(&self, tread: &Tread) -> std::result::Result<Message, ErrorKind> {
+        let fid = self.fid(tread.fid)?;
+        let readable = fid
+            .open
+            .is_some_and(|mode| matches!(access(mode), OpenMode::Read | OpenMode::ReadWrite));
+        if !readable {
+            return Err(ErrorKind::BadFid);
+        }
+        let count = tread.count.min(self.msize.saturating_sub(P9_IOHDRSZ));
+
+        let data = if fid.node.is_dir() {
+            let entries: Vec<Stat> = fid
+                .node
+                .children(self.relays())
+                .into_iter()
+                .map(|child| self.stat(child))
+                .collect();
+            encode_dir(&entries, tread.offset, count).map_err(|_| ErrorKind::InvalidArgument)?
+        } else {
+            let contents = self.contents(fid.node);
+            let start = usize::try_from(tread.offset)
+                .map_or(contents.len(), |offset| offset.min(contents.len()));
+            let end = start.saturating_add(count as usize).min(contents.len());
+            contents.as_bytes()[start..end].to_vec().into()
+        };
+        Ok(Message::Rread(Rread { data }))
+    }
+
+    fn write(&self, twrite: &Twrite) -> std::result::Result<Message, ErrorKind> {
+        let fid = self.fid(twrite.fid)?;
+        let writable = fid
+            .open
+            .is_some_and(|mode| matches!(access(mode), OpenMode::Write | OpenMode::ReadWrite));
+        let Node::File(relay, file) = fid.node else {
+            return Err(ErrorKind::IsADirectory);
+        };
+        if !writable {
+            return Err(ErrorKind::BadFid);
+        }
+
+        let text = std::str::from_utf8(&twrite.data).map_err(|_| ErrorKind::InvalidArgument)?;
+        match file {
+            RelayFile::State => self.command(relay, text.trim())?,
+            RelayFile::Name => self.command(relay, &format!("name {}", text.trim()))?,
+            RelayFile::Ctl => {
+                for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    self.command(relay, line)?;
+                }
+            }
+        }
+        let count = u32::try_from(twrite.data.len()).unwrap_or(u32::MAX);
+        Ok(Message::Rwrite(Rwrite { count }))
+    }
+
+    fn insert(&mut self, fid: u32, entry: Fid) -> std::result::Result<(), ErrorKind> {
+        if self.fids.contains_key(&fid) {
+            return Err(ErrorKind::BadFid);
+        }
+        self.fids.insert(fid, entry);
+        Ok(())
+    }
+
+    fn contents(&self, node: Node) -> String {
+        match node {
+            Node::File(relay, RelayFile::State) => match self.relay_controller.get_state(relay) {
+                Some(true) => "on\n".to_string(),
+                Some(false) => "off\n".to_string(),
+                None => String::new(),
+            },
+            Node::File(relay, RelayFile::Name) => self
+                .relay_controller
+                .get_name(relay)
+                .map(|name| format!("{name}\n"))
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    fn stat(&self, node: Node) -> Stat {
+        let (perm, length) = match node {
+            Node::File(_, RelayFile::Ctl) => (0o220, 0),
+            Node::File(..) => (0o664, self.contents(node).len() as u64),
+            _ => (0o555, 0),
+        };
+        Stat {
+            r#type: 0,
+            dev: 0,
+            qid: node.qid(),
+            mode: FileMode::from_unix_perm(perm, node.is_dir()),
+            atime: 0,
+            mtime: 0,
+            length,
+            name: node.name(),
+            uid: "relay".to_string(),
+            gid: "relay".to_string(),
+            muid: "relay".to_string(),
+        }
+    }
+
+    /// Run a ctl command against `relay`
+    fn command(&self, relay: usize, command: &str) -> std::result::Result<(), ErrorKind> {
+        let relays = &self.relay_controller;
+        let done = match command.split_once(' ') {
+            Some(("name", name)) => {
+                let name = name.trim();
+                if !valid_relay_name(name) {
+                    return Err(ErrorKind::InvalidArgument);
+                }
+                relays.set_name(relay, name)
+            }
+            _ => match command {
+                "on" | "1" => relays.set_state(relay, true).is_some(),
+                "off" | "0" => relays.set_state(relay, false).is_some(),
+                "toggle" => relays.toggle(relay).is_some(),
+                _ => return Err(ErrorKind::InvalidArgument),
+            },
+        };
+        if done {
+            Ok(())
+        } else {
+            Err(ErrorKind::Io)
+        }
+    }
+}
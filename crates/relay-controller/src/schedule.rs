@@ -0,0 +1,79 @@
+//! Switches relays at the times of day given by the settings' schedules.
+use crate::{error::Result, relay::RelayController, settings::SettingsStore};
+use esp_idf_svc::timer::{EspTimerService, Task};
+use log::{info, warn};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// How often the clock is checked
+const TICK: Duration = Duration::from_secs(20);
+
+/// Times before this have not been set by SNTP yet (2024-01-01)
+const CLOCK_SET: u64 = 1_704_067_200;
+
+/// Seconds since the unix epoch, once the clock has been set
+pub fn unix_time(now: SystemTime) -> Option<u64> {
+    let secs = now.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    (secs >= CLOCK_SET).then_some(secs)
+}
+
+/// Minutes since midnight UTC, once the clock has been set
+fn minute_of_day(now: SystemTime) -> Option<u32> {
+    let secs = unix_time(now)?;
+    u32::try_from((secs % 86_400) / 60).ok()
+}
+
+/// Whether `at` lies in the minutes after `last` up to and including `now`
+fn is_due(last: u32, now: u32, at: u32) -> bool {
+    if last <= now {
+        last < at && at <= now
+    } else {
+        // the day rolled over
+        last < at || at <= now
+    }
+}
+
+/// Runs the schedules of `settings` until the timer fails. Schedules that were
+/// missed while the board was off or the clock unset are not caught up on.
+pub async fn run_schedules(
+    settings: Arc<SettingsStore>,
+    relay_controller: Arc<RelayController>,
+    timer: EspTimerService<Task>,
+) -> Result<()> {
+    let mut timer = timer.timer_async()?;
+    let mut last = None;
+
+    loop {
+        timer.after(TICK).await?;
+
+        let Some(now) = minute_of_day(SystemTime::now()) else {
+            continue;
+        };
+        if let Some(last) = last.filter(|last| *last != now) {
+            for schedule in settings.current().schedules {
+                let Some(at) = schedule.minute_of_day() else {
+                    continue;
+                };
+                if !is_due(last, now, at) {
+                    continue;
+                }
+                info!(
+                    "schedule {}: relay {} -> {}",
+                    schedule.at, schedule.relay, schedule.state
+                );
+                if relay_controller
+                    .set_state(schedule.relay, schedule.state)
+                    .is_none()
+                {
+                    warn!(
+                        "schedule {} failed to switch relay {}",
+                        schedule.at, schedule.relay
+                    );
+                }
+            }
+        }
+        last = Some(now);
+    }
+}
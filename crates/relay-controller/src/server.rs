@@ -1,17 +1,33 @@
 use crate::error::Result;
+use crate::health::BootHealth;
+use crate::ota::{PushTarget, PushedImage};
 use crate::relay::RelayController;
 use crate::wifi::WifiState;
 use axum::{
+    body::Body,
     extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     routing::*,
     Json, Router,
 };
-use log::info;
+use firmware::{
+    push::{parse_authorization, Challenge, PendingChallenges, CHALLENGE_LEN},
+    signature::SignedManifest,
+};
+use log::{info, warn};
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::fmt::Write as _;
 use std::net::{IpAddr, Ipv4Addr};
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Time given to the response to a push before the board restarts
+const RESTART_DELAY: Duration = Duration::from_secs(1);
 
 #[derive(Debug, Deserialize)]
 struct RelayAction {
@@ -22,15 +38,25 @@ struct SharedState {
     #[allow(dead_code)]
     pub wifi_state: Arc<WifiState>,
     pub relay_controller: Arc<RelayController>,
+    pub ota: PushTarget,
+    /// Shared secret that pushes are authenticated with; without it pushes are refused
+    pub ota_push_secret: Option<String>,
+    pub challenges: Mutex<PendingChallenges>,
 }
 
 pub async fn run_server(
     wifi_state: Arc<WifiState>,
     relay_controller: Arc<RelayController>,
+    health: Arc<BootHealth>,
+    ota: PushTarget,
+    ota_push_secret: Option<String>,
 ) -> Result<()> {
     let state = Arc::new(SharedState {
         wifi_state,
         relay_controller,
+        ota,
+        ota_push_secret,
+        challenges: Mutex::default(),
     });
 
     let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 80);
@@ -38,11 +64,15 @@ pub async fn run_server(
         .route("/", get(move || async { "Hello!" }))
         .route("/relays", get(get_all_relays))
         .route("/relays/{id}", get(get_relay_state).put(set_relay_state))
+        .route("/ota", get(get_ota_status))
+        .route("/ota/challenge", get(get_ota_challenge))
+        .route("/ota/image", post(push_image))
         .fallback(not_found)
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     info!("API server listening on {addr:?}");
+    health.set_http_bound();
     Ok(axum::serve(listener, app.into_make_service()).await?)
 }
 
@@ -113,6 +143,136 @@ async fn set_relay_state(
     }
 }
 
+async fn get_ota_status(State(state): State<Arc<SharedState>>) -> Json<Value> {
+    let status = &state.ota.status;
+    let progress = status.progress().map(|progress| {
+        json!({
+            "written": progress.written,
+            "total": progress.total,
+            "percent": progress.percent(),
+            "bytes_per_sec": progress.bytes_per_sec(),
+            "elapsed_secs": progress.elapsed.as_secs(),
+            "resumes": progress.resumes
+        })
+    });
+
+    Json(json!({
+        "running_version": status.running_version(),
+        "last_result": status.last_result(),
+        "download": progress,
+        "success": true
+    }))
+}
+
+/// Hands out a fresh challenge for a push, which stays pending until it is answered
+/// or expires
+async fn get_ota_challenge(State(state): State<Arc<SharedState>>) -> impl IntoResponse {
+    let mut challenge: Challenge = [0; CHALLENGE_LEN];
+    // SAFETY: the pointer and length describe `challenge`, which outlives the call
+    unsafe { esp_idf_svc::sys::esp_fill_random(challenge.as_mut_ptr().cast(), challenge.len()) };
+    if let Ok(mut challenges) = state.challenges.lock() {
+        challenges.insert(challenge, Instant::now());
+    }
+    hex::encode(challenge)
+}
+
+fn push_failure(status: StatusCode, error: impl std::fmt::Display) -> (StatusCode, Json<Value>) {
+    (
+        status,
+        Json(json!({
+            "success": false,
+            "error": error.to_string()
+        })),
+    )
+}
+
+/// Checks the `Authorization` header against the challenges handed out, each of which
+/// can only be answered once
+fn authenticate(state: &SharedState, headers: &HeaderMap) -> std::result::Result<(), String> {
+    let Some(secret) = &state.ota_push_secret else {
+        return Err("pushing images requires device.ota_push_secret".into());
+    };
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or("missing authorization")?;
+    let (challenge, response) = parse_authorization(authorization).map_err(|e| e.to_string())?;
+    state
+        .challenges
+        .lock()
+        .map_err(|_| "challenges unavailable")?
+        .answer(secret.as_bytes(), &challenge, &response, Instant::now())
+        .map_err(|e| e.to_string())
+}
+
+/// What the headers of a push say about the image in its body
+fn pushed_image(headers: &HeaderMap) -> std::result::Result<PushedImage, String> {
+    let get = |name: &str| {
+        headers
+            .get(name)
+            .map(|value| value.as_bytes())
+            .ok_or(format!("missing {name}"))
+    };
+    let len = std::str::from_utf8(get(header::CONTENT_LENGTH.as_str())?)
+        .ok()
+        .and_then(|len| len.parse().ok())
+        .ok_or("invalid content-length")?;
+    // the headers carry the lines of the release manifest
+    let mut manifest = String::new();
+    for line in ["version", "channel", "sha256", "signature"] {
+        let value = std::str::from_utf8(get(&format!("x-firmware-{line}"))?)
+            .map_err(|_| format!("invalid x-firmware-{line}"))?;
+        let _ = writeln!(manifest, "{line} {value}");
+    }
+    Ok(PushedImage {
+        signed: SignedManifest::parse(manifest.as_bytes()).map_err(|e| e.to_string())?,
+        len,
+    })
+}
+
+/// Installs the image in the body and restarts into it. The image has to be signed
+/// like one published on the update server, with the lines of its manifest given in
+/// the `x-firmware-version`, `x-firmware-channel`, `x-firmware-sha256` and
+/// `x-firmware-signature` headers.
+async fn push_image(
+    State(state): State<Arc<SharedState>>,
+    headers: HeaderMap,
+    body: Body,
+) -> (StatusCode, Json<Value>) {
+    if let Err(e) = authenticate(&state, &headers) {
+        warn!("refused image push: {e}");
+        return push_failure(StatusCode::UNAUTHORIZED, e);
+    }
+    let image = match pushed_image(&headers) {
+        Ok(image) => image,
+        Err(e) => return push_failure(StatusCode::BAD_REQUEST, e),
+    };
+    let Some(mut ota) = state.ota.ota.try_lock() else {
+        return push_failure(StatusCode::CONFLICT, "an update is already in progress");
+    };
+
+    if let Err(e) = state
+        .ota
+        .install(&mut ota, image, body.into_data_stream())
+        .await
+    {
+        return push_failure(StatusCode::UNPROCESSABLE_ENTITY, e);
+    }
+
+    // restart once the response is out
+    std::thread::spawn(|| {
+        std::thread::sleep(RESTART_DELAY);
+        esp_idf_svc::hal::reset::restart();
+    });
+    (
+        StatusCode::OK,
+        Json(json!({
+            "message": "image installed, restarting",
+            "success": true
+        })),
+    )
+}
+
 async fn not_found() -> impl IntoResponse {
     (axum::http::StatusCode::NOT_FOUND, "not found")
 }
@@ -0,0 +1,223 @@
+//! Settings that can change while the board runs.
+//!
+//! The update handler pulls them from `config/<hostname>` on the update server as
+//! JSON. A new version is validated before anything is changed; a valid one is
+//! applied right away and persisted to NVS so that it survives a restart. Failures
+//! are recorded in [`SettingsStore::last_result`], which the status upload reports.
+//!
+//! ```json
+//! {
+//!   "relay_names": ["pump", "lights"],
+//!   "schedules": [{ "relay": 1, "at": "18:30", "state": true }],
+//!   "ota_interval": 16,
+//!   "telemetry_interval": 60,
+//!   "allow_downgrade": false
+//! }
+//! ```
+use crate::{
+    error::{Error, Result},
+    relay::RelayController,
+};
+use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, RwLock};
+
+const NAMESPACE: &str = "device";
+const KEY: &str = "settings";
+
+/// Longest relay name accepted
+const MAX_NAME_LEN: usize = 32;
+
+/// Range of the poll intervals, in seconds
+const INTERVALS: std::ops::RangeInclusive<u64> = 5..=86_400;
+
+/// Whether `name` can name a relay: 1 to [`MAX_NAME_LEN`] bytes without whitespace
+pub(crate) fn valid_relay_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= MAX_NAME_LEN && !name.contains(char::is_whitespace)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Names of the relays by id; relays without one keep their default name
+    pub relay_names: Vec<String>,
+    pub schedules: Vec<Schedule>,
+    /// Seconds between update checks
+    pub ota_interval: u64,
+    /// Seconds between status uploads
+    pub telemetry_interval: u64,
+    /// Install a published version older than the running one
+    pub allow_downgrade: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            relay_names: Vec::new(),
+            schedules: Vec::new(),
+            ota_interval: 16,
+            telemetry_interval: 60,
+            allow_downgrade: false,
+        }
+    }
+}
+
+/// Switch a relay at the same time every day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    pub relay: usize,
+    /// Time of day in UTC, as `HH:MM`
+    pub at: String,
+    pub state: bool,
+}
+
+impl Schedule {
+    /// Minutes since midnight of [`Schedule::at`]
+    pub fn minute_of_day(&self) -> Option<u32> {
+        let (hours, minutes) = self.at.split_once(':')?;
+        let hours: u32 = hours.parse().ok()?;
+        let minutes: u32 = minutes.parse().ok()?;
+        (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+    }
+}
+
+impl Settings {
+    /// Check the settings against a board with `relays` relays
+    pub fn validate(&self, relays: usize) -> Result<()> {
+        let invalid = |reason: String| Err(Error::InvalidSettings(reason));
+
+        if self.relay_names.len() > relays {
+            return invalid(format!(
+                "{} relay names for {relays} relays",
+                self.relay_names.len()
+            ));
+        }
+        for name in &self.relay_names {
+            if !valid_relay_name(name) {
+                return invalid(format!(
+                    "relay name {name:?} must be 1 to {MAX_NAME_LEN} bytes without whitespace"
+                ));
+            }
+        }
+        for schedule in &self.schedules {
+            if schedule.relay >= relays {
+                return invalid(format!("schedule for unknown relay {}", schedule.relay));
+            }
+            if schedule.minute_of_day().is_none() {
+                return invalid(format!("schedule time {:?} is not HH:MM", schedule.at));
+            }
+        }
+        for (name, interval) in [
+            ("ota_interval", self.ota_interval),
+            ("telemetry_interval", self.telemetry_interval),
+        ] {
+            if !INTERVALS.contains(&interval) {
+                return invalid(format!(
+                    "{name} must be between {} and {} seconds",
+                    INTERVALS.start(),
+                    INTERVALS.end()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The settings in effect, shared by every task that uses them
+pub struct SettingsStore {
+    current: RwLock<Settings>,
+    nvs: Mutex<EspNvs<NvsDefault>>,
+    relay_controller: Arc<RelayController>,
+    last_result: Mutex<Option<String>>,
+}
+
+impl SettingsStore {
+    /// Loads the settings persisted in NVS and applies them. Settings that no longer
+    /// validate, say after relays were removed, are replaced by the defaults.
+    pub fn load(
+        partition: EspDefaultNvsPartition,
+        relay_controller: Arc<RelayController>,
+    ) -> Result<Self> {
+        let nvs = EspNvs::new(partition, NAMESPACE, true)?;
+        let relays = relay_controller.get_all_states().len();
+
+        let settings = match nvs.str_len(KEY)? {
+            Some(len) => {
+                let mut buf = vec![0; len];
+                let stored = nvs.get_str(KEY, &mut buf)?.unwrap_or_default();
+                serde_json::from_str::<Settings>(stored)
+                    .map_err(|e| Error::InvalidSettings(e.to_string()))
+                    .and_then(|settings| settings.validate(relays).map(|()| settings))
+                    .unwrap_or_else(|e| {
+                        warn!("ignoring stored settings: {e}");
+                        Settings::default()
+                    })
+            }
+            None => Settings::default(),
+        };
+
+        let store = Self {
+            current: RwLock::new(Settings::default()),
+            nvs: Mutex::new(nvs),
+            relay_controller,
+            last_result: Mutex::new(None),
+        };
+        store.apply(settings);
+        Ok(store)
+    }
+
+    pub fn current(&self) -> Settings {
+        self.current
+            .read()
+            .map(|settings| settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// Outcome of the most recent pull
+    pub fn last_result(&self) -> Option<String> {
+        self.last_result.lock().ok()?.clone()
+    }
+
+    pub fn set_last_result(&self, result: String) {
+        if let Ok(mut last_result) = self.last_result.lock() {
+            *last_result = Some(result);
+        }
+    }
+
+    /// Validate `data` as settings and, if they differ from the current ones, apply
+    /// and persist them. Returns whether anything changed.
+    pub fn update(&self, data: &[u8]) -> Result<bool> {
+        let settings: Settings =
+            serde_json::from_slice(data).map_err(|e| Error::InvalidSettings(e.to_string()))?;
+        settings.validate(self.relay_controller.get_all_states().len())?;
+        if settings == self.current() {
+            return Ok(false);
+        }
+
+        let json =
+            serde_json::to_string(&settings).map_err(|e| Error::InvalidSettings(e.to_string()))?;
+        self.nvs
+            .lock()
+            .map_err(|_| Error::Other("settings storage is poisoned".into()))?
+            .set_str(KEY, &json)?;
+        self.apply(settings);
+        info!("applied new settings");
+        Ok(true)
+    }
+
+    fn apply(&self, settings: Settings) {
+        for (id, _) in self.relay_controller.get_all_states() {
+            let name = settings
+                .relay_names
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| RelayController::default_name(id));
+            self.relay_controller.set_name(id, &name);
+        }
+        if let Ok(mut current) = self.current.write() {
+            *current = settings;
+        }
+    }
+}
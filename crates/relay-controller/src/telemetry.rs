@@ -0,0 +1,246 @@
+//! Periodic upload of the board's status to the 9P file server.
+//!
+//! Every board writes a few small files into a directory named after it, so that
+//! listing the status directory on the NAS shows the whole fleet:
+//!
+//! ```text
+//! <path>/<hostname>/version  firmware in the running slot
+//! <path>/<hostname>/uptime   seconds since the firmware started
+//! <path>/<hostname>/relays   one line per relay: id, name and on or off
+//! <path>/<hostname>/rssi     signal strength of the access point in dBm
+//! <path>/<hostname>/ota      outcome of the last update check
+//! <path>/<hostname>/config   outcome of the last settings pull
+//! <path>/<hostname>/boots    the last few boots and whether they were rolled back
+//! ```
+//!
+//! The board creates its own directory, but `<path>` has to exist on the server.
+use crate::{
+    error::{Error, Result},
+    ota::{
+        cleanup_fid, parse_path_components, receive_message, send_message, walk_once, walk_to_path,
+        OtaStatus, UpdateServer, ROOT_FID,
+    },
+    relay::RelayController,
+    settings::SettingsStore,
+    wifi::WifiState,
+};
+use esp_idf_svc::timer::{EspTimerService, Task};
+use log::{info, warn};
+use std::{
+    fmt::Write,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use stowage_proto::{
+    consts::P9_IOHDRSZ, transport::Connection, FileMode, Message, OpenMode, TaggedMessage, Tcreate,
+    Topen, Twrite,
+};
+
+/// Fid of the board's directory
+const DIR_FID: u32 = ROOT_FID + 1;
+
+/// Fid of the file being written
+const FILE_FID: u32 = ROOT_FID + 2;
+
+pub struct Telemetry {
+    server: UpdateServer,
+    /// Directory of this board on the server
+    path: String,
+    timer: EspTimerService<Task>,
+    relay_controller: Arc<RelayController>,
+    wifi_state: Arc<WifiState>,
+    ota_status: Arc<OtaStatus>,
+    settings: Arc<SettingsStore>,
+    started: Instant,
+}
+
+impl Telemetry {
+    pub fn new(
+        server: UpdateServer,
+        path: String,
+        timer: EspTimerService<Task>,
+        relay_controller: Arc<RelayController>,
+        wifi_state: Arc<WifiState>,
+        ota_status: Arc<OtaStatus>,
+        settings: Arc<SettingsStore>,
+    ) -> Self {
+        Self {
+            server,
+            path,
+            timer,
+            relay_controller,
+            wifi_state,
+            ota_status,
+            settings,
+            started: Instant::now(),
+        }
+    }
+
+    /// Uploads the status every `telemetry_interval` seconds. Failed uploads are logged and retried on
+    /// the next round, so this only returns if the timer fails.
+    pub async fn run(&mut self) -> Result<()> {
+        let mut timer = self.timer.timer_async()?;
+
+        timer.after(Duration::from_secs(30)).await?;
+
+        loop {
+            match self.upload().await {
+                Ok(()) => info!("uploaded status to {}", self.path),
+                Err(e) => warn!("status upload failed: {e:?}"),
+            }
+            let interval = self.settings.current().telemetry_interval;
+            timer.after(Duration::from_secs(interval)).await?;
+        }
+    }
+
+    /// Name and contents of every status file
+    async fn status(&self) -> Vec<(&'static str, String)> {
+        let mut relays = String::new();
+        for (id, active) in self.relay_controller.get_all_states() {
+            let name = self.relay_controller.get_name(id).unwrap_or_default();
+            let state = if active { "on" } else { "off" };
+            let _ = writeln!(relays, "{id} {name} {state}");
+        }
+        let rssi = self
+            .wifi_state
+            .rssi()
+            .await
+            .map_or_else(|| "unknown".to_string(), |rssi| rssi.to_string());
+
+        vec![
+            (
+                "version",
+                self.ota_status
+                    .running_version()
+                    .unwrap_or_else(|| "unknown".to_string()),
+            ),
+            ("uptime", self.started.elapsed().as_secs().to_string()),
+            ("relays", relays),
+            ("rssi", rssi),
+            (
+                "ota",
+                self.ota_status
+                    .last_result()
+                    .unwrap_or_else(|| "no check yet".to_string()),
+            ),
+            (
+                "config",
+                self.settings
+                    .last_result()
+                    .unwrap_or_else(|| "no pull yet".to_string()),
+            ),
+            (
+                "boots",
+                self.ota_status
+                    .boot_history()
+                    .unwrap_or_else(|| "not checked yet".to_string()),
+            ),
+        ]
+    }
+
+    async fn upload(&self) -> Result<()> {
+        let tag: u16 = 1;
+        let (mut conn, msize) = self.server.connect(tag).await?;
+
+        open_dir(&mut conn, tag, &self.path).await?;
+        for (name, mut contents) in self.status().await {
+            if !contents.ends_with('\n') {
+                contents.push('\n');
+            }
+            write_file(&mut conn, tag, name, contents.as_bytes(), msize).await?;
+        }
+        cleanup_fid(&mut conn, tag, DIR_FID).await?;
+
+        Ok(())
+    }
+}
+
+/// Walk [`DIR_FID`] to `path`, creating the last directory of it if it's missing
+async fn open_dir(conn: &mut Connection, tag: u16, path: &str) -> Result<()> {
+    if walk_to_path(conn, tag, ROOT_FID, DIR_FID, path).await? {
+        return Ok(());
+    }
+
+    let mut components = parse_path_components(path);
+    let Some(name) = components.pop() else {
+        return Err(Error::Other("status path is empty".into()));
+    };
+    if !walk_to_path(conn, tag, ROOT_FID, DIR_FID, &components.join("/")).await? {
+        return Err(Error::Other(format!("{} not found", components.join("/"))));
+    }
+
+    info!("creating {path}");
+    let create = Tcreate {
+        fid: DIR_FID,
+        name,
+        perm: FileMode::from_unix_perm(0o755, true),
+        mode: OpenMode::Read.into(),
+    };
+    send_message(conn, TaggedMessage::new(tag, Message::Tcreate(create))).await?;
+    match receive_message(conn).await?.message {
+        Message::Rcreate(_) => {}
+        Message::Rerror(err) => return Err(Error::remote("failed to create directory", err)),
+        _ => return Err(Error::Other("unexpected response to Tcreate".into())),
+    }
+
+    // the created directory is open and an open fid can't be walked from
+    cleanup_fid(conn, tag, DIR_FID).await?;
+    if walk_to_path(conn, tag, ROOT_FID, DIR_FID, path).await? {
+        Ok(())
+    } else {
+        Err(Error::Other(format!("{path} not found after creating it")))
+    }
+}
+
+/// Replace the contents of the file `name` in [`DIR_FID`] with `data`
+async fn write_file(
+    conn: &mut Connection,
+    tag: u16,
+    name: &str,
+    data: &[u8],
+    msize: u32,
+) -> Result<()> {
+    let message = if walk_once(conn, tag, DIR_FID, FILE_FID, &[name.to_string()]).await? {
+        Message::Topen(Topen {
+            fid: FILE_FID,
+            mode: OpenMode::Write | OpenMode::Trunc,
+        })
+    } else {
+        // walking no names clones the directory fid, which Tcreate then turns into
+        // the new file
+        if !walk_once(conn, tag, DIR_FID, FILE_FID, &[]).await? {
+            return Err(Error::Other("failed to clone the directory fid".into()));
+        }
+        Message::Tcreate(Tcreate {
+            fid: FILE_FID,
+            name: name.to_string(),
+            perm: FileMode::from_unix_perm(0o644, false),
+            mode: OpenMode::Write.into(),
+        })
+    };
+    send_message(conn, TaggedMessage::new(tag, message)).await?;
+    match receive_message(conn).await?.message {
+        Message::Ropen(_) | Message::Rcreate(_) => {}
+        Message::Rerror(err) => return Err(Error::remote("failed to open status file", err)),
+        _ => return Err(Error::Other("unexpected response to open".into())),
+    }
+
+    let mut offset: u64 = 0;
+    for chunk in data.chunks(msize.saturating_sub(P9_IOHDRSZ).max(1) as usize) {
+        let write = Twrite {
+            fid: FILE_FID,
+            offset,
+            data: chunk.to_vec().into(),
+        };
+        send_message(conn, TaggedMessage::new(tag, Message::Twrite(write))).await?;
+        match receive_message(conn).await?.message {
+            Message::Rwrite(rwrite) if rwrite.count as usize == chunk.len() => {}
+            Message::Rwrite(_) => return Err(Error::Other("short write".into())),
+            Message::Rerror(err) => return Err(Error::remote("failed to write status", err)),
+            _ => return Err(Error::Other("unexpected response to Twrite".into())),
+        }
+        offset += chunk.len() as u64;
+    }
+
+    cleanup_fid(conn, tag, FILE_FID).await
+}
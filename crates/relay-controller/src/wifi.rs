@@ -9,6 +9,7 @@ use esp_idf_svc::{
     ipv4::{self, DHCPClientSettings},
     netif::{self, EspNetif},
     nvs::EspDefaultNvsPartition,
+    sys::ESP_ERR_TIMEOUT,
     timer::{EspTimerService, Task},
     wifi::{AsyncWifi, EspWifi, WifiDriver},
 };
@@ -17,11 +18,15 @@ use std::{net::Ipv4Addr, str::FromStr, sync::Arc, time::Duration};
 
 use tokio::{sync::RwLock, time::sleep};
 
+/// How often the signal strength is sampled while connected
+const RSSI_INTERVAL: Duration = Duration::from_secs(30);
+
 #[allow(dead_code)]
 pub struct WifiState {
     pub mac_address: String,
     pub ssid: String,
     ip_addr: RwLock<Option<Ipv4Addr>>,
+    rssi: RwLock<Option<i32>>,
 }
 
 #[allow(dead_code)]
@@ -29,6 +34,11 @@ impl WifiState {
     pub async fn ip_addr(&self) -> Option<Ipv4Addr> {
         *self.ip_addr.read().await
     }
+
+    /// Signal strength of the access point in dBm, while connected
+    pub async fn rssi(&self) -> Option<i32> {
+        *self.rssi.read().await
+    }
 }
 
 pub struct WifiConnection<'a> {
@@ -73,6 +83,7 @@ impl<'a> WifiConnection<'a> {
         );
         let state = Arc::new(WifiState {
             ip_addr: RwLock::new(None),
+            rssi: RwLock::new(None),
             mac_address,
             ssid: config.wifi_ssid.to_string(),
         });
@@ -124,8 +135,19 @@ impl<'a> WifiConnection<'a> {
             *self.state.ip_addr.write().await = ip_info.ok().map(|i| i.ip);
             info!("Connected to '{}': {ip_info:#?}", self.state.ssid);
 
-            // Wait for Wi-Fi to be down
-            self.wifi.wifi_wait(|w| w.is_up(), None).await?;
+            // Wait for Wi-Fi to be down, sampling the signal strength meanwhile
+            loop {
+                *self.state.rssi.write().await = self.wifi.wifi().driver().get_rssi().ok();
+                match self
+                    .wifi
+                    .wifi_wait(|w| w.is_up(), Some(RSSI_INTERVAL))
+                    .await
+                {
+                    Err(err) if err.code() == ESP_ERR_TIMEOUT => {}
+                    result => break result?,
+                }
+            }
+            *self.state.rssi.write().await = None;
             warn!("Wi-Fi disconnected.");
         }
     }
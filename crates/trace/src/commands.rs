@@ -0,0 +1,30 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+/// Transparent 9P proxy that prints every message passing through it
+#[derive(clap::Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub(crate) struct Args {
+    /// Address to accept client connections on
+    #[clap(short, long, default_value = "127.0.0.1:5640")]
+    pub listen: SocketAddr,
+
+    /// Address of the 9P server to forward connections to
+    #[clap(short, long)]
+    pub upstream: String,
+
+    /// Directory to save a replayable recording of each connection to
+    #[clap(short, long)]
+    pub record: Option<PathBuf>,
+
+    /// PEM certificate chain to serve clients over TLS with
+    #[clap(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for `--tls-cert`
+    #[clap(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Connect to the upstream over TLS, expecting the certificate with this SHA-256 fingerprint
+    #[clap(long)]
+    pub upstream_fingerprint: Option<String>,
+}
@@ -0,0 +1,93 @@
+use crate::error::Result;
+use clap::Parser;
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use stowage_proto::{
+    tls::{self, Acceptor, Fingerprint},
+    transport::Transport,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info};
+
+mod commands;
+mod error;
+mod proxy;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let args = commands::Args::parse();
+    let acceptor = match (&args.tls_cert, &args.tls_key) {
+        (Some(cert), Some(key)) => Some(Acceptor::new(tls::server_config_from_pem(cert, key)?)),
+        _ => None,
+    };
+    let fingerprint = args
+        .upstream_fingerprint
+        .as_deref()
+        .map(tls::parse_fingerprint)
+        .transpose()?;
+
+    let listener = TcpListener::bind(args.listen).await?;
+    info!("proxying {} to {}", args.listen, args.upstream);
+
+    let connections = AtomicUsize::new(0);
+    loop {
+        let (client, peer) = listener.accept().await?;
+        let id = connections.fetch_add(1, Ordering::Relaxed);
+        let upstream = args.upstream.clone();
+        let acceptor = acceptor.clone();
+        let record = args
+            .record
+            .as_ref()
+            .map(|dir| dir.join(format!("session-{id}.9prec")));
+
+        tokio::spawn(async move {
+            info!("connection {id} from {peer}");
+            let result = proxy_connection(
+                id,
+                client,
+                acceptor.as_ref(),
+                &upstream,
+                fingerprint,
+                record,
+            )
+            .await;
+
+            match result {
+                Ok(()) => info!("connection {id} closed"),
+                Err(e) => error!("connection {id} failed: {e}"),
+            }
+        });
+    }
+}
+
+async fn proxy_connection(
+    id: usize,
+    client: TcpStream,
+    acceptor: Option<&Acceptor>,
+    upstream: &str,
+    fingerprint: Option<Fingerprint>,
+    record: Option<PathBuf>,
+) -> Result<()> {
+    let client: Box<dyn Transport> = match acceptor {
+        Some(acceptor) => Box::new(acceptor.accept(client).await?),
+        None => Box::new(client),
+    };
+
+    let server = TcpStream::connect(upstream).await?;
+    let server: Box<dyn Transport> = match fingerprint {
+        Some(fingerprint) => Box::new(tls::handshake(server, upstream, fingerprint).await?),
+        None => Box::new(server),
+    };
+
+    let mut session = proxy::Session::new(id);
+    if let Some(path) = record {
+        session = session.record_to(path);
+    }
+    session.run(client, server).await
+}
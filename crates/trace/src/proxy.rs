@@ -0,0 +1,178 @@
+//! Forwarding and decoding of a single proxied connection.
+//!
+//! Bytes are relayed untouched in both directions; a copy of each direction is fed
+//! through a `MessageCodec` purely to print it. A frame that fails to decode stops the
+//! tracing of that direction but never the forwarding, so the proxy can't change what
+//! the client and server see.
+use crate::error::Result;
+use bytes::BytesMut;
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use stowage_proto::{
+    transport::Transport, Dialect, Direction, Message, MessageCodec, Recorder, TaggedMessage,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::Decoder;
+use tracing::{info, warn};
+
+/// Decoding state for one direction of the connection
+struct Stream {
+    direction: Direction,
+    codec: MessageCodec,
+    buf: BytesMut,
+    broken: bool,
+}
+
+impl Stream {
+    fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            codec: MessageCodec::new(),
+            buf: BytesMut::new(),
+            broken: false,
+        }
+    }
+
+    /// Append forwarded bytes and return every message they complete
+    fn feed(&mut self, id: usize, data: &[u8]) -> Vec<TaggedMessage> {
+        let mut messages = Vec::new();
+        if self.broken {
+            return messages;
+        }
+
+        self.buf.extend_from_slice(data);
+        loop {
+            match self.codec.decode(&mut self.buf) {
+                Ok(Some(message)) => messages.push(message),
+                Ok(None) => break,
+                Err(e) => {
+                    warn!(
+                        "connection {id} {}: undecodable frame, no longer tracing this direction: {e}",
+                        self.direction
+                    );
+                    self.broken = true;
+                    self.buf.clear();
+                    break;
+                }
+            }
+        }
+
+        messages
+    }
+}
+
+pub(crate) struct Session {
+    id: usize,
+    start: Instant,
+    client: Stream,
+    server: Stream,
+    recorder: Option<(Recorder, PathBuf)>,
+}
+
+impl Session {
+    pub(crate) fn new(id: usize) -> Self {
+        Self {
+            id,
+            start: Instant::now(),
+            client: Stream::new(Direction::ClientToServer),
+            server: Stream::new(Direction::ServerToClient),
+            recorder: None,
+        }
+    }
+
+    /// Also record the session, to be written to `path` once the connection closes
+    pub(crate) fn record_to(mut self, path: PathBuf) -> Self {
+        self.recorder = Some((Recorder::new(), path));
+        self
+    }
+
+    /// Relay traffic between `client` and `server` until either side closes
+    pub(crate) async fn run(
+        mut self,
+        client: Box<dyn Transport>,
+        server: Box<dyn Transport>,
+    ) -> Result<()> {
+        let result = self.relay(client, server).await;
+
+        if let Some((recorder, path)) = self.recorder.take() {
+            let mut file = BufWriter::new(File::create(&path)?);
+            recorder.finish().write_to(&mut file)?;
+            info!("connection {} recorded to {}", self.id, path.display());
+        }
+
+        result
+    }
+
+    async fn relay(
+        &mut self,
+        mut client: Box<dyn Transport>,
+        mut server: Box<dyn Transport>,
+    ) -> Result<()> {
+        let mut from_client = vec![0u8; 8192];
+        let mut from_server = vec![0u8; 8192];
+
+        loop {
+            tokio::select! {
+                n = client.read(&mut from_client) => {
+                    let n = n?;
+                    if n == 0 {
+                        break;
+                    }
+                    server.write_all(&from_client[..n]).await?;
+                    self.trace(Direction::ClientToServer, &from_client[..n]);
+                }
+                n = server.read(&mut from_server) => {
+                    let n = n?;
+                    if n == 0 {
+                        break;
+                    }
+                    client.write_all(&from_server[..n]).await?;
+                    self.trace(Direction::ServerToClient, &from_server[..n]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn trace(&mut self, direction: Direction, data: &[u8]) {
+        if let Some((recorder, _)) = &mut self.recorder {
+            recorder.record(direction, data);
+        }
+
+        let stream = match direction {
+            Direction::ClientToServer => &mut self.client,
+            Direction::ServerToClient => &mut self.server,
+        };
+
+        for message in stream.feed(self.id, data) {
+            println!(
+                "{} +{:.6} {} {direction} {message}",
+                unix_timestamp(),
+                self.start.elapsed().as_secs_f64(),
+                self.id,
+            );
+
+            // both directions switch to what the server agreed to once Rversion passes by
+            if let Message::Rversion(rversion) = &message.message {
+                let dialect = Dialect::from_version(&rversion.version).unwrap_or_default();
+                for stream in [&mut self.client, &mut self.server] {
+                    stream.codec.set_dialect(dialect);
+                    stream.codec.set_msize(rversion.msize);
+                }
+            }
+        }
+    }
+}
+
+/// Wall clock time as fractional seconds since the unix epoch
+fn unix_timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{}.{:06}", now.as_secs(), now.subsec_micros())
+}